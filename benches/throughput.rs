@@ -1,10 +1,13 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
-use nym_mixnode_rs::{SphinxMixer, SphinxPacket, SphinxHeader, MixNodeRegistry, MixNodeInfo, Region};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use nym_mixnode_rs::{SphinxMixer, SphinxPacket, SphinxHeader, SphinxPacketBuilder, MixNodeRegistry, MixNodeInfo, Region};
+use nym_mixnode_rs::{SPHINX_MAC_SIZE, SPHINX_ROUTING_INFO_SIZE, SPHINX_PACKET_SIZE};
+use nym_mixnode_rs::net::{BatchedSocket, RecvBuffers};
 use curve25519_dalek::{scalar::Scalar, ristretto::RistrettoPoint};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 use rand_core::{OsRng, RngCore};
 
-const SPHINX_HEADER_SIZE: usize = 512;
 const SPHINX_PAYLOAD_SIZE: usize = 512;
 
 fn bench_sphinx_processing(c: &mut Criterion) {
@@ -13,29 +16,32 @@ fn bench_sphinx_processing(c: &mut Criterion) {
     rng.fill_bytes(&mut key_bytes);
     let private_key = Scalar::from_bytes_mod_order(key_bytes);
     let mut mixer = SphinxMixer::new(private_key);
-    
-    // Create test packet
-    let test_packet = create_test_packet();
+    let mixer_key = mixer.public_key();
     
     let mut group = c.benchmark_group("sphinx_processing");
     
     // CRITICAL: Benchmark single packet processing time
+    // Fresh packets per iteration, replays are rejected by the mixer
     group.bench_function("single_packet", |b| {
-        b.iter(|| {
-            black_box(mixer.process_packet(&test_packet).unwrap());
-        });
+        b.iter_batched(
+            || create_test_packet(mixer_key),
+            |packet| black_box(mixer.process_packet(&packet).unwrap()),
+            BatchSize::SmallInput,
+        );
     });
     
     // CRITICAL: Benchmark batch processing for 25k pkt/s validation
     group.throughput(Throughput::Elements(1000));
     group.bench_function("batch_1000", |b| {
-        let packets: Vec<_> = (0..1000).map(|_| create_test_packet()).collect();
-        
-        b.iter(|| {
-            for packet in &packets {
-                black_box(mixer.process_packet(packet).unwrap());
-            }
-        });
+        b.iter_batched(
+            || (0..1000).map(|_| create_test_packet(mixer_key)).collect::<Vec<_>>(),
+            |packets| {
+                for packet in &packets {
+                    black_box(mixer.process_packet(packet).unwrap());
+                }
+            },
+            BatchSize::LargeInput,
+        );
     });
     
     group.finish();
@@ -70,6 +76,7 @@ fn bench_full_throughput(c: &mut Criterion) {
     rng.fill_bytes(&mut key_bytes);
     let private_key = Scalar::from_bytes_mod_order(key_bytes);
     let mut mixer = SphinxMixer::new(private_key);
+    let mixer_key = mixer.public_key();
     
     let mut group = c.benchmark_group("full_throughput");
     group.measurement_time(Duration::from_secs(10));
@@ -78,13 +85,15 @@ fn bench_full_throughput(c: &mut Criterion) {
     // Test processing rate to validate 25k pkt/s target
     group.throughput(Throughput::Elements(25_000));
     group.bench_function("25k_packets", |b| {
-        let packets: Vec<_> = (0..25_000).map(|_| create_test_packet()).collect();
-        
-        b.iter(|| {
-            for packet in &packets {
-                black_box(mixer.process_packet(packet).unwrap());
-            }
-        });
+        b.iter_batched(
+            || (0..25_000).map(|_| create_test_packet(mixer_key)).collect::<Vec<_>>(),
+            |packets| {
+                for packet in &packets {
+                    black_box(mixer.process_packet(packet).unwrap());
+                }
+            },
+            BatchSize::LargeInput,
+        );
     });
     
     group.finish();
@@ -96,15 +105,17 @@ fn bench_constant_time_validation(c: &mut Criterion) {
     rng.fill_bytes(&mut key_bytes);
     let private_key = Scalar::from_bytes_mod_order(key_bytes);
     let mut mixer = SphinxMixer::new(private_key);
+    let mixer_key = mixer.public_key();
     
     let mut group = c.benchmark_group("constant_time");
     
     // Test that processing time is constant regardless of packet content
     group.bench_function("valid_packet", |b| {
-        let packet = create_test_packet();
-        b.iter(|| {
-            black_box(mixer.process_packet(&packet).unwrap());
-        });
+        b.iter_batched(
+            || create_test_packet(mixer_key),
+            |packet| black_box(mixer.process_packet(&packet).unwrap()),
+            BatchSize::SmallInput,
+        );
     });
     
     group.bench_function("invalid_packet", |b| {
@@ -117,23 +128,62 @@ fn bench_constant_time_validation(c: &mut Criterion) {
     group.finish();
 }
 
-fn create_test_packet() -> SphinxPacket {
-    // Create realistic test packet
-    SphinxPacket {
-        header: SphinxHeader {
-            ephemeral_key: curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT,
-            routing_info: [0u8; SPHINX_HEADER_SIZE - 32],
-        },
-        payload: [0u8; SPHINX_PAYLOAD_SIZE],
+fn bench_udp_io(c: &mut Criterion) {
+    // Small enough that a burst fits in the default loopback receive buffer
+    const DATAGRAMS: usize = 32;
+    
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let mut group = c.benchmark_group("udp_io");
+    group.throughput(Throughput::Elements(DATAGRAMS as u64));
+    
+    // recvmmsg/sendmmsg against one recv_from/send_to per datagram, over loopback
+    for (name, enable_mmsg) in [("mmsg", true), ("per_datagram", false)] {
+        let (sender, receiver) = runtime.block_on(async {
+            let bind = || async { Arc::new(tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap()) };
+            (BatchedSocket::new(bind().await, enable_mmsg), BatchedSocket::new(bind().await, enable_mmsg))
+        });
+        let destination = receiver.socket().local_addr().unwrap();
+        let payload = [0x5au8; SPHINX_PACKET_SIZE];
+        let datagrams: Vec<(&[u8], SocketAddr)> = vec![(&payload[..], destination); DATAGRAMS];
+        let mut buffers = RecvBuffers::new();
+        
+        group.bench_function(format!("round_trip_{}", name), |b| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let mut sent = 0;
+                    while sent < DATAGRAMS {
+                        sent += sender.send_batch(&datagrams[sent..]).await.unwrap();
+                    }
+                    let mut received = 0;
+                    while received < DATAGRAMS {
+                        received += receiver.recv_batch(&mut buffers).await.unwrap();
+                    }
+                    black_box(received)
+                })
+            });
+        });
     }
+    
+    group.finish();
+}
+
+fn create_test_packet(mixer_key: RistrettoPoint) -> SphinxPacket {
+    // Create realistic single-hop test packet addressed to the benchmarked mixer
+    SphinxPacketBuilder::new()
+        .route(vec![([0x01u8; 32], mixer_key)])
+        .payload(b"benchmark payload")
+        .build()
+        .unwrap()
 }
 
 fn create_invalid_packet() -> SphinxPacket {
     // Create packet with invalid data for constant-time testing
     SphinxPacket {
         header: SphinxHeader {
-            ephemeral_key: curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT,
-            routing_info: [0xFFu8; SPHINX_HEADER_SIZE - 32],
+            version: 1,
+            ephemeral_key: curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT.compress().to_bytes(),
+            mac: [0xFFu8; SPHINX_MAC_SIZE],
+            routing_info: [0xFFu8; SPHINX_ROUTING_INFO_SIZE],
         },
         payload: [0xFFu8; SPHINX_PAYLOAD_SIZE],
     }
@@ -146,6 +196,7 @@ fn create_test_node(id: u32) -> MixNodeInfo {
     MixNodeInfo {
         id: node_id,
         public_key: &Scalar::from_bytes_mod_order([1u8; 32]) * curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE,
+        address: std::net::SocketAddr::from(([127, 0, 0, 1], 10_000 + (id % 50_000) as u16)),
         stake_weight: 1000 + (id % 5000) as u64,
         reliability_score: 0.95,
         geographic_region: match id % 6 {
@@ -157,8 +208,9 @@ fn create_test_node(id: u32) -> MixNodeInfo {
             _ => Region::Africa,
         },
         last_seen: std::time::SystemTime::now(),
+        layer: None,
     }
 }
 
-criterion_group!(benches, bench_sphinx_processing, bench_vrf_selection, bench_full_throughput, bench_constant_time_validation);
+criterion_group!(benches, bench_sphinx_processing, bench_vrf_selection, bench_full_throughput, bench_constant_time_validation, bench_udp_io);
 criterion_main!(benches);
\ No newline at end of file
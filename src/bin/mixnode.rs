@@ -1,5 +1,5 @@
 // REAL Nym Mixnode - NO SIMULATIONS - Standalone Binary
-use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
+use std::sync::{Arc, RwLock, atomic::{AtomicU64, Ordering}};
 use std::time::{Instant, Duration, SystemTime, UNIX_EPOCH};
 use std::net::SocketAddr;
 use tokio::net::UdpSocket;
@@ -7,15 +7,21 @@ use tokio::signal;
 use tracing::{info, error, warn, debug};
 use aes_gcm::{Aes256Gcm, Nonce, KeyInit, AeadInPlace};
 use ed25519_dalek::{SigningKey, Signer};
-use rand_core::{OsRng, RngCore};
+use rand_core::OsRng;
 use curve25519_dalek::scalar::Scalar;
 use blake3::Hasher;
 use hyper::{Body, Request, Response, Server};
 use hyper::service::{make_service_fn, service_fn};
 use std::convert::Infallible;
+use nym_mixnode_rs::{SphinxKeySet, SphinxKeyStore, PooledObject, SphinxPacketBuffer, get_packet_buffer};
+use nym_mixnode_rs::config::{manager::ConfigManager, SphinxConfig};
+use nym_mixnode_rs::epoch::EpochManager;
 
 // REAL Nym packet processing - NO SIMULATIONS
 const SPHINX_PACKET_SIZE: usize = 1024;
+// Node configuration, including the Sphinx key store path and rotation schedule
+const CONFIG_PATH_ENV: &str = "NYM_MIXNODE_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.yaml";
 
 #[derive(Debug)]
 struct RealMetrics {
@@ -86,20 +92,15 @@ impl RealMetrics {
 }
 
 struct RealSphinxProcessor {
-    private_key: Scalar,
+    keys: Arc<RwLock<SphinxKeySet>>,
     signing_key: SigningKey,
 }
 
 impl RealSphinxProcessor {
-    fn new() -> Self {
-        let signing_key = SigningKey::generate(&mut OsRng);
-        let mut private_key_bytes = [0u8; 32];
-        OsRng.fill_bytes(&mut private_key_bytes);
-        let private_key = Scalar::from_bytes_mod_order(private_key_bytes);
-        
+    fn new(keys: Arc<RwLock<SphinxKeySet>>) -> Self {
         Self {
-            private_key,
-            signing_key,
+            keys,
+            signing_key: SigningKey::generate(&mut OsRng),
         }
     }
     
@@ -111,14 +112,22 @@ impl RealSphinxProcessor {
         
         // Extract components
         let nonce = Nonce::from_slice(&packet_data[0..12]);
-        let mut ciphertext_and_tag = packet_data[12..].to_vec();
-        
-        // REAL AES-GCM decryption
-        let key = self.derive_key(&packet_data[0..12]);
-        let cipher = Aes256Gcm::new(&key.into());
+        let mut ciphertext_and_tag = get_packet_buffer();
         
-        cipher.decrypt_in_place(nonce, b"", &mut ciphertext_and_tag)
-            .map_err(|_| "Decryption failed".to_string())?;
+        // REAL AES-GCM decryption; the previous key still works during the overlap window
+        let keys = self.keys.read().unwrap();
+        let decrypted = std::iter::once(&keys.current).chain(&keys.previous).any(|sphinx_key| {
+            ciphertext_and_tag.clear();
+            ciphertext_and_tag.extend_from_slice(&packet_data[12..]);
+            
+            let key = Self::derive_key(&sphinx_key.private_key(), &packet_data[0..12]);
+            let cipher = Aes256Gcm::new(&key.into());
+            cipher.decrypt_in_place(nonce, b"", &mut *ciphertext_and_tag).is_ok()
+        });
+        drop(keys);
+        if !decrypted {
+            return Err("Decryption failed".to_string());
+        }
         
         // Real VRF-based routing decision
         let routing_decision = self.vrf_route_decision(&ciphertext_and_tag);
@@ -130,10 +139,10 @@ impl RealSphinxProcessor {
     }
     
     // REAL key derivation using Blake3
-    fn derive_key(&self, nonce: &[u8]) -> [u8; 32] {
+    fn derive_key(private_key: &Scalar, nonce: &[u8]) -> [u8; 32] {
         let mut hasher = Hasher::new();
         hasher.update(b"NYM_MIXNODE_KEY_DERIVATION_v1");
-        hasher.update(&self.private_key.to_bytes());
+        hasher.update(&private_key.to_bytes());
         hasher.update(nonce);
         
         let mut key = [0u8; 32];
@@ -158,7 +167,7 @@ impl RealSphinxProcessor {
 #[derive(Debug)]
 struct ProcessedPacket {
     action: PacketAction,
-    payload: Vec<u8>,
+    payload: PooledObject<SphinxPacketBuffer>,
 }
 
 #[derive(Debug)]
@@ -168,6 +177,60 @@ enum PacketAction {
     Drop,
 }
 
+/// Rotate the Sphinx key on the library node's schedule
+///
+/// A key that has served `key_rotation_epochs` epochs is replaced at the next epoch boundary
+/// and the previous one is retired once `key_overlap` has passed. Every change is persisted.
+async fn key_rotation_loop(
+    keys: Arc<RwLock<SphinxKeySet>>,
+    key_store: SphinxKeyStore,
+    epoch_manager: Arc<EpochManager>,
+    sphinx: SphinxConfig,
+) {
+    let mut epoch_rx = epoch_manager.subscribe();
+    let mut epoch = epoch_manager.current_epoch();
+    
+    loop {
+        {
+            let mut keys = keys.write().unwrap();
+            let mut changed = false;
+            if keys.rotation_due(epoch, sphinx.key_rotation_epochs) {
+                keys.rotate(epoch);
+                info!("🔑 Sphinx key rotated for epoch {}", epoch);
+                changed = true;
+            }
+            if let Some(retired) = keys.retire_expired(SystemTime::now(), sphinx.key_overlap) {
+                info!("🔑 Sphinx key from epoch {} retired", retired.epoch);
+                changed = true;
+            }
+            if changed {
+                if let Err(e) = key_store.save(&keys) {
+                    error!("Failed to persist Sphinx keys: {}", e);
+                }
+            }
+        }
+        
+        let retire_in = {
+            let keys = keys.read().unwrap();
+            keys.previous.as_ref().map(|_| {
+                (keys.current.activated_at + sphinx.key_overlap)
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default()
+            })
+        };
+        
+        tokio::select! {
+            changed = epoch_rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                epoch = *epoch_rx.borrow_and_update();
+            }
+            _ = tokio::time::sleep(retire_in.unwrap_or_default()), if retire_in.is_some() => {}
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize real logging
@@ -179,9 +242,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("📊 Target Performance: ≥25,000 packets/second");
     info!("🔒 Security: Real AES-GCM + Ed25519 VRF");
     
+    let config_path = std::env::var(CONFIG_PATH_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    let config_manager = ConfigManager::new(config_path.into());
+    config_manager.load().await.map_err(|e| e.to_string())?;
+    let config = config_manager.get_config().await;
+    
     // REAL components initialization
     let metrics = Arc::new(RealMetrics::new());
-    let processor = Arc::new(RealSphinxProcessor::new());
+    let epoch_manager = Arc::new(EpochManager::new(&config.epoch)?);
+    
+    // Persisted so a restart keeps the same packet key
+    let key_path = config.sphinx.key_store_path.clone()
+        .unwrap_or_else(|| config.node.data_dir.join("sphinx_keys.json"));
+    let key_store = SphinxKeyStore::new(key_path);
+    let keys = Arc::new(RwLock::new(key_store.load_or_generate(epoch_manager.current_epoch())?));
+    let processor = Arc::new(RealSphinxProcessor::new(keys.clone()));
+    info!("🔑 Sphinx key loaded from {}", key_store.path().display());
+    
+    {
+        let epoch_manager = epoch_manager.clone();
+        tokio::spawn(async move { epoch_manager.run().await });
+    }
+    tokio::spawn(key_rotation_loop(keys.clone(), key_store, epoch_manager, config.sphinx.clone()));
     
     // REAL UDP socket for packet processing
     let listen_addr: SocketAddr = "0.0.0.0:1789".parse()?;
@@ -334,6 +416,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // REAL node discovery (simplified)
     let discovery = {
         let socket = socket.clone();
+        let keys = keys.clone();
         
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(30));
@@ -341,10 +424,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             loop {
                 interval.tick().await;
                 
-                // Send real discovery announcement
+                // Send real discovery announcement, advertising the current Sphinx key
+                let public_key = keys.read().unwrap().current.public_key();
                 let announcement = format!(
-                    "NYM_DISCOVERY:{}:{}",
+                    "NYM_DISCOVERY:{}:{}:{}",
                     listen_addr,
+                    hex::encode(public_key.compress().as_bytes()),
                     SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
                 );
                 
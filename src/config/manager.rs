@@ -6,7 +6,7 @@ use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use tracing::{info, warn, error, debug};
 
-use super::{AppConfig, NodeConfig, P2PConfig, SphinxConfig, MetricsConfig, LoggingConfig, StorageConfig, SecurityConfig};
+use super::{AppConfig, NodeConfig, P2PConfig, SphinxConfig, EpochConfig, MetricsConfig, LoggingConfig, StorageConfig, SecurityConfig};
 
 /// Configuration manager for handling app configuration loading and saving
 pub struct ConfigManager {
@@ -269,6 +269,27 @@ impl ConfigManager {
             errors.push("Sphinx max hops must be greater than 0".to_string());
         }
         
+        if let Err(e) = crate::mixing::DelayDistribution::from_config_str(&config.sphinx.delay_distribution) {
+            errors.push(e);
+        }
+        
+        if config.sphinx.mean_delay > config.sphinx.max_delay {
+            errors.push("Sphinx mean delay must not exceed max delay".to_string());
+        }
+        
+        if config.sphinx.key_rotation_epochs == 0 {
+            errors.push("Sphinx key rotation interval must be at least one epoch".to_string());
+        }
+        
+        if config.sphinx.release_quantum.is_some_and(|quantum| quantum.is_zero()) {
+            errors.push("Sphinx release quantum must be greater than 0".to_string());
+        }
+        
+        // Validate epoch configuration
+        if config.epoch.epoch_length.is_zero() {
+            errors.push("Epoch length must be greater than 0".to_string());
+        }
+        
         // Validate metrics configuration
         if config.metrics.enabled && config.metrics.collection_interval.as_secs() == 0 {
             errors.push("Metrics collection interval must be greater than 0".to_string());
@@ -408,6 +429,11 @@ impl ConfigBuilder {
         self
     }
     
+    pub fn epoch(mut self, epoch_config: EpochConfig) -> Self {
+        self.config.epoch = epoch_config;
+        self
+    }
+    
     pub fn metrics(mut self, metrics_config: MetricsConfig) -> Self {
         self.config.metrics = metrics_config;
         self
@@ -2,7 +2,7 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use serde::{Serialize, Deserialize};
 
 pub mod manager;
@@ -13,6 +13,8 @@ pub struct AppConfig {
     pub node: NodeConfig,
     pub p2p: P2PConfig,
     pub sphinx: SphinxConfig,
+    #[serde(default)]
+    pub epoch: EpochConfig,
     pub metrics: MetricsConfig,
     pub logging: LoggingConfig,
     pub storage: StorageConfig,
@@ -25,6 +27,7 @@ impl Default for AppConfig {
             node: NodeConfig::default(),
             p2p: P2PConfig::default(),
             sphinx: SphinxConfig::default(),
+            epoch: EpochConfig::default(),
             metrics: MetricsConfig::default(),
             logging: LoggingConfig::default(),
             storage: StorageConfig::default(),
@@ -100,6 +103,8 @@ impl Default for P2PConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SphinxConfig {
     pub max_delay: Duration,
+    #[serde(default = "default_mean_delay")]
+    pub mean_delay: Duration, // Used when a packet's header carries no delay
     pub delay_distribution: String, // "uniform", "exponential", "poisson"
     pub cover_traffic_rate: f64,
     pub batch_size: usize,
@@ -107,12 +112,39 @@ pub struct SphinxConfig {
     pub max_hops: u8,
     pub enable_simd: bool,
     pub memory_pool_size: usize,
+    #[serde(default)]
+    pub key_store_path: Option<PathBuf>, // Persist Sphinx keys here; None keeps them in memory
+    #[serde(default = "default_key_rotation_epochs")]
+    pub key_rotation_epochs: u64,
+    #[serde(default = "default_key_overlap")]
+    pub key_overlap: Duration, // How long the previous key still decrypts after a rotation
+    #[serde(default = "default_replay_save_interval")]
+    pub replay_save_interval: Duration, // How often replay caches are saved next to persisted keys
+    #[serde(default)]
+    pub release_quantum: Option<Duration>, // Round packet release times up to this interval
+}
+
+fn default_mean_delay() -> Duration {
+    Duration::from_millis(50)
+}
+
+fn default_key_rotation_epochs() -> u64 {
+    1
+}
+
+fn default_key_overlap() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+fn default_replay_save_interval() -> Duration {
+    Duration::from_secs(10)
 }
 
 impl Default for SphinxConfig {
     fn default() -> Self {
         Self {
             max_delay: Duration::from_secs(10),
+            mean_delay: default_mean_delay(),
             delay_distribution: "exponential".to_string(),
             cover_traffic_rate: 0.1,
             batch_size: 100,
@@ -120,6 +152,27 @@ impl Default for SphinxConfig {
             max_hops: 5,
             enable_simd: true,
             memory_pool_size: 10000,
+            key_store_path: None,
+            key_rotation_epochs: default_key_rotation_epochs(),
+            key_overlap: default_key_overlap(),
+            replay_save_interval: default_replay_save_interval(),
+            release_quantum: None,
+        }
+    }
+}
+
+/// Epoch schedule shared by all nodes in the network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpochConfig {
+    pub genesis: SystemTime, // Start of epoch 0
+    pub epoch_length: Duration,
+}
+
+impl Default for EpochConfig {
+    fn default() -> Self {
+        Self {
+            genesis: SystemTime::UNIX_EPOCH,
+            epoch_length: Duration::from_secs(3600),
         }
     }
 }
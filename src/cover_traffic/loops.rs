@@ -0,0 +1,60 @@
+// Loop cover messages
+// The plaintext a loop cover packet delivers back to the node that sent it. Only the final
+// hop, which is the sender itself, ever sees it; on the wire the packet is an ordinary Sphinx
+// packet.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use rand_core::RngCore;
+
+/// Marks a delivered payload as a loop cover message
+const LOOP_COVER_TAG: &[u8; 8] = b"nymloop\x01";
+
+/// tag (8) || loop id (16) || send time in ms since the Unix epoch (8)
+pub const LOOP_COVER_MESSAGE_SIZE: usize = 8 + 16 + 8;
+
+pub type LoopId = [u8; 16];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopCoverMessage {
+    pub id: LoopId,
+    pub sent_at_ms: u64,
+}
+
+impl LoopCoverMessage {
+    pub fn new(rng: &mut impl RngCore) -> Self {
+        let mut id = [0u8; 16];
+        rng.fill_bytes(&mut id);
+        Self { id, sent_at_ms: now_ms() }
+    }
+
+    pub fn to_bytes(&self) -> [u8; LOOP_COVER_MESSAGE_SIZE] {
+        let mut bytes = [0u8; LOOP_COVER_MESSAGE_SIZE];
+        bytes[..8].copy_from_slice(LOOP_COVER_TAG);
+        bytes[8..24].copy_from_slice(&self.id);
+        bytes[24..].copy_from_slice(&self.sent_at_ms.to_be_bytes());
+        bytes
+    }
+
+    /// Parse a delivered message, returning None for anything that is not a loop
+    pub fn from_bytes(message: &[u8]) -> Option<Self> {
+        if message.len() != LOOP_COVER_MESSAGE_SIZE || &message[..8] != LOOP_COVER_TAG {
+            return None;
+        }
+        Some(Self {
+            id: message[8..24].try_into().ok()?,
+            sent_at_ms: u64::from_be_bytes(message[24..].try_into().ok()?),
+        })
+    }
+
+    /// Time since the loop was sent
+    pub fn round_trip_ms(&self) -> u64 {
+        now_ms().saturating_sub(self.sent_at_ms)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
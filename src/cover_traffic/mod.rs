@@ -1,7 +1,41 @@
 // Cover traffic generation module
+// Loop cover packets are real Sphinx packets sent over a VRF-selected path whose final hop is
+// this node. Mixes cannot tell them from client traffic, and their return shows the path is
+// alive. Drop cover packets go one hop to a neighbour from the peer registry, chosen by stake
+// and reliability, which delivers and discards them.
+pub mod loops;
+pub mod scheduler;
+pub mod tracker;
+
+pub use loops::*;
+pub use scheduler::*;
+pub use tracker::*;
+
 use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use rand_core::{OsRng, RngCore};
+use std::time::{Duration, Instant};
+use curve25519_dalek::ristretto::RistrettoPoint;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use tracing::{debug, warn};
+
+use crate::epoch::EpochManager;
+use crate::metrics::MetricsCollector;
+use crate::p2p::peer::{PeerInfo, PeerRegistry, ReputationUpdate};
+use crate::p2p::transport::PeerId;
+use crate::sphinx::{MixNodeId, SphinxError, SphinxKeySet, SphinxPacket, SphinxPacketBuilder};
+use crate::vrf::{MixNodeRegistry, VRFError, DEFAULT_MIX_LAYERS};
+
+/// Paths drawn per loop before giving up on avoiding this node
+const LOOP_PATH_ATTEMPTS: usize = 8;
+/// How far the schedule may fall behind before missed cover packets are dropped
+const MAX_SCHEDULE_LAG: Duration = Duration::from_secs(1);
+/// How long the cached list of drop cover targets is used before re-reading the registry
+const PEER_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+/// How often timed out loops are settled as lost
+const LOOP_EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct CoverTrafficGenerator {
     ratio: f64,
@@ -10,23 +44,75 @@ pub struct CoverTrafficGenerator {
 
 #[derive(Debug, Clone)]
 pub struct CoverTrafficConfig {
-    pub min_interval_ms: u64,
-    pub max_interval_ms: u64,
-    pub cover_packet_ratio: f64, // 0.1 = 10% cover traffic
+    pub min_interval_ms: u64,     // Burst protection caps cover at one packet per interval
+    pub max_interval_ms: u64,     // Cover rate floor while there is no real traffic
+    pub cover_packet_ratio: f64,  // 0.1 = one cover packet per ten real packets
     pub burst_protection: bool,
+    pub loop_path_length: usize, // Mix hops before the loop returns to this node
+    pub loop_fraction: f64,       // Share of cover sent as loops, the rest is drop cover to peers
 }
 
 impl Default for CoverTrafficConfig {
     fn default() -> Self {
         Self {
-            min_interval_ms: 100,
+            min_interval_ms: 1,
             max_interval_ms: 1000,
             cover_packet_ratio: 0.1,
             burst_protection: true,
+            loop_path_length: DEFAULT_MIX_LAYERS as usize,
+            loop_fraction: 0.5,
         }
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum CoverTrafficError {
+    #[error("Loop path selection failed: {0}")]
+    PathSelection(#[from] VRFError),
+    #[error("Selected hop is missing from the registry")]
+    UnknownHop,
+    #[error("Selected path passes through this node")]
+    PathThroughSelf,
+    #[error("Loop packet construction failed: {0}")]
+    Sphinx(#[from] SphinxError),
+}
+
+/// This node as the final hop of its own loops
+#[derive(Debug, Clone, Copy)]
+pub struct LocalNode {
+    pub id: MixNodeId,
+    pub public_key: RistrettoPoint,
+}
+
+/// A loop cover packet ready to send to its first hop
+#[derive(Debug, Clone)]
+pub struct LoopCoverPacket {
+    pub packet: SphinxPacket,
+    pub first_hop: SocketAddr,
+    pub path: Vec<MixNodeId>,
+    pub message: LoopCoverMessage,
+}
+
+/// A drop cover packet ready to send to a neighbouring mix
+#[derive(Debug, Clone)]
+pub struct DropCoverPacket {
+    pub packet: SphinxPacket,
+    pub target: SocketAddr,
+    pub peer_id: PeerId,
+}
+
+/// Node state the generator reads to route loops back to itself and find drop cover targets
+pub struct CoverTrafficContext {
+    pub node_id: MixNodeId,
+    pub packet_counter: Arc<AtomicU64>, // Real packets received, drives the cover rate
+    pub keys: Arc<Mutex<SphinxKeySet>>,
+    pub registry: Arc<Mutex<MixNodeRegistry>>,
+    pub epoch_manager: Arc<EpochManager>,
+    pub metrics: Arc<MetricsCollector>,
+    pub peers: Option<Arc<PeerRegistry>>, // Drop cover targets; without peers all cover loops
+    pub loops: Arc<std::sync::Mutex<LoopTracker>>, // Sent loops, settled by the receive path or timeout
+}
+
 impl CoverTrafficGenerator {
     pub fn new(ratio: f64) -> Self {
         let config = CoverTrafficConfig {
@@ -37,74 +123,366 @@ impl CoverTrafficGenerator {
         Self { ratio, config }
     }
     
-    /// Generate cover traffic to hide real traffic patterns
-    pub async fn run(&mut self, socket: Arc<UdpSocket>) {
+    pub fn with_config(config: CoverTrafficConfig) -> Self {
+        Self { ratio: config.cover_packet_ratio, config }
+    }
+    
+    /// Generate loop and drop cover traffic to hide real traffic patterns
+    pub async fn run(&mut self, socket: Arc<UdpSocket>, context: CoverTrafficContext) {
         let mut rng = OsRng;
+        let mut scheduler = PoissonScheduler::new(&self.config);
+        let mut next_at = tokio::time::Instant::now();
+        let mut peers: Vec<PeerInfo> = Vec::new();
+        let mut peers_refreshed_at: Option<tokio::time::Instant> = None;
+        let mut loops_expired_at = tokio::time::Instant::now();
         
         println!("🎭 Starting cover traffic generator ({}% ratio)", self.ratio * 100.0);
         
         loop {
-            let interval = self.calculate_next_interval(&mut rng);
-            tokio::time::sleep(std::time::Duration::from_millis(interval)).await;
+            let now = tokio::time::Instant::now();
+            scheduler.observe(context.packet_counter.load(Ordering::Relaxed), now.into_std());
+            
+            // Deadlines accumulate so timer granularity does not lower the rate; after a
+            // stall the schedule restarts instead of emitting the backlog at once
+            next_at = next_at.max(now - MAX_SCHEDULE_LAG) + scheduler.next_interval(&mut rng);
+            tokio::time::sleep_until(next_at).await;
             
-            // Generate realistic cover packet
-            let cover_packet = self.generate_cover_packet(&mut rng);
-            let target_addr = self.select_random_peer(&mut rng);
+            if loops_expired_at.elapsed() >= LOOP_EXPIRY_INTERVAL {
+                loops_expired_at = tokio::time::Instant::now();
+                Self::settle_lost_loops(&context).await;
+            }
+            
+            if let Some(registry) = &context.peers {
+                if peers_refreshed_at.is_none_or(|at| at.elapsed() >= PEER_REFRESH_INTERVAL) {
+                    peers = registry.get_sphinx_peers().await;
+                    peers_refreshed_at = Some(tokio::time::Instant::now());
+                }
+            }
+            
+            if !self.next_is_loop(&mut rng) {
+                if let Some(peer) = select_cover_peer(&peers, &context.node_id, &mut rng) {
+                    match self.build_drop_packet(peer, &mut rng) {
+                        Ok(cover) => match socket.send_to(&cover.packet.to_bytes(), cover.target).await {
+                            Ok(_) => context.metrics.record_drop_sent(),
+                            Err(e) => eprintln!("Cover traffic send error: {}", e),
+                        },
+                        Err(e) => debug!("Skipping drop cover packet: {}", e),
+                    }
+                    continue;
+                }
+                // No eligible neighbour, so this one goes out as a loop
+            }
             
-            if let Err(e) = socket.send_to(&cover_packet, target_addr).await {
-                eprintln!("Cover traffic send error: {}", e);
+            let local = LocalNode {
+                id: context.node_id,
+                public_key: context.keys.lock().await.current.public_key(),
+            };
+            let epoch = context.epoch_manager.current_epoch();
+            
+            let cover = {
+                let mut registry = context.registry.lock().await;
+                self.build_loop_packet(&mut registry, &local, epoch, &mut rng)
+            };
+            let cover = match cover {
+                Ok(cover) => cover,
+                Err(e) => {
+                    // Expected while the topology is still too small for a loop
+                    debug!("Skipping loop cover packet: {}", e);
+                    continue;
+                }
+            };
+            
+            match socket.send_to(&cover.packet.to_bytes(), cover.first_hop).await {
+                Ok(_) => {
+                    context.loops.lock().unwrap().record_sent(cover.message.id, cover.path, Instant::now());
+                    context.metrics.record_loop_sent();
+                },
+                Err(e) => eprintln!("Cover traffic send error: {}", e),
             }
         }
     }
     
-    fn calculate_next_interval(&self, rng: &mut impl RngCore) -> u64 {
-        let min = self.config.min_interval_ms;
-        let max = self.config.max_interval_ms;
+    /// Count timed out loops as lost and report the suspected droppers on their paths
+    async fn settle_lost_loops(context: &CoverTrafficContext) {
+        let (lost, droppers) = {
+            let mut loops = context.loops.lock().unwrap();
+            let lost = loops.expire(Instant::now());
+            // Only hops whose loops are lost clearly more often than loops avoiding them are
+            // blamed; an honest mix next to a dropper loses no more than its neighbours do
+            let droppers: Vec<MixNodeId> = lost.iter()
+                .flat_map(|lost_loop| lost_loop.path.iter())
+                .filter(|node| loops.is_suspect(node))
+                .copied()
+                .collect();
+            (lost.len(), droppers)
+        };
+        if lost == 0 {
+            return;
+        }
         
-        if max <= min {
-            return min;
+        context.metrics.record_loops_lost(lost as u64);
+        if droppers.is_empty() {
+            return;
         }
+        warn!("{} lost loops attributed to suspected packet droppers", droppers.len());
         
-        let range = max - min;
-        let random_offset = (rng.next_u64() % range) as u64;
+        if let Some(peers) = &context.peers {
+            for node in droppers {
+                peers.update_reputation(&PeerId(node), ReputationUpdate::PacketDropped).await;
+            }
+        }
+    }
+    
+    fn next_is_loop(&self, rng: &mut impl RngCore) -> bool {
+        ((rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < self.config.loop_fraction
+    }
+    
+    /// Build a single-hop packet that `peer` delivers and discards
+    pub fn build_drop_packet<R: RngCore + CryptoRng>(
+        &self,
+        peer: &PeerInfo,
+        rng: &mut R
+    ) -> Result<DropCoverPacket, CoverTrafficError> {
+        // Random content of a loop message's length; only the peer can see it
+        let mut payload = [0u8; LOOP_COVER_MESSAGE_SIZE];
+        rng.fill_bytes(&mut payload);
+        let packet = SphinxPacketBuilder::new()
+            .route(vec![(peer.peer_id.0, peer.public_key)])
+            .payload(&payload)
+            .build_with_rng(rng)?;
         
-        min + random_offset
+        Ok(DropCoverPacket { packet, target: peer.address, peer_id: peer.peer_id.clone() })
     }
     
-    fn generate_cover_packet(&self, rng: &mut impl RngCore) -> Vec<u8> {
-        // Generate a packet that looks like a real Sphinx packet
-        let packet_size = crate::sphinx::SPHINX_PACKET_SIZE;
-        let mut packet = vec![0u8; packet_size];
-        
-        // Fill with realistic but random data
-        rng.fill_bytes(&mut packet);
-        
-        // Make some parts look more structured like real packets
-        // Set some realistic header patterns
-        packet[0..4].copy_from_slice(&[0x42, 0x4E, 0x01, 0x00]); // "BN" + version
-        
-        // Add some structure to make it look like encrypted data
-        for i in (32..64).step_by(8) {
-            let value = rng.next_u64();
-            if i + 8 <= packet.len() {
-                packet[i..i + 8].copy_from_slice(&value.to_be_bytes());
+    /// Build a loop over a fresh VRF-selected path that ends at `local`
+    pub fn build_loop_packet<R: RngCore + CryptoRng>(
+        &self,
+        registry: &mut MixNodeRegistry,
+        local: &LocalNode,
+        epoch: u64,
+        rng: &mut R
+    ) -> Result<LoopCoverPacket, CoverTrafficError> {
+        let path = self.select_loop_path(registry, local, epoch, rng)?;
+        
+        let mut route = Vec::with_capacity(path.len() + 1);
+        for id in &path {
+            let node = registry.get_node(id).ok_or(CoverTrafficError::UnknownHop)?;
+            route.push((node.id, node.public_key));
+        }
+        let first_hop = registry.get_node(&path[0]).ok_or(CoverTrafficError::UnknownHop)?.address;
+        route.push((local.id, local.public_key));
+        
+        let message = LoopCoverMessage::new(rng);
+        let packet = SphinxPacketBuilder::new()
+            .route(route)
+            .payload(&message.to_bytes())
+            .build_with_rng(rng)?;
+        
+        Ok(LoopCoverPacket { packet, first_hop, path, message })
+    }
+    
+    /// Draw VRF paths until one avoids this node, which the registry may itself contain
+    fn select_loop_path(
+        &self,
+        registry: &mut MixNodeRegistry,
+        local: &LocalNode,
+        epoch: u64,
+        rng: &mut impl RngCore
+    ) -> Result<Vec<MixNodeId>, CoverTrafficError> {
+        for _ in 0..LOOP_PATH_ATTEMPTS {
+            // A random stream id gives every loop an independent path
+            let mut stream_id = [0u8; 16];
+            rng.fill_bytes(&mut stream_id);
+            let (path, _proof) = registry.select_path(&stream_id, epoch, self.config.loop_path_length)?;
+            if !path.contains(&local.id) {
+                return Ok(path);
             }
         }
+        Err(CoverTrafficError::PathThroughSelf)
+    }
+}
+
+/// Pick a drop cover target with probability proportional to stake × reliability
+///
+/// `peers` should already exclude blocked and offline peers (see
+/// `PeerRegistry::get_sphinx_peers`); this node itself is never picked.
+pub fn select_cover_peer<'a>(
+    peers: &'a [PeerInfo],
+    local_id: &MixNodeId,
+    rng: &mut impl RngCore
+) -> Option<&'a PeerInfo> {
+    let weight = |peer: &PeerInfo| {
+        if &peer.peer_id.0 == local_id {
+            0.0
+        } else {
+            peer.stake as f64 * peer.reputation.reliability_score.clamp(0.0, 1.0)
+        }
+    };
+    
+    let total: f64 = peers.iter().map(weight).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    
+    let mut target = (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * total;
+    for peer in peers {
+        let peer_weight = weight(peer);
+        if peer_weight <= 0.0 {
+            continue;
+        }
+        if target < peer_weight {
+            return Some(peer);
+        }
+        target -= peer_weight;
+    }
+    // Rounding can leave the target just past the last weight
+    peers.iter().rev().find(|peer| weight(peer) > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, scalar::Scalar};
+    use crate::sphinx::{ProcessedPayload, RoutingCommand, SphinxMixer};
+    use crate::vrf::{MixNodeInfo, Region};
+    
+    #[test]
+    fn test_loop_packet_returns_to_sender() {
+        let regions = [Region::Europe, Region::Asia, Region::NorthAmerica];
+        let mut mixers: Vec<(MixNodeId, SphinxMixer)> = Vec::new();
+        let mut registry = MixNodeRegistry::new().unwrap();
+        for (i, region) in regions.iter().enumerate() {
+            let id = [i as u8 + 1; 32];
+            let mixer = SphinxMixer::new(Scalar::from(i as u64 + 100));
+            registry.add_node(MixNodeInfo {
+                id,
+                public_key: mixer.public_key(),
+                address: format!("127.0.0.1:{}", 9000 + i).parse().unwrap(),
+                stake_weight: 1000,
+                reliability_score: 1.0,
+                geographic_region: region.clone(),
+                last_seen: std::time::SystemTime::now(),
+                layer: None,
+            });
+            mixers.push((id, mixer));
+        }
+        
+        let local_key = Scalar::from(7u64);
+        let local = LocalNode { id: [0xAA; 32], public_key: local_key * RISTRETTO_BASEPOINT_POINT };
+        let generator = CoverTrafficGenerator::new(0.1);
+        let cover = generator.build_loop_packet(&mut registry, &local, 1, &mut OsRng).unwrap();
+        assert_eq!(cover.path.len(), 3);
+        
+        // Peel each mix layer in path order; the last layer is ours and delivers the loop
+        let mut packet = cover.packet;
+        for id in &cover.path {
+            let mixer = &mut mixers.iter_mut().find(|(mix_id, _)| mix_id == id).unwrap().1;
+            let processed = mixer.process_packet(&packet).unwrap();
+            let ProcessedPayload::Forward(bytes) = processed.payload else { panic!("mix hop must forward") };
+            packet = SphinxPacket::from_bytes(&bytes).unwrap();
+        }
         
-        packet
+        let processed = SphinxMixer::new(local_key).process_packet(&packet).unwrap();
+        assert!(matches!(processed.routing_info.command, RoutingCommand::Deliver));
+        let ProcessedPayload::Final(message) = processed.payload else { panic!("loop must be delivered") };
+        assert_eq!(LoopCoverMessage::from_bytes(&message), Some(cover.message));
     }
     
-    fn select_random_peer(&self, rng: &mut impl RngCore) -> std::net::SocketAddr {
-        // For testing, use a set of dummy addresses
-        // In production, this would select from the actual peer list
-        let test_addresses = [
-            "127.0.0.1:8081",
-            "127.0.0.1:8082", 
-            "127.0.0.1:8083",
-            "127.0.0.1:8084",
-        ];
-        
-        let index = (rng.next_u32() as usize) % test_addresses.len();
-        test_addresses[index].parse().unwrap_or_else(|_| "127.0.0.1:8080".parse().unwrap())
+    #[tokio::test]
+    async fn test_drop_cover_targets_weighted_unblocked_peers() {
+        use crate::p2p::peer::{PeerRegistryConfig, PeerReputation};
+        
+        let registry = PeerRegistry::new(PeerRegistryConfig::default());
+        let local_id = [0xAA; 32];
+        // (id, stake, reliability); the blocked peer would otherwise dominate
+        for (id, stake, reliability) in [(1u8, 1000, 1.0), (2, 1000, 0.25), (3, 100_000, 1.0), (0xAA, 100_000, 1.0)] {
+            registry.register_peer(PeerInfo {
+                peer_id: PeerId([id; 32]),
+                address: format!("127.0.0.1:{}", 9000 + id as u16).parse().unwrap(),
+                public_key: Scalar::from(id as u64) * RISTRETTO_BASEPOINT_POINT,
+                stake,
+                region: "Europe".to_string(),
+                version: "1.0.0".to_string(),
+                capabilities: Default::default(),
+                reputation: PeerReputation { reliability_score: reliability, ..Default::default() },
+                connection_info: Default::default(),
+                last_seen: std::time::SystemTime::now(),
+                is_online: true,
+            }).await.unwrap();
+        }
+        registry.block_peer(&PeerId([3; 32]), "test".to_string()).await;
+        
+        let peers = registry.get_sphinx_peers().await;
+        let mut counts = [0usize; 2];
+        for _ in 0..10_000 {
+            let peer = select_cover_peer(&peers, &local_id, &mut OsRng).unwrap();
+            counts[peer.peer_id.0[0] as usize - 1] += 1;
+        }
+        // Weights 1000 and 250
+        assert!((7_600..8_400).contains(&counts[0]), "counts {:?}", counts);
+        assert_eq!(counts[0] + counts[1], 10_000);
+        
+        let generator = CoverTrafficGenerator::new(0.1);
+        let cover = generator.build_drop_packet(&peers[0], &mut OsRng).unwrap();
+        assert_eq!(cover.target, peers[0].address);
+    }
+    
+    #[tokio::test]
+    async fn test_lost_loops_lower_dropper_reputation() {
+        use crate::config::EpochConfig;
+        use crate::metrics::MetricsConfig;
+        use crate::p2p::peer::PeerRegistryConfig;
+        
+        let peers = Arc::new(PeerRegistry::new(PeerRegistryConfig::default()));
+        for id in 1..=4u8 {
+            peers.register_peer(PeerInfo {
+                peer_id: PeerId([id; 32]),
+                address: format!("127.0.0.1:{}", 9000 + id as u16).parse().unwrap(),
+                public_key: Scalar::from(id as u64) * RISTRETTO_BASEPOINT_POINT,
+                stake: 1000,
+                region: "Europe".to_string(),
+                version: "1.0.0".to_string(),
+                capabilities: Default::default(),
+                reputation: Default::default(),
+                connection_info: Default::default(),
+                last_seen: std::time::SystemTime::now(),
+                is_online: true,
+            }).await.unwrap();
+        }
+        
+        let context = CoverTrafficContext {
+            node_id: [0xAA; 32],
+            packet_counter: Arc::new(AtomicU64::new(0)),
+            keys: Arc::new(Mutex::new(SphinxKeySet::generate(0))),
+            registry: Arc::new(Mutex::new(MixNodeRegistry::new().unwrap())),
+            epoch_manager: Arc::new(EpochManager::new(&EpochConfig::default()).unwrap()),
+            metrics: Arc::new(MetricsCollector::new(MetricsConfig::default())),
+            peers: Some(peers.clone()),
+            loops: Arc::new(std::sync::Mutex::new(LoopTracker::new(LoopTrackerConfig::default()))),
+        };
+        
+        // Two-hop loops over nodes 1..=4; node 4 drops every loop it carries
+        let sent_at = Instant::now() - Duration::from_secs(60);
+        {
+            let mut loops = context.loops.lock().unwrap();
+            for i in 0..120u32 {
+                let mut id = [0u8; 16];
+                id[..4].copy_from_slice(&i.to_be_bytes());
+                let path = vec![[(i % 2) as u8 + 1; 32], [((i >> 1) % 2) as u8 + 3; 32]];
+                let dropped = path[1] == [4; 32];
+                loops.record_sent(id, path, sent_at);
+                if !dropped {
+                    loops.record_returned(&id, sent_at);
+                }
+            }
+        }
+        
+        CoverTrafficGenerator::settle_lost_loops(&context).await;
+        
+        let reliability = |peer: Option<PeerInfo>| peer.unwrap().reputation.reliability_score;
+        assert!(reliability(peers.get_peer(&PeerId([4; 32])).await) < 1.0);
+        for honest in 1..=3u8 {
+            assert_eq!(reliability(peers.get_peer(&PeerId([honest; 32])).await), 1.0);
+        }
     }
-}
\ No newline at end of file
+}
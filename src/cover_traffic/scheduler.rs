@@ -0,0 +1,116 @@
+// Poisson cover traffic scheduling
+// Cover packets are emitted as a Poisson process whose rate follows the node's real traffic,
+// so the configured cover ratio holds at any load. Exponential inter-arrival times carry no
+// timing pattern an observer could lock on to.
+
+use std::time::{Duration, Instant};
+use rand_core::RngCore;
+
+use crate::cover_traffic::CoverTrafficConfig;
+
+/// Time constant of the real traffic rate estimate
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+pub struct PoissonScheduler {
+    ratio: f64,
+    min_rate: f64,
+    max_rate: Option<f64>,
+    observed_rate: f64,
+    last_count: u64,
+    last_sample: Option<Instant>,
+}
+
+impl PoissonScheduler {
+    pub fn new(config: &CoverTrafficConfig) -> Self {
+        Self {
+            ratio: config.cover_packet_ratio,
+            // Cover keeps flowing at least every max_interval_ms when the node is idle
+            min_rate: 1000.0 / config.max_interval_ms.max(1) as f64,
+            max_rate: config.burst_protection.then(|| 1000.0 / config.min_interval_ms.max(1) as f64),
+            observed_rate: 0.0,
+            last_count: 0,
+            last_sample: None,
+        }
+    }
+
+    /// Update the real traffic estimate from a running packet count
+    pub fn observe(&mut self, packet_count: u64, now: Instant) {
+        let Some(last_sample) = self.last_sample.replace(now) else {
+            self.last_count = packet_count;
+            return;
+        };
+
+        let elapsed = now.saturating_duration_since(last_sample).as_secs_f64();
+        let packets = packet_count.saturating_sub(self.last_count);
+        self.last_count = packet_count;
+        if elapsed <= 0.0 {
+            return;
+        }
+
+        // Exponentially weighted so a single busy interval moves the estimate gradually
+        let weight = 1.0 - (-elapsed / RATE_WINDOW.as_secs_f64()).exp();
+        self.observed_rate += weight * (packets as f64 / elapsed - self.observed_rate);
+    }
+
+    /// Real packets per second as currently estimated
+    pub fn observed_rate(&self) -> f64 {
+        self.observed_rate
+    }
+
+    /// Cover packets per second to emit
+    ///
+    /// `cover_packet_ratio` of the real rate, never below the idle rate and, with burst
+    /// protection, never above one packet per `min_interval_ms`.
+    pub fn cover_rate(&self) -> f64 {
+        let rate = (self.observed_rate * self.ratio).max(self.min_rate);
+        match self.max_rate {
+            Some(max_rate) => rate.min(max_rate),
+            None => rate,
+        }
+    }
+
+    /// Exponentially distributed wait until the next cover packet
+    pub fn next_interval(&self, rng: &mut impl RngCore) -> Duration {
+        // Uniform in (0, 1], so the logarithm is finite
+        let uniform = 1.0 - (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        Duration::from_secs_f64(-uniform.ln() / self.cover_rate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::OsRng;
+
+    fn mean_interval(scheduler: &PoissonScheduler) -> f64 {
+        let samples = 20_000;
+        (0..samples).map(|_| scheduler.next_interval(&mut OsRng).as_secs_f64()).sum::<f64>() / samples as f64
+    }
+
+    #[test]
+    fn test_rate_tracks_real_traffic() {
+        let config = CoverTrafficConfig {
+            cover_packet_ratio: 0.1,
+            min_interval_ms: 1,
+            max_interval_ms: 1000,
+            burst_protection: true,
+            ..Default::default()
+        };
+        let mut scheduler = PoissonScheduler::new(&config);
+        assert_eq!(scheduler.cover_rate(), 1.0);
+
+        // 2000 real packets per second, observed long enough for the estimate to settle
+        let start = Instant::now();
+        for second in 0..=60u64 {
+            scheduler.observe(second * 2000, start + Duration::from_secs(second));
+        }
+        assert!((scheduler.cover_rate() - 200.0).abs() < 1.0, "rate {}", scheduler.cover_rate());
+        assert!((mean_interval(&scheduler) - 0.005).abs() < 0.0003);
+
+        // A spike to 100k pkt/s is capped at one cover packet per millisecond
+        for second in 61..=120u64 {
+            scheduler.observe(120_000 + (second - 60) * 100_000, start + Duration::from_secs(second));
+        }
+        assert_eq!(scheduler.cover_rate(), 1000.0);
+    }
+}
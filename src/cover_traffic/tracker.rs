@@ -0,0 +1,262 @@
+// Loop cover accounting
+// Every loop this node sends is remembered with its path until it returns or times out. A
+// single lost loop does not say which hop dropped it, but loops take independent random paths,
+// so a mix that silently drops packets loses more of its loops than the rest of the topology
+// does. Honest mixes that often share paths with a dropper also lose many loops, as do all
+// mixes in a small topology, so a node is only a suspect when its loss rate stands out against
+// the loops that avoided it.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::cover_traffic::LoopId;
+use crate::sphinx::MixNodeId;
+
+#[derive(Debug, Clone)]
+pub struct LoopTrackerConfig {
+    pub loop_timeout: Duration,    // A loop not back by then counts as lost
+    pub min_samples: u64,          // Settled loops through a node before it can be a suspect
+    pub suspect_loss_rate: f64,    // Excess loss over loops avoiding a node that marks it as a dropper
+    pub max_tracked_paths: usize,  // Bound on per-path statistics
+}
+
+impl Default for LoopTrackerConfig {
+    fn default() -> Self {
+        Self {
+            loop_timeout: Duration::from_secs(30),
+            min_samples: 20,
+            suspect_loss_rate: 0.3,
+            max_tracked_paths: 10_000,
+        }
+    }
+}
+
+/// Loop outcomes for a node or a path
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LossStats {
+    pub sent: u64,
+    pub returned: u64,
+    pub lost: u64,
+}
+
+impl LossStats {
+    /// Lost share of the loops that have returned or timed out
+    pub fn loss_rate(&self) -> f64 {
+        let settled = self.returned + self.lost;
+        if settled == 0 {
+            0.0
+        } else {
+            self.lost as f64 / settled as f64
+        }
+    }
+
+    fn in_flight(&self) -> bool {
+        self.sent > self.returned + self.lost
+    }
+}
+
+/// A loop that timed out, with the hops that may have dropped it
+#[derive(Debug, Clone)]
+pub struct LostLoop {
+    pub id: LoopId,
+    pub path: Vec<MixNodeId>,
+}
+
+struct PendingLoop {
+    path: Vec<MixNodeId>,
+    sent_at: Instant,
+}
+
+/// Matches returned loops to sent ones and keeps per-node and per-path loss
+pub struct LoopTracker {
+    config: LoopTrackerConfig,
+    pending: HashMap<LoopId, PendingLoop>,
+    // Send order, so expiry only looks at the oldest loops
+    expiry: VecDeque<(Instant, LoopId)>,
+    nodes: HashMap<MixNodeId, LossStats>,
+    paths: HashMap<Vec<MixNodeId>, LossStats>,
+    total: LossStats,
+}
+
+impl LoopTracker {
+    pub fn new(config: LoopTrackerConfig) -> Self {
+        Self {
+            config,
+            pending: HashMap::new(),
+            expiry: VecDeque::new(),
+            nodes: HashMap::new(),
+            paths: HashMap::new(),
+            total: LossStats::default(),
+        }
+    }
+
+    pub fn record_sent(&mut self, id: LoopId, path: Vec<MixNodeId>, now: Instant) {
+        for node in &path {
+            self.nodes.entry(*node).or_default().sent += 1;
+        }
+
+        // Settled paths are forgotten first; loops in flight keep theirs
+        if self.paths.len() >= self.config.max_tracked_paths && !self.paths.contains_key(&path) {
+            self.paths.retain(|_, stats| stats.in_flight());
+        }
+        self.paths.entry(path.clone()).or_default().sent += 1;
+        self.total.sent += 1;
+
+        self.pending.insert(id, PendingLoop { path, sent_at: now });
+        self.expiry.push_back((now, id));
+    }
+
+    /// Settle a returned loop, giving its round trip time
+    ///
+    /// Returns None for loops this node did not send, already counted or already given up on.
+    pub fn record_returned(&mut self, id: &LoopId, now: Instant) -> Option<Duration> {
+        let pending = self.pending.remove(id)?;
+        self.settle(&pending.path, |stats| stats.returned += 1);
+        Some(now.saturating_duration_since(pending.sent_at))
+    }
+
+    /// Settle every loop older than the timeout as lost
+    pub fn expire(&mut self, now: Instant) -> Vec<LostLoop> {
+        let mut lost = Vec::new();
+        while let Some(&(sent_at, id)) = self.expiry.front() {
+            if now.saturating_duration_since(sent_at) < self.config.loop_timeout {
+                break;
+            }
+            self.expiry.pop_front();
+
+            // Loops that returned are no longer pending
+            if let Some(pending) = self.pending.remove(&id) {
+                self.settle(&pending.path, |stats| stats.lost += 1);
+                lost.push(LostLoop { id, path: pending.path });
+            }
+        }
+        lost
+    }
+
+    fn settle(&mut self, path: &[MixNodeId], update: impl Fn(&mut LossStats)) {
+        for node in path {
+            if let Some(stats) = self.nodes.get_mut(node) {
+                update(stats);
+            }
+        }
+        if let Some(stats) = self.paths.get_mut(path) {
+            update(stats);
+        }
+        update(&mut self.total);
+    }
+
+    /// How much more often loops through `node` are lost than loops that avoided it
+    ///
+    /// None until `node` has `min_samples` settled loops.
+    pub fn excess_loss(&self, node: &MixNodeId) -> Option<f64> {
+        let stats = self.nodes.get(node)?;
+        if stats.returned + stats.lost < self.config.min_samples {
+            return None;
+        }
+
+        // Saturating in case a path ever repeats a node
+        let avoided = LossStats {
+            sent: self.total.sent.saturating_sub(stats.sent),
+            returned: self.total.returned.saturating_sub(stats.returned),
+            lost: self.total.lost.saturating_sub(stats.lost),
+        };
+        Some(stats.loss_rate() - avoided.loss_rate())
+    }
+
+    /// Whether loops through `node` are lost past the suspect threshold more often than others
+    pub fn is_suspect(&self, node: &MixNodeId) -> bool {
+        self.excess_loss(node).is_some_and(|excess| excess >= self.config.suspect_loss_rate)
+    }
+
+    pub fn suspects(&self) -> Vec<(MixNodeId, LossStats)> {
+        self.nodes.iter()
+            .filter(|(node, _)| self.is_suspect(node))
+            .map(|(node, stats)| (*node, *stats))
+            .collect()
+    }
+
+    pub fn node_stats(&self, node: &MixNodeId) -> Option<LossStats> {
+        self.nodes.get(node).copied()
+    }
+
+    pub fn path_stats(&self, path: &[MixNodeId]) -> Option<LossStats> {
+        self.paths.get(path).copied()
+    }
+
+    /// Loops sent and not yet settled
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dropper_becomes_suspect() {
+        let mut tracker = LoopTracker::new(LoopTrackerConfig::default());
+        let start = Instant::now();
+        let dropper = [9u8; 32];
+
+        // Node 9 drops every loop it sees and is on one path in five; nodes 1..=4 are honest
+        for i in 0..400u32 {
+            let mut id = [0u8; 16];
+            id[..4].copy_from_slice(&i.to_be_bytes());
+            let first = [(i % 4) as u8 + 1; 32];
+            let second = if i % 5 == 0 { dropper } else { [((i + 1) % 4) as u8 + 1; 32] };
+            tracker.record_sent(id, vec![first, second], start);
+            if second != dropper {
+                assert!(tracker.record_returned(&id, start + Duration::from_millis(50)).is_some());
+            }
+        }
+        assert!(tracker.record_returned(&[0xFF; 16], start).is_none());
+        assert_eq!(tracker.pending(), 80);
+
+        assert!(tracker.expire(start + Duration::from_secs(1)).is_empty());
+        let lost = tracker.expire(start + Duration::from_secs(31));
+        assert_eq!(lost.len(), 80);
+        assert_eq!(tracker.pending(), 0);
+
+        let dropper_stats = tracker.node_stats(&dropper).unwrap();
+        assert_eq!(dropper_stats, LossStats { sent: 80, returned: 0, lost: 80 });
+        assert_eq!(tracker.path_stats(&[[1; 32], dropper]).unwrap().loss_rate(), 1.0);
+        assert_eq!(tracker.path_stats(&[[2; 32], [3; 32]]).unwrap().loss_rate(), 0.0);
+
+        let suspects: Vec<MixNodeId> = tracker.suspects().into_iter().map(|(node, _)| node).collect();
+        assert_eq!(suspects, vec![dropper]);
+    }
+
+    #[test]
+    fn test_small_layered_topology_blames_only_dropper() {
+        let mut tracker = LoopTracker::new(LoopTrackerConfig::default());
+        let start = Instant::now();
+        // Two mixes per layer; node 4 in the middle layer drops everything, so each honest
+        // mix outside that layer loses half of its loops
+        let layers = [[[1u8; 32], [2; 32]], [[3; 32], [4; 32]], [[5; 32], [6; 32]]];
+        let dropper = [4u8; 32];
+
+        for i in 0..240u32 {
+            let mut id = [0u8; 16];
+            id[..4].copy_from_slice(&i.to_be_bytes());
+            let path: Vec<MixNodeId> = layers.iter()
+                .enumerate()
+                .map(|(layer, mixes)| mixes[(i as usize >> layer) % 2])
+                .collect();
+            let dropped = path.contains(&dropper);
+            tracker.record_sent(id, path, start);
+            if !dropped {
+                tracker.record_returned(&id, start + Duration::from_millis(50));
+            }
+        }
+        tracker.expire(start + Duration::from_secs(31));
+
+        let honest = tracker.node_stats(&[1; 32]).unwrap();
+        assert_eq!(honest.loss_rate(), 0.5);
+        assert!(tracker.excess_loss(&[1; 32]).unwrap().abs() < 0.01);
+        assert_eq!(tracker.excess_loss(&dropper), Some(1.0));
+
+        let suspects: Vec<MixNodeId> = tracker.suspects().into_iter().map(|(node, _)| node).collect();
+        assert_eq!(suspects, vec![dropper]);
+    }
+}
@@ -0,0 +1,131 @@
+// Network epochs
+// Epochs are fixed-length windows counted from a network-wide genesis time. Key rotation,
+// replay cache generations and path selection all follow the current epoch.
+
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::config::EpochConfig;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EpochError {
+    #[error("Epoch length must be greater than 0")]
+    ZeroLength,
+}
+
+/// Tracks the current epoch and broadcasts transitions to subscribers
+pub struct EpochManager {
+    genesis: SystemTime,
+    epoch_length: Duration,
+    sender: watch::Sender<u64>,
+}
+
+impl EpochManager {
+    pub fn new(config: &EpochConfig) -> Result<Self, EpochError> {
+        if config.epoch_length.is_zero() {
+            return Err(EpochError::ZeroLength);
+        }
+
+        let manager = Self {
+            genesis: config.genesis,
+            epoch_length: config.epoch_length,
+            sender: watch::channel(0).0,
+        };
+        manager.sender.send_replace(manager.epoch_at(SystemTime::now()));
+        Ok(manager)
+    }
+
+    /// Epoch containing `time`; times before genesis belong to epoch 0
+    pub fn epoch_at(&self, time: SystemTime) -> u64 {
+        time.duration_since(self.genesis)
+            .map(|elapsed| (elapsed.as_nanos() / self.epoch_length.as_nanos()) as u64)
+            .unwrap_or(0)
+    }
+
+    /// Time at which `epoch` begins
+    pub fn epoch_start(&self, epoch: u64) -> SystemTime {
+        let nanos = self.epoch_length.as_nanos() * epoch as u128;
+        self.genesis + Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+    }
+
+    pub fn epoch_length(&self) -> Duration {
+        self.epoch_length
+    }
+
+    /// Last epoch published to subscribers
+    pub fn current_epoch(&self) -> u64 {
+        *self.sender.borrow()
+    }
+
+    /// Receiver notified on every epoch transition
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.sender.subscribe()
+    }
+
+    /// Time remaining until the epoch after the one containing `now`
+    pub fn time_until_next(&self, now: SystemTime) -> Duration {
+        self.epoch_start(self.epoch_at(now) + 1)
+            .duration_since(now)
+            .unwrap_or_default()
+    }
+
+    /// Publish the epoch for `now`, returning it if a transition happened
+    ///
+    /// Epochs never move backwards, so a clock stepping back does not re-run a transition.
+    pub fn advance(&self, now: SystemTime) -> Option<u64> {
+        let epoch = self.epoch_at(now);
+        let advanced = self.sender.send_if_modified(|current| {
+            if epoch > *current {
+                *current = epoch;
+                true
+            } else {
+                false
+            }
+        });
+
+        advanced.then_some(epoch)
+    }
+
+    /// Advance at every epoch boundary
+    pub async fn run(&self) {
+        loop {
+            tokio::time::sleep(self.time_until_next(SystemTime::now())).await;
+
+            if let Some(epoch) = self.advance(SystemTime::now()) {
+                info!("Entered epoch {}", epoch);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch_schedule_and_transitions() {
+        let now = SystemTime::now();
+        let config = EpochConfig {
+            genesis: now - Duration::from_secs(250),
+            epoch_length: Duration::from_secs(100),
+        };
+        let manager = EpochManager::new(&config).unwrap();
+        let mut receiver = manager.subscribe();
+
+        assert_eq!(manager.current_epoch(), 2);
+        assert_eq!(manager.epoch_start(2), config.genesis + Duration::from_secs(200));
+        assert_eq!(manager.time_until_next(now), Duration::from_secs(50));
+
+        // Nothing to publish within the same epoch or when the clock goes back
+        assert_eq!(manager.advance(now + Duration::from_secs(49)), None);
+        assert_eq!(manager.advance(now - Duration::from_secs(100)), None);
+        assert!(!receiver.has_changed().unwrap());
+
+        assert_eq!(manager.advance(now + Duration::from_secs(50)), Some(3));
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(*receiver.borrow_and_update(), 3);
+
+        assert!(EpochManager::new(&EpochConfig { epoch_length: Duration::ZERO, ..config }).is_err());
+    }
+}
@@ -124,6 +124,7 @@ pub struct SystemHealth {
     pub uptime: Duration,
     pub recovery_count: u32,
     pub trend: HealthTrend,
+    pub epoch: u64,
 }
 
 /// Health trend analysis
@@ -319,6 +320,7 @@ impl HealthMonitor {
                 time_window: Duration::from_secs(3600),
                 predictions: Vec::new(),
             },
+            epoch: metrics_collector.current_epoch(),
         }));
         
         let health_monitor = Self {
@@ -395,6 +397,7 @@ impl HealthMonitor {
             uptime: SystemTime::now().duration_since(self.start_time).unwrap_or_default(),
             recovery_count: self.get_total_recovery_attempts(),
             trend: self.analyze_trend(&check_results).await,
+            epoch: self.metrics_collector.current_epoch(),
         };
         
         // Update current health
@@ -750,6 +753,7 @@ impl HealthMonitorTask {
                 time_window: Duration::from_secs(3600),
                 predictions: Vec::new(),
             },
+            epoch: self.metrics_collector.current_epoch(),
         }
     }
     
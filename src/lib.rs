@@ -2,10 +2,13 @@ use tokio::net::UdpSocket;
 use tokio::sync::mpsc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use curve25519_dalek::scalar::Scalar;
-use rand_core::{OsRng, RngCore};
+use std::net::{IpAddr, SocketAddr};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime};
 
 pub mod sphinx;
+pub mod mixing;
+pub mod epoch;
 pub mod vrf;
 pub mod cover_traffic;
 pub mod rate_limit;
@@ -19,29 +22,56 @@ pub mod cli;
 pub mod p2p;
 pub mod storage;
 pub mod logging;
+pub mod net;
 
 pub use sphinx::*;
 pub use vrf::*;
+pub use cover_traffic::{CoverTrafficGenerator, CoverTrafficConfig};
+pub use rate_limit::{RateLimiter, RateLimitConfig, RateLimitResult, RateLimitReason, RateLimitStats};
 
 use chrono;
+use crate::config::{SphinxConfig, EpochConfig};
+use crate::cover_traffic::{CoverTrafficContext, LoopCoverMessage, LoopTracker, LoopTrackerConfig};
+use crate::epoch::EpochManager;
+use crate::logging::LoggingConfig;
+use crate::logging::audit::{AuditLogger, AuditEvent, AuditEventType, AuditResult, RiskLevel};
+use crate::metrics::{MetricsCollector, DropReason, PacketType};
+use crate::mixing::DelayQueueConfig;
+use crate::net::{bind_reuseport, BatchedSocket, RecvBuffers, MMSG_BATCH_SIZE};
+use crate::p2p::peer::{PeerRegistry, PeerRegistryConfig};
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use crate::net::uring::{UringReceiver, UringSender};
 
 pub struct HighPerformanceMixnode {
     config: MixnodeConfig,
-    mixer: Arc<tokio::sync::Mutex<SphinxMixer>>,
+    node_id: MixNodeId,
+    mixer_pool: Arc<MixerPool<SocketAddr>>,
+    mixer_workers: Vec<MixerWorker<SocketAddr>>,
     vrf_selector: Arc<tokio::sync::Mutex<MixNodeRegistry>>,
     packet_counter: Arc<AtomicU64>,
-    #[allow(dead_code)]
-    cover_traffic: CoverTrafficGenerator,
-    #[allow(dead_code)]
-    rate_limiter: RateLimiter,
+    metrics: Arc<MetricsCollector>,
+    epoch_manager: Arc<EpochManager>,
+    key_rotation: Arc<KeyRotation>,
+    replay_store: Option<ReplayStore>, // Saved replay caches for the persisted keys
+    peers: Arc<PeerRegistry>,
+    loop_tracker: Arc<std::sync::Mutex<LoopTracker>>,
+    rate_limit: RateLimitConfig,     // Node-wide budgets, split into one limiter per receiver
 }
 
 #[derive(Debug, Clone)]
 pub struct MixnodeConfig {
     pub listen_address: String,
     pub max_packet_rate: u64,        // packets per second
+    pub per_ip_packet_rate: u32,     // packets per second from one source IP
+    pub per_ip_burst: u32,           // packets one source IP may send back to back
     pub cover_traffic_ratio: f64,    // 0.1 = 10% cover traffic
     pub worker_threads: usize,       // Number of packet processing threads
+    pub sphinx: SphinxConfig,        // Mixing delays and packet parameters
+    pub epoch: EpochConfig,          // Genesis and length of network epochs
+    pub batched_io: bool,            // recvmmsg/sendmmsg on Linux, per-datagram I/O otherwise
+    pub cpu_steering: bool,          // Steer datagrams to receivers by CPU (Linux CBPF)
+    pub node_id: Option<MixNodeId>,  // Identity in the topology; None derives it from the VRF key
+    pub vrf_key_path: Option<std::path::PathBuf>, // Persist the VRF key here; None keeps it in memory
 }
 
 impl Default for MixnodeConfig {
@@ -49,135 +79,433 @@ impl Default for MixnodeConfig {
         Self {
             listen_address: "127.0.0.1:8080".to_string(),
             max_packet_rate: 30_000,
+            per_ip_packet_rate: 1000,
+            per_ip_burst: 100,
             cover_traffic_ratio: 0.1,
             worker_threads: num_cpus::get(),
+            sphinx: SphinxConfig::default(),
+            epoch: EpochConfig::default(),
+            batched_io: true,
+            cpu_steering: false,
+            node_id: None,
+            vrf_key_path: None,
         }
     }
 }
 
 struct PacketBatch {
-    packets: Vec<(SphinxPacket, std::net::SocketAddr)>,
+    packets: Vec<(PooledPacket, SocketAddr)>, // Emptied by dispatch and reused
 }
 
 impl PacketBatch {
     fn new() -> Self {
         Self {
-            packets: Vec::with_capacity(100), // Batch size for efficiency
+            packets: Vec::with_capacity(MMSG_BATCH_SIZE), // One receive call's worth
         }
     }
     
-    fn push(&mut self, packet: SphinxPacket, addr: std::net::SocketAddr) {
+    fn push(&mut self, packet: PooledPacket, addr: SocketAddr) {
         self.packets.push((packet, addr));
     }
     
-    fn is_full(&self) -> bool {
-        self.packets.len() >= 100
+    fn is_empty(&self) -> bool {
+        self.packets.is_empty()
     }
 }
 
-pub struct CoverTrafficGenerator {
-    _ratio: f64,
+/// Processed packet waiting in the delay queue, with the address of its next hop
+type OutboundPacket = (PooledObject<SphinxPacketBuffer>, SocketAddr);
+
+/// Sphinx key lifecycle driven by epoch transitions
+struct KeyRotation {
+    keys: Arc<tokio::sync::Mutex<SphinxKeySet>>,
+    store: Option<SphinxKeyStore>,
+    rotation_epochs: u64,
+    overlap: Duration,
+    mixer_pool: Arc<MixerPool<SocketAddr>>,
+    registry: Arc<tokio::sync::Mutex<MixNodeRegistry>>, // Our entry advertises the current key
+    node_id: MixNodeId,
+    audit: AuditLogger,
 }
 
-impl CoverTrafficGenerator {
-    pub fn new(ratio: f64) -> Self {
-        Self { _ratio: ratio }
+impl KeyRotation {
+    /// Rotate the Sphinx key if the current one has served its epochs
+    async fn on_epoch(self: &Arc<Self>, epoch: u64) {
+        let mut keys = self.keys.lock().await;
+        if !keys.rotation_due(epoch, self.rotation_epochs) {
+            return;
+        }
+        
+        keys.rotate(epoch);
+        self.mixer_pool.rotate_key(keys.current.private_key(), epoch).await;
+        
+        let mut details = HashMap::new();
+        details.insert("epoch".to_string(), epoch.to_string());
+        details.insert("public_key".to_string(), hex::encode(keys.current.public_key().compress().as_bytes()));
+        if let Some(previous) = &keys.previous {
+            details.insert("previous_epoch".to_string(), previous.epoch.to_string());
+        }
+        details.insert("overlap_secs".to_string(), self.overlap.as_secs().to_string());
+        let persisted = self.persist(&keys);
+        let public_key = keys.current.public_key();
+        drop(keys);
+        
+        // Senders must learn the new key while the old one still decrypts
+        if !self.registry.lock().await.set_public_key(&self.node_id, public_key) {
+            details.insert("published".to_string(), "false".to_string());
+        }
+        
+        self.log_key_event("sphinx_key_rotated", details, persisted).await;
+        self.schedule_retirement();
     }
     
-    pub async fn run(&mut self, _socket: Arc<UdpSocket>) {
-        // Placeholder for cover traffic generation
-        loop {
-            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    /// Retire the previous key when its overlap window ends
+    fn schedule_retirement(self: &Arc<Self>) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let remaining = {
+                let keys = this.keys.lock().await;
+                if keys.previous.is_none() {
+                    return;
+                }
+                (keys.current.activated_at + this.overlap)
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default()
+            };
+            tokio::time::sleep(remaining).await;
+            
+            let mut keys = this.keys.lock().await;
+            let Some(retired) = keys.retire_expired(SystemTime::now(), this.overlap) else {
+                return;
+            };
+            this.mixer_pool.retire_previous_key().await;
+            
+            let mut details = HashMap::new();
+            details.insert("epoch".to_string(), retired.epoch.to_string());
+            let persisted = this.persist(&keys);
+            drop(keys);
+            
+            this.log_key_event("sphinx_key_retired", details, persisted).await;
+        });
+    }
+    
+    fn persist(&self, keys: &SphinxKeySet) -> Result<(), KeyStoreError> {
+        match &self.store {
+            Some(store) => store.save(keys),
+            None => Ok(()),
         }
     }
+    
+    async fn log_key_event(
+        &self,
+        action: &str,
+        mut details: HashMap<String, String>,
+        persisted: Result<(), KeyStoreError>
+    ) {
+        let (result, risk_level) = match persisted {
+            Ok(()) => (AuditResult::Success, RiskLevel::Low),
+            Err(e) => {
+                // The new key works until restart, but paths built for it break afterwards
+                details.insert("persist_error".to_string(), e.to_string());
+                (AuditResult::Error, RiskLevel::High)
+            }
+        };
+        
+        self.audit.log_event(AuditEvent {
+            event_id: uuid::Uuid::new_v4().to_string(),
+            event_type: AuditEventType::Security,
+            timestamp: SystemTime::now(),
+            user_id: None,
+            session_id: None,
+            source_ip: None,
+            action: action.to_string(),
+            resource: Some("sphinx_key".to_string()),
+            result,
+            details,
+            risk_level,
+        }).await;
+    }
 }
 
-pub struct RateLimiter {
-    _config: RateLimitConfig,
-}
-
-#[derive(Debug, Clone)]
-pub struct RateLimitConfig {
-    pub packets_per_second_per_ip: u32,
-    pub global_packets_per_second: u32,
-    pub burst_size: u32,
-}
-
-impl RateLimiter {
-    pub fn new(config: RateLimitConfig) -> Self {
-        Self { _config: config }
+/// Load persisted Sphinx keys together with the replay caches saved for them
+///
+/// Keys without usable replay caches would accept replays of packets seen before the restart,
+/// so they are replaced by a fresh key, breaking paths built for the old ones.
+fn restore_sphinx_keys(
+    key_store: &SphinxKeyStore,
+    replay_store: &ReplayStore,
+    workers: usize,
+    epoch: u64
+) -> Result<(SphinxKeySet, Option<ReplayState>), KeyStoreError> {
+    if let Some(keys) = key_store.load()? {
+        match replay_store.load(workers, &MixerPool::<SocketAddr>::replay_config(workers)) {
+            Ok(Some(state)) => return Ok((keys, Some(state))),
+            Ok(None) => eprintln!("⚠️  No replay caches saved for the Sphinx keys; starting on a fresh key"),
+            Err(e) => eprintln!("⚠️  Replay caches unusable ({}); starting on a fresh key", e),
+        }
+    }
+    
+    // Caches left from older keys must not be restored with the new ones
+    match std::fs::remove_file(replay_store.path()) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {},
     }
+    let keys = SphinxKeySet::generate(epoch);
+    key_store.save(&keys)?;
+    Ok((keys, None))
 }
 
 impl HighPerformanceMixnode {
     pub fn new(config: MixnodeConfig) -> Result<Self, Box<dyn std::error::Error>> {
-        // Generate mixnode identity
-        let mut rng = OsRng;
-        let mut key_bytes = [0u8; 32];
-        rng.fill_bytes(&mut key_bytes);
-        let private_key = Scalar::from_bytes_mod_order(key_bytes);
-        let mixer = Arc::new(tokio::sync::Mutex::new(SphinxMixer::new(private_key)));
+        Self::with_peers(config, Arc::new(PeerRegistry::new(PeerRegistryConfig::default())))
+    }
+    
+    /// Create a node sharing `peers` with peer discovery
+    ///
+    /// Drop cover goes to the peers discovery has found, and reputation updates from lost
+    /// loops land where discovery and connection handling see them.
+    pub fn with_peers(config: MixnodeConfig, peers: Arc<PeerRegistry>) -> Result<Self, Box<dyn std::error::Error>> {
+        // The rate limiter counts in u32; a larger budget is a configuration error, not a wrap
+        let global_packets_per_second = u32::try_from(config.max_packet_rate).map_err(|_| {
+            format!("max_packet_rate {} exceeds the limit of {} packets per second", config.max_packet_rate, u32::MAX)
+        })?;
+        let epoch_manager = Arc::new(EpochManager::new(&config.epoch)?);
+        
+        // Reuse persisted Sphinx keys so a restart keeps in-flight paths working
+        let key_store = config.sphinx.key_store_path.clone().map(SphinxKeyStore::new);
+        let replay_store = key_store.as_ref()
+            .map(|store| ReplayStore::new(store.path().with_extension("replay")));
+        let (keys, replay_state) = match (&key_store, &replay_store) {
+            (Some(key_store), Some(replay_store)) => restore_sphinx_keys(
+                key_store,
+                replay_store,
+                config.worker_threads.max(1),
+                epoch_manager.current_epoch(),
+            )?,
+            _ => (SphinxKeySet::generate(epoch_manager.current_epoch()), None),
+        };
+        
+        // Pick the SIMD backend before any mixer captures the dispatch table
+        let simd_backend = init_simd(config.sphinx.enable_simd);
+        println!("⚡ SIMD backend: {:?}", simd_backend);
         
-        let vrf_selector = Arc::new(tokio::sync::Mutex::new(MixNodeRegistry::new()?));
+        // One mixer per worker, each holding its own copy of the key material
+        let (mixer_pool, mixer_workers) = match replay_state {
+            Some(state) => MixerPool::with_replay_state(&keys, state),
+            None => MixerPool::new(config.worker_threads, &keys),
+        };
+        let mixer_pool = Arc::new(mixer_pool);
+        
+        // Path proofs are checked against this key, so it must outlive restarts too
+        let vrf_selector = match &config.vrf_key_path {
+            Some(path) => MixNodeRegistry::with_vrf_key(VrfKeyStore::new(path).load_or_generate()?),
+            None => MixNodeRegistry::new()?,
+        };
+        // A persisted VRF key keeps a derived identity stable across restarts
+        let node_id = config.node_id.unwrap_or_else(|| derive_node_id(&vrf_selector.get_vrf_public_key()));
+        let vrf_selector = Arc::new(tokio::sync::Mutex::new(vrf_selector));
+        
+        let key_rotation = Arc::new(KeyRotation {
+            keys: Arc::new(tokio::sync::Mutex::new(keys)),
+            store: key_store,
+            rotation_epochs: config.sphinx.key_rotation_epochs,
+            overlap: config.sphinx.key_overlap,
+            mixer_pool: mixer_pool.clone(),
+            registry: vrf_selector.clone(),
+            node_id,
+            audit: AuditLogger::new(LoggingConfig::default()),
+        });
+        
+        let metrics = Arc::new(MetricsCollector::new(crate::metrics::MetricsConfig::default()));
+        metrics.record_epoch(epoch_manager.current_epoch());
         
         Ok(Self {
-            mixer,
+            node_id,
+            mixer_pool,
+            mixer_workers,
             vrf_selector,
             packet_counter: Arc::new(AtomicU64::new(0)),
-            cover_traffic: CoverTrafficGenerator::new(config.cover_traffic_ratio),
-            rate_limiter: RateLimiter::new(RateLimitConfig {
-                packets_per_second_per_ip: 1000,
-                global_packets_per_second: config.max_packet_rate as u32,
-                burst_size: 100,
-            }),
+            metrics,
+            epoch_manager,
+            key_rotation,
+            replay_store,
+            peers,
+            loop_tracker: Arc::new(std::sync::Mutex::new(LoopTracker::new(LoopTrackerConfig::default()))),
+            rate_limit: RateLimitConfig {
+                packets_per_second_per_ip: config.per_ip_packet_rate,
+                global_packets_per_second,
+                burst_size: config.per_ip_burst,
+                ..Default::default()
+            },
             config,
         })
     }
     
+    /// Registry used to resolve next hops; populate it before calling `run()`
+    pub fn registry(&self) -> Arc<tokio::sync::Mutex<MixNodeRegistry>> {
+        self.vrf_selector.clone()
+    }
+    
+    /// Neighbouring mixes; drop cover traffic is sent to the online, unblocked ones
+    pub fn peers(&self) -> Arc<PeerRegistry> {
+        self.peers.clone()
+    }
+    
+    /// Per-node and per-path loss of this node's loop cover packets
+    pub fn loop_tracker(&self) -> Arc<std::sync::Mutex<LoopTracker>> {
+        self.loop_tracker.clone()
+    }
+    
+    pub fn metrics(&self) -> Arc<MetricsCollector> {
+        self.metrics.clone()
+    }
+    
+    /// Identity to register this node under
+    pub fn node_id(&self) -> MixNodeId {
+        self.node_id
+    }
+    
+    pub async fn public_key(&self) -> curve25519_dalek::ristretto::RistrettoPoint {
+        self.key_rotation.keys.lock().await.current.public_key()
+    }
+    
+    /// VRF public key to publish so others can check this node's path proofs
+    pub async fn vrf_public_key(&self) -> VrfPublicKey {
+        self.vrf_selector.lock().await.get_vrf_public_key()
+    }
+    
+    pub fn epoch_manager(&self) -> Arc<EpochManager> {
+        self.epoch_manager.clone()
+    }
+    
     /// CRITICAL: Main performance target - ≥25k packets/second sustained
     pub async fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         println!("🚀 Starting High-Performance Mixnode");
         println!("📊 Target: ≥25,000 packets/second");
         
-        // Create high-throughput UDP socket with SO_REUSEPORT for multi-core scaling
-        let socket = self.create_optimized_socket().await?;
-        let socket = Arc::new(socket);
+        // One SO_REUSEPORT socket per receiver, so the kernel spreads flows across cores
+        let num_cores = num_cpus::get();
+        let sockets = self.create_optimized_sockets(num_cores).await?;
+        let batched_sockets: Vec<Arc<BatchedSocket>> = sockets.iter()
+            .map(|socket| Arc::new(BatchedSocket::new(socket.clone(), self.config.batched_io)))
+            .collect();
+        if batched_sockets[0].uses_mmsg() {
+            println!("📦 Batched UDP I/O: recvmmsg/sendmmsg, {} datagrams per syscall", MMSG_BATCH_SIZE);
+        }
+        
+        // Multi-threaded packet processing pipeline:
+        // receivers -> mixer workers -> router -> delay queue -> sender
+        let (processed_tx, processed_rx) = mpsc::channel::<ProcessedResult<SocketAddr>>(10_000);
+        let (delay_tx, delay_rx) = mpsc::channel::<(OutboundPacket, Duration)>(10_000);
+        let (outbound_tx, outbound_rx) = mpsc::channel::<OutboundPacket>(10_000);
         
-        // Multi-threaded packet processing pipeline
-        let (packet_tx, packet_rx) = mpsc::channel::<PacketBatch>(1000);
+        let delay_config = DelayQueueConfig::from_sphinx_config(&self.config.sphinx)?;
+        tokio::spawn(mixing::run_delay_queue(
+            delay_config,
+            delay_rx,
+            outbound_tx,
+            self.metrics.clone(),
+        ));
+        // Catch up on a rotation missed while the node was down, then follow epoch transitions
+        self.key_rotation.on_epoch(self.epoch_manager.current_epoch()).await;
+        self.key_rotation.schedule_retirement();
         
-        // Spawn packet receivers (one per CPU core)
-        let num_cores = num_cpus::get();
-        println!("💻 Using {} CPU cores for packet processing", num_cores);
+        // Packets seen before a restart stay rejected after it, up to the last save
+        if let Some(store) = self.replay_store.take() {
+            tokio::spawn(Self::replay_save_loop(
+                self.mixer_pool.clone(),
+                store,
+                self.config.sphinx.replay_save_interval,
+            ));
+        }
+        
+        let epoch_manager = self.epoch_manager.clone();
+        let epoch_rx = epoch_manager.subscribe();
+        tokio::spawn(async move { epoch_manager.run().await });
+        tokio::spawn(Self::epoch_transition_loop(
+            epoch_rx,
+            self.key_rotation.clone(),
+            self.vrf_selector.clone(),
+            self.metrics.clone(),
+        ));
         
-        for core_id in 0..num_cores {
-            let socket_clone = socket.clone();
-            let mixer_clone = self.mixer.clone();
-            let counter_clone = self.packet_counter.clone();
-            let packet_tx_clone = packet_tx.clone();
+        // Known mixes forward for many clients, so they skip the per-IP limit
+        let (exempt_tx, exempt_rx) = tokio::sync::watch::channel(Arc::new(HashSet::new()));
+        tokio::spawn(Self::exempt_peers_loop(exempt_tx, self.vrf_selector.clone(), self.peers.clone()));
+        
+        // io_uring rings replace the tokio receive and send loops when built in and supported
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        let outbound_rx = match Self::create_uring(&sockets) {
+            Some((receivers, sender)) => {
+                self.spawn_uring_loops(receivers, sender, outbound_rx, exempt_rx.clone());
+                None
+            },
+            None => Some(outbound_rx),
+        };
+        #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+        let outbound_rx = Some(outbound_rx);
+        
+        if let Some(outbound_rx) = outbound_rx {
+            // Every socket in the group is bound to the listen address, so any of them can send
+            tokio::spawn(Self::packet_sender_loop(
+                batched_sockets[0].clone(),
+                outbound_rx,
+                self.metrics.clone(),
+            ));
             
-            tokio::spawn(async move {
-                Self::packet_receiver_loop(
-                    core_id,
-                    socket_clone,
-                    mixer_clone,
-                    counter_clone,
-                    packet_tx_clone
-                ).await;
-            });
+            // Spawn packet receivers (one per CPU core, each on its own socket where supported)
+            println!("💻 Using {} CPU cores for packet processing", num_cores);
+            let receivers_per_socket = num_cores.div_ceil(batched_sockets.len());
+            for core_id in 0..num_cores {
+                let socket_clone = batched_sockets[core_id % batched_sockets.len()].clone();
+                let counter_clone = self.packet_counter.clone();
+                let metrics_clone = self.metrics.clone();
+                let pool_clone = self.mixer_pool.clone();
+                let limiter = self.rate_limiter_shard(num_cores, receivers_per_socket);
+                let exempt_clone = exempt_rx.clone();
+                
+                tokio::spawn(async move {
+                    Self::packet_receiver_loop(
+                        core_id,
+                        socket_clone,
+                        counter_clone,
+                        metrics_clone,
+                        pool_clone,
+                        limiter,
+                        exempt_clone
+                    ).await;
+                });
+            }
+        }
+        
+        // Spawn mixer workers (batch processing, sharded by ephemeral key)
+        println!("🧮 Running {} Sphinx mixer workers", self.mixer_pool.workers());
+        for worker in std::mem::take(&mut self.mixer_workers) {
+            tokio::spawn(worker.run(processed_tx.clone()));
         }
         
-        // Spawn packet processor (batch processing for efficiency)
         let vrf_clone = self.vrf_selector.clone();
+        let metrics_clone = self.metrics.clone();
+        let loop_tracker = self.loop_tracker.clone();
         tokio::spawn(async move {
-            Self::packet_processor_loop(packet_rx, vrf_clone).await;
+            Self::packet_processor_loop(processed_rx, vrf_clone, delay_tx, metrics_clone, loop_tracker).await;
         });
         
         // Spawn cover traffic generator
-        let cover_socket = socket.clone();
+        let cover_socket = sockets[0].clone();
+        let cover_context = CoverTrafficContext {
+            node_id: self.node_id,
+            packet_counter: self.packet_counter.clone(),
+            keys: self.key_rotation.keys.clone(),
+            registry: self.vrf_selector.clone(),
+            epoch_manager: self.epoch_manager.clone(),
+            metrics: self.metrics.clone(),
+            peers: Some(self.peers.clone()),
+            loops: self.loop_tracker.clone(),
+        };
         let mut cover_traffic = CoverTrafficGenerator::new(self.config.cover_traffic_ratio);
         tokio::spawn(async move {
-            cover_traffic.run(cover_socket).await;
+            cover_traffic.run(cover_socket, cover_context).await;
         });
         
         // Main metrics loop - CRITICAL for validating 25k pkt/s requirement
@@ -186,92 +514,354 @@ impl HighPerformanceMixnode {
         Ok(())
     }
     
+    /// Limiter owned by one of `receivers` receivers, `per_socket` of which read the same socket
+    ///
+    /// Receivers never share a limiter, so they never wait on each other. The global budget is
+    /// split across all receivers. A source's flow hashes to one socket, so the per-IP budget is
+    /// only split among the receivers reading that socket.
+    fn rate_limiter_shard(&self, receivers: usize, per_socket: usize) -> RateLimiter {
+        let split = |budget: u32, ways: usize| budget.div_ceil(ways as u32).max(1);
+        RateLimiter::new(RateLimitConfig {
+            packets_per_second_per_ip: split(self.rate_limit.packets_per_second_per_ip, per_socket),
+            global_packets_per_second: split(self.rate_limit.global_packets_per_second, receivers),
+            burst_size: split(self.rate_limit.burst_size, per_socket),
+            ..self.rate_limit.clone()
+        })
+    }
+    
     async fn packet_receiver_loop(
         core_id: usize,
-        socket: Arc<UdpSocket>,
-        _mixer: Arc<tokio::sync::Mutex<SphinxMixer>>,
+        socket: Arc<BatchedSocket>,
         counter: Arc<AtomicU64>,
-        packet_tx: mpsc::Sender<PacketBatch>
+        metrics: Arc<MetricsCollector>,
+        mixer_pool: Arc<MixerPool<SocketAddr>>,
+        mut rate_limiter: RateLimiter,
+        mut exempt_rx: tokio::sync::watch::Receiver<Arc<HashSet<IpAddr>>>
     ) {
-        let mut buffer = [0u8; SPHINX_PACKET_SIZE];
+        let mut buffers = RecvBuffers::new();
         let mut batch = PacketBatch::new();
         
         println!("🔄 Core {} ready for packet processing", core_id);
         
         loop {
-            match socket.recv_from(&mut buffer).await {
-                Ok((size, addr)) => {
-                    if size == SPHINX_PACKET_SIZE {
-                        // Parse sphinx packet (zero-copy)
-                        let packet = unsafe {
-                            std::ptr::read(buffer.as_ptr() as *const SphinxPacket)
-                        };
-                        
-                        batch.push(packet, addr);
-                        counter.fetch_add(1, Ordering::Relaxed);
-                        
-                        // Send batch when full (for efficient processing)
-                        if batch.is_full() {
-                            if packet_tx.try_send(batch).is_err() {
-                                // Channel full, drop batch (backpressure)
-                                println!("⚠️  Core {}: Packet processing overloaded, dropping batch", core_id);
-                            }
-                            batch = PacketBatch::new();
+            // One recvmmsg call, or recv_from plus a drain of already queued datagrams
+            if let Err(e) = socket.recv_batch(&mut buffers).await {
+                eprintln!("❌ Core {}: Socket receive error: {}", core_id, e);
+                continue;
+            }
+            
+            if exempt_rx.has_changed().unwrap_or(false) {
+                rate_limiter.set_exempt_ips(exempt_rx.borrow_and_update().clone());
+            }
+            for (datagram, addr) in buffers.drain() {
+                Self::push_datagram(&mut batch, datagram, addr, &mut rate_limiter, &counter, &metrics);
+            }
+            
+            if batch.is_empty() {
+                continue;
+            }
+            
+            Self::dispatch_batch(core_id, &mut batch, &mixer_pool, &metrics);
+        }
+    }
+    
+    fn dispatch_batch(
+        core_id: usize,
+        batch: &mut PacketBatch,
+        mixer_pool: &MixerPool<SocketAddr>,
+        metrics: &MetricsCollector
+    ) {
+        let dropped = mixer_pool.dispatch(&mut batch.packets);
+        if dropped > 0 {
+            // Worker queue full, drop its share (backpressure)
+            println!("⚠️  Core {}: Packet processing overloaded, dropping {} packets", core_id, dropped);
+            for _ in 0..dropped {
+                metrics.record_packet_dropped(DropReason::ResourceExhaustion);
+            }
+        }
+    }
+    
+    fn push_datagram(
+        batch: &mut PacketBatch,
+        datagram: PooledObject<SphinxPacketBuffer>,
+        addr: SocketAddr,
+        rate_limiter: &mut RateLimiter,
+        counter: &AtomicU64,
+        metrics: &MetricsCollector
+    ) {
+        // Refused before parsing, so floods cost as little as possible
+        match rate_limiter.check_rate_limit(addr.ip()) {
+            RateLimitResult::Allowed => {},
+            RateLimitResult::RateLimited(_) | RateLimitResult::Banned(_) => {
+                metrics.record_packet_dropped(DropReason::RateLimit);
+                return;
+            }
+        }
+        counter.fetch_add(1, Ordering::Relaxed);
+        
+        // Parsed in place; the receive buffer itself travels on to the mixer worker
+        match PooledPacket::from_buffer(datagram) {
+            Ok(packet) => batch.push(packet, addr),
+            Err(_) => metrics.record_packet_dropped(DropReason::InvalidFormat),
+        }
+    }
+    
+    /// Route packets processed by the mixer workers to their next hop
+    async fn packet_processor_loop(
+        mut processed_rx: mpsc::Receiver<ProcessedResult<SocketAddr>>,
+        vrf_selector: Arc<tokio::sync::Mutex<MixNodeRegistry>>,
+        delay_tx: mpsc::Sender<(OutboundPacket, Duration)>,
+        metrics: Arc<MetricsCollector>,
+        loop_tracker: Arc<std::sync::Mutex<LoopTracker>>
+    ) {
+        let mut batch = Vec::with_capacity(WORKER_BATCH_SIZE);
+        let mut outbound = Vec::with_capacity(WORKER_BATCH_SIZE);
+        
+        while processed_rx.recv_many(&mut batch, WORKER_BATCH_SIZE).await > 0 {
+            {
+                let registry = vrf_selector.lock().await;
+                
+                for (_source, result) in batch.drain(..) {
+                    let processed = match result {
+                        Ok(processed) => processed,
+                        Err(e) => {
+                            metrics.record_packet_dropped(DropReason::from(&e));
+                            continue;
                         }
+                    };
+                    
+                    match (processed.routing_info.command, processed.payload) {
+                        (RoutingCommand::Forward { next_hop }, ProcessedPayload::Forward(bytes)) => {
+                            let Some(node) = registry.get_node(&next_hop) else {
+                                metrics.record_packet_dropped(DropReason::RoutingError);
+                                continue;
+                            };
+                            
+                            metrics.record_packet_processed(processed.processing_time, SPHINX_PACKET_SIZE, PacketType::Forward);
+                            outbound.push(((bytes, node.address), processed.routing_info.delay));
+                        },
+                        (RoutingCommand::Deliver, ProcessedPayload::Final(message)) => {
+                            // Our own loop cover packets end here; client delivery is not wired up yet
+                            let returned = LoopCoverMessage::from_bytes(&message)
+                                .and_then(|cover| loop_tracker.lock().unwrap().record_returned(&cover.id, Instant::now()));
+                            if let Some(round_trip) = returned {
+                                metrics.record_loop_returned(round_trip);
+                            }
+                            metrics.record_packet_processed(processed.processing_time, SPHINX_PACKET_SIZE, PacketType::Deliver);
+                        },
+                        (RoutingCommand::Reply { .. }, ProcessedPayload::Reply(_message)) => {
+                            // This node is the exit hop, but nothing hands replies to their SURB creator yet
+                            metrics.record_packet_dropped(DropReason::NotImplemented);
+                        },
+                        _ => metrics.record_packet_dropped(DropReason::RoutingError),
                     }
-                },
-                Err(e) => {
-                    eprintln!("❌ Core {}: Socket receive error: {}", core_id, e);
+                }
+            }
+            
+            // The registry lock is released before waiting on the delay queue
+            for item in outbound.drain(..) {
+                if delay_tx.send(item).await.is_err() {
+                    return;
                 }
             }
         }
     }
     
-    async fn packet_processor_loop(
-        mut packet_rx: mpsc::Receiver<PacketBatch>,
-        _vrf_selector: Arc<tokio::sync::Mutex<MixNodeRegistry>>
+    async fn epoch_transition_loop(
+        mut epoch_rx: tokio::sync::watch::Receiver<u64>,
+        key_rotation: Arc<KeyRotation>,
+        vrf_selector: Arc<tokio::sync::Mutex<MixNodeRegistry>>,
+        metrics: Arc<MetricsCollector>
     ) {
-        while let Some(_batch) = packet_rx.recv().await {
-            // Process batch of packets
-            // For now, just acknowledge receipt
+        while epoch_rx.changed().await.is_ok() {
+            let epoch = *epoch_rx.borrow_and_update();
+            
+            key_rotation.on_epoch(epoch).await;
+            vrf_selector.lock().await.advance_epoch(epoch);
+            metrics.record_epoch(epoch);
+            
+            println!("🔄 Epoch {}: topology refreshed", epoch);
         }
     }
     
-    async fn create_optimized_socket(&self) -> Result<UdpSocket, Box<dyn std::error::Error>> {
-        let socket = UdpSocket::bind(&self.config.listen_address).await?;
+    /// Save the workers' replay caches next to the persisted keys every `period`
+    async fn replay_save_loop(
+        mixer_pool: Arc<MixerPool<SocketAddr>>,
+        store: ReplayStore,
+        period: Duration
+    ) {
+        let store = Arc::new(store);
+        let mut interval = tokio::time::interval(period);
         
-        println!("🔧 Optimizing socket for high performance...");
+        loop {
+            interval.tick().await;
+            
+            let Some(state) = mixer_pool.replay_state().await else {
+                return;
+            };
+            let store_clone = store.clone();
+            match tokio::task::spawn_blocking(move || store_clone.save(&state)).await {
+                Ok(Ok(())) => {},
+                Ok(Err(e)) => eprintln!("❌ Failed to save replay caches to {}: {}", store.path().display(), e),
+                Err(_) => return,
+            }
+        }
+    }
+    
+    /// Publish the addresses of registry nodes and discovered peers for the rate limiters
+    async fn exempt_peers_loop(
+        exempt_tx: tokio::sync::watch::Sender<Arc<HashSet<IpAddr>>>,
+        vrf_selector: Arc<tokio::sync::Mutex<MixNodeRegistry>>,
+        peers: Arc<PeerRegistry>
+    ) {
+        let mut interval = tokio::time::interval(Duration::from_secs(10));
         
-        // Set socket options for high performance
-        #[cfg(unix)]
-        {
-            use std::os::unix::io::AsRawFd;
-            let fd = socket.as_raw_fd();
+        loop {
+            interval.tick().await;
             
-            unsafe {
-                // SO_REUSEPORT for multi-core scaling
-                libc::setsockopt(
-                    fd,
-                    libc::SOL_SOCKET,
-                    libc::SO_REUSEPORT,
-                    &1i32 as *const i32 as *const libc::c_void,
-                    std::mem::size_of::<i32>() as libc::socklen_t
-                );
-                
-                // Increase receive buffer size
-                let buf_size = 1024 * 1024; // 1MB
-                libc::setsockopt(
-                    fd,
-                    libc::SOL_SOCKET, 
-                    libc::SO_RCVBUF,
-                    &buf_size as *const i32 as *const libc::c_void,
-                    std::mem::size_of::<i32>() as libc::socklen_t
-                );
+            let mut ips: HashSet<IpAddr> = vrf_selector.lock().await.snapshot().iter()
+                .map(|node| node.address.ip())
+                .collect();
+            ips.extend(peers.get_sphinx_peers().await.iter().map(|peer| peer.address.ip()));
+            
+            exempt_tx.send_if_modified(|current| {
+                if **current == ips {
+                    return false;
+                }
+                *current = Arc::new(ips);
+                true
+            });
+            if exempt_tx.is_closed() {
+                return;
             }
         }
+    }
+    
+    async fn packet_sender_loop(
+        socket: Arc<BatchedSocket>,
+        mut outbound_rx: mpsc::Receiver<OutboundPacket>,
+        metrics: Arc<MetricsCollector>
+    ) {
+        let mut pending: Vec<OutboundPacket> = Vec::with_capacity(MMSG_BATCH_SIZE);
         
-        println!("✅ Socket optimized for high throughput");
-        Ok(socket)
+        while outbound_rx.recv_many(&mut pending, MMSG_BATCH_SIZE).await > 0 {
+            let mut sent = 0;
+            while sent < pending.len() {
+                match socket.send_batch(&pending[sent..]).await {
+                    Ok(count) => sent += count,
+                    Err(e) => {
+                        // Skip the datagram the kernel rejected and carry on with the rest
+                        eprintln!("❌ Failed to forward packet to {}: {}", pending[sent].1, e);
+                        metrics.record_packet_dropped(DropReason::Error);
+                        sent += 1;
+                    }
+                }
+            }
+            
+            pending.clear();
+        }
+    }
+    
+    async fn create_optimized_sockets(&self, count: usize) -> Result<Vec<Arc<UdpSocket>>, Box<dyn std::error::Error>> {
+        println!("🔧 Optimizing sockets for high performance...");
+        
+        // SO_REUSEPORT and the receive buffer size are set before bind on every socket
+        let sockets = bind_reuseport(&self.config.listen_address, count, self.config.cpu_steering).await?;
+        
+        println!("✅ Bound {} socket(s) to {}{}", sockets.len(), self.config.listen_address,
+            if self.config.cpu_steering && sockets.len() > 1 { ", steered by CPU" } else { "" });
+        Ok(sockets.into_iter().map(Arc::new).collect())
+    }
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+impl HighPerformanceMixnode {
+    /// Set up one receive ring per socket and a sender ring, or None to fall back to tokio
+    fn create_uring(sockets: &[Arc<UdpSocket>]) -> Option<(Vec<UringReceiver>, UringSender)> {
+        let rings = net::uring::probe().and_then(|()| {
+            let receivers = sockets.iter()
+                .map(|socket| UringReceiver::new(socket.clone()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            Ok((receivers, UringSender::new(sockets[0].clone())?))
+        });
+        
+        match rings {
+            Ok(rings) => {
+                println!("💍 io_uring: {} receive ring(s), registered-buffer sender", rings.0.len());
+                Some(rings)
+            },
+            Err(e) => {
+                println!("⚠️  io_uring unavailable ({}), using tokio UDP loops", e);
+                None
+            }
+        }
+    }
+    
+    /// Run the rings on dedicated threads feeding the same batch pipeline as the tokio loops
+    fn spawn_uring_loops(
+        &self,
+        receivers: Vec<UringReceiver>,
+        mut sender: UringSender,
+        mut outbound_rx: mpsc::Receiver<OutboundPacket>,
+        exempt_rx: tokio::sync::watch::Receiver<Arc<HashSet<IpAddr>>>
+    ) {
+        let receiver_count = receivers.len();
+        for (core_id, mut receiver) in receivers.into_iter().enumerate() {
+            let counter = self.packet_counter.clone();
+            let metrics = self.metrics.clone();
+            let mixer_pool = self.mixer_pool.clone();
+            // Each ring owns its socket, so it alone sees a source's flow
+            let mut rate_limiter = self.rate_limiter_shard(receiver_count, 1);
+            let mut exempt_rx = exempt_rx.clone();
+            
+            std::thread::spawn(move || {
+                let mut batch = PacketBatch::new();
+                println!("🔄 Core {} ready for packet processing (io_uring)", core_id);
+                
+                loop {
+                    // recv_batch blocks until completions arrive, so the list is checked after
+                    // they are reaped rather than before the wait
+                    let received = receiver.recv_batch(|datagram, addr| {
+                        if exempt_rx.has_changed().unwrap_or(false) {
+                            rate_limiter.set_exempt_ips(exempt_rx.borrow_and_update().clone());
+                        }
+                        Self::push_datagram(&mut batch, datagram, addr, &mut rate_limiter, &counter, &metrics);
+                    });
+                    if let Err(e) = received {
+                        eprintln!("❌ Core {}: io_uring receive error: {}", core_id, e);
+                    }
+                    
+                    if !batch.is_empty() {
+                        Self::dispatch_batch(core_id, &mut batch, &mixer_pool, &metrics);
+                    }
+                }
+            });
+        }
+        
+        let metrics = self.metrics.clone();
+        std::thread::spawn(move || {
+            let mut pending: Vec<OutboundPacket> = Vec::with_capacity(MMSG_BATCH_SIZE);
+            
+            while let Some(packet) = outbound_rx.blocking_recv() {
+                pending.push(packet);
+                while pending.len() < MMSG_BATCH_SIZE {
+                    match outbound_rx.try_recv() {
+                        Ok(packet) => pending.push(packet),
+                        Err(_) => break,
+                    }
+                }
+                
+                let sent = sender.send_batch(&pending, |destination, e| {
+                    eprintln!("❌ Failed to forward packet to {}: {}", destination, e);
+                    metrics.record_packet_dropped(DropReason::Error);
+                });
+                if let Err(e) = sent {
+                    eprintln!("❌ io_uring send error: {}", e);
+                }
+                
+                pending.clear();
+            }
+        });
     }
 }
 
@@ -310,6 +900,8 @@ impl HighPerformanceMixnode {
                 eprintln!("🚨 Current: {} pkt/s | Required: ≥25,000 pkt/s", pps);
             }
             
+            self.metrics.record_buffer_pools(get_pool_stats());
+            
             // Emit structured metrics for monitoring
             self.emit_performance_metrics(pps).await;
         }
@@ -317,11 +909,15 @@ impl HighPerformanceMixnode {
     
     async fn emit_performance_metrics(&self, pps: u64) {
         // Emit metrics in structured format for external monitoring
+        let packet_pool = self.metrics.buffer_pools().packet;
         let metrics = serde_json::json!({
             "timestamp": chrono::Utc::now().to_rfc3339(),
             "packets_per_second": pps,
             "target_met": pps >= 25_000,
             "total_packets": self.packet_counter.load(Ordering::Relaxed),
+            "epoch": self.metrics.current_epoch(),
+            "packet_buffer_pool_hits": packet_pool.cache_hits,
+            "packet_buffer_pool_misses": packet_pool.cache_misses,
         });
         
         // In production, send to monitoring system
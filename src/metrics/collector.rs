@@ -4,6 +4,7 @@ use std::sync::{Arc, Mutex, atomic::{AtomicU64, AtomicU32, Ordering}};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 use crate::metrics::telemetry::{TelemetryCollector, TelemetryConfig};
+use crate::sphinx::{MixError, PoolStats};
 
 /// High-performance metrics collector for real-time monitoring
 pub struct MetricsCollector {
@@ -15,10 +16,18 @@ pub struct MetricsCollector {
     bytes_processed: AtomicU64,
     errors_total: AtomicU64,
     rate_limited: AtomicU64,
+    replayed: AtomicU64,
+    not_implemented: AtomicU64,
+
+    // Epoch the node is currently operating in
+    current_epoch: AtomicU64,
 
     // Performance metrics
     performance_metrics: Arc<RwLock<PerformanceMetrics>>,
     
+    // Processing time histogram, one counter per configured bucket and a last one for +Inf
+    processing_time_buckets: Box<[AtomicU64]>,
+    
     // VRF and routing metrics
     routing_metrics: Arc<Mutex<RoutingMetrics>>,
     
@@ -28,6 +37,15 @@ pub struct MetricsCollector {
     // Security metrics
     security_metrics: Arc<Mutex<SecurityMetrics>>,
     
+    // Mixing delay queue metrics
+    mixing_metrics: Arc<Mutex<MixingMetrics>>,
+    
+    // Packet buffer pool usage
+    buffer_pool_metrics: Arc<Mutex<BufferPoolMetrics>>,
+    
+    // Loop cover traffic
+    cover_traffic_metrics: Arc<Mutex<CoverTrafficMetrics>>,
+    
     // Telemetry integration
     telemetry: Option<Arc<TelemetryCollector>>,
     
@@ -108,6 +126,32 @@ pub struct SecurityMetrics {
     pub intrusion_attempts: u64,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct MixingMetrics {
+    pub delay_queue_depth: usize,
+    pub peak_delay_queue_depth: usize,
+    pub packets_released: u64,
+    pub avg_release_jitter_us: f64,
+    pub max_release_jitter_us: u64,
+}
+
+/// Hit/miss counters of the shared buffer pools (see `sphinx::get_pool_stats`)
+#[derive(Debug, Clone, Default)]
+pub struct BufferPoolMetrics {
+    pub packet: PoolStats,
+    pub header: PoolStats,
+    pub payload: PoolStats,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CoverTrafficMetrics {
+    pub loops_sent: u64,
+    pub loops_returned: u64,
+    pub avg_loop_rtt_ms: f64,
+    pub loops_lost: u64,
+    pub drops_sent: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct CurrentMetrics {
     pub packets_per_second: f64,
@@ -117,6 +161,7 @@ pub struct CurrentMetrics {
     pub uptime_seconds: u64,
     pub memory_usage_mb: f64,
     pub cpu_usage_percent: f64,
+    pub epoch: u64,
 }
 
 /// Comprehensive metrics snapshot
@@ -124,6 +169,7 @@ pub struct CurrentMetrics {
 pub struct MetricsSnapshot {
     pub timestamp: SystemTime,
     pub uptime_seconds: u64,
+    pub epoch: u64,
     
     // Basic counters
     pub packets_processed: u64,
@@ -133,12 +179,17 @@ pub struct MetricsSnapshot {
     pub bytes_processed: u64,
     pub errors_total: u64,
     pub rate_limited: u64,
+    pub replayed: u64,
+    pub not_implemented: u64,
     
     // Detailed metrics
     pub performance: PerformanceMetrics,
     pub routing: RoutingMetrics,
     pub network: NetworkMetrics,
     pub security: SecurityMetrics,
+    pub mixing: MixingMetrics,
+    pub buffer_pools: BufferPoolMetrics,
+    pub cover_traffic: CoverTrafficMetrics,
     
     // Derived metrics
     pub success_rate: f64,
@@ -163,10 +214,17 @@ impl MetricsCollector {
             bytes_processed: AtomicU64::new(0),
             errors_total: AtomicU64::new(0),
             rate_limited: AtomicU64::new(0),
+            replayed: AtomicU64::new(0),
+            not_implemented: AtomicU64::new(0),
+            current_epoch: AtomicU64::new(0),
             performance_metrics: Arc::new(RwLock::new(PerformanceMetrics::default())),
+            processing_time_buckets: (0..=config.histogram_buckets.len()).map(|_| AtomicU64::new(0)).collect(),
             routing_metrics: Arc::new(Mutex::new(RoutingMetrics::default())),
             network_metrics: Arc::new(Mutex::new(NetworkMetrics::default())),
             security_metrics: Arc::new(Mutex::new(SecurityMetrics::default())),
+            mixing_metrics: Arc::new(Mutex::new(MixingMetrics::default())),
+            buffer_pool_metrics: Arc::new(Mutex::new(BufferPoolMetrics::default())),
+            cover_traffic_metrics: Arc::new(Mutex::new(CoverTrafficMetrics::default())),
             telemetry,
             config,
             start_time: SystemTime::now(),
@@ -188,22 +246,9 @@ impl MetricsCollector {
             telemetry.record_packet_processed(processing_time);
         }
 
-        // Update performance metrics
-        tokio::spawn({
-            let perf_metrics = self.performance_metrics.clone();
-            let bucket_key = self.get_histogram_bucket(processing_time.as_secs_f64());
-            async move {
-                let mut metrics = perf_metrics.write().await;
-                *metrics.processing_time_histogram.entry(bucket_key).or_insert(0) += 1;
-                
-                // Keep throughput history bounded
-                let now = SystemTime::now();
-                metrics.throughput_history.push((now, 0.0)); // Will be calculated separately
-                if metrics.throughput_history.len() > 1000 {
-                    metrics.throughput_history.drain(0..500);
-                }
-            }
-        });
+        // Lock-free, so recording a packet never allocates or waits
+        let bucket = self.get_histogram_bucket(processing_time.as_secs_f64());
+        self.processing_time_buckets[bucket].fetch_add(1, Ordering::Relaxed);
     }
 
     /// Record packet drop
@@ -213,6 +258,13 @@ impl MetricsCollector {
         match reason {
             DropReason::RateLimit => { self.rate_limited.fetch_add(1, Ordering::Relaxed); },
             DropReason::Error => { self.errors_total.fetch_add(1, Ordering::Relaxed); },
+            DropReason::Replay => {
+                self.replayed.fetch_add(1, Ordering::Relaxed);
+                if let Ok(mut security) = self.security_metrics.lock() {
+                    security.attack_attempts += 1;
+                }
+            },
+            DropReason::NotImplemented => { self.not_implemented.fetch_add(1, Ordering::Relaxed); },
             _ => {}
         };
     }
@@ -226,6 +278,78 @@ impl MetricsCollector {
         }
     }
 
+    /// Record current delay queue depth
+    pub fn record_delay_queue_depth(&self, depth: usize) {
+        if let Ok(mut mixing) = self.mixing_metrics.lock() {
+            mixing.delay_queue_depth = depth;
+            mixing.peak_delay_queue_depth = mixing.peak_delay_queue_depth.max(depth);
+        }
+    }
+
+    /// Record how late a delayed packet was released
+    pub fn record_release_jitter(&self, jitter: Duration) {
+        if let Ok(mut mixing) = self.mixing_metrics.lock() {
+            let jitter_us = jitter.as_micros() as u64;
+            mixing.packets_released += 1;
+            mixing.avg_release_jitter_us +=
+                (jitter_us as f64 - mixing.avg_release_jitter_us) / mixing.packets_released as f64;
+            mixing.max_release_jitter_us = mixing.max_release_jitter_us.max(jitter_us);
+        }
+    }
+
+    /// Record buffer pool statistics as returned by `get_pool_stats`
+    pub fn record_buffer_pools(&self, (packet, header, payload): (PoolStats, PoolStats, PoolStats)) {
+        if let Ok(mut pools) = self.buffer_pool_metrics.lock() {
+            *pools = BufferPoolMetrics { packet, header, payload };
+        }
+    }
+
+    pub fn buffer_pools(&self) -> BufferPoolMetrics {
+        self.buffer_pool_metrics.lock().unwrap().clone()
+    }
+
+    /// Record a loop cover packet handed to its first hop
+    pub fn record_loop_sent(&self) {
+        if let Ok(mut cover) = self.cover_traffic_metrics.lock() {
+            cover.loops_sent += 1;
+        }
+    }
+
+    /// Record loop cover packets that timed out without returning
+    pub fn record_loops_lost(&self, count: u64) {
+        if let Ok(mut cover) = self.cover_traffic_metrics.lock() {
+            cover.loops_lost += count;
+        }
+    }
+
+    pub fn record_drop_sent(&self) {
+        if let Ok(mut cover) = self.cover_traffic_metrics.lock() {
+            cover.drops_sent += 1;
+        }
+    }
+
+    /// Record a loop cover packet that came back after `round_trip`
+    pub fn record_loop_returned(&self, round_trip: Duration) {
+        if let Ok(mut cover) = self.cover_traffic_metrics.lock() {
+            cover.loops_returned += 1;
+            cover.avg_loop_rtt_ms +=
+                (round_trip.as_secs_f64() * 1000.0 - cover.avg_loop_rtt_ms) / cover.loops_returned as f64;
+        }
+    }
+
+    pub fn cover_traffic(&self) -> CoverTrafficMetrics {
+        self.cover_traffic_metrics.lock().unwrap().clone()
+    }
+
+    /// Record an epoch transition
+    pub fn record_epoch(&self, epoch: u64) {
+        self.current_epoch.store(epoch, Ordering::Relaxed);
+    }
+
+    pub fn current_epoch(&self) -> u64 {
+        self.current_epoch.load(Ordering::Relaxed)
+    }
+
     /// Record network connection
     pub fn record_connection(&self, peer_id: &str, bytes_sent: u64, bytes_received: u64) {
         if let Ok(mut network) = self.network_metrics.lock() {
@@ -281,10 +405,11 @@ impl MetricsCollector {
         let mut perf_metrics = self.performance_metrics.write().await;
         perf_metrics.packets_per_second = packets_per_second;
         
-        // Update throughput history
+        // Update throughput history, keeping it bounded
         let now = SystemTime::now();
-        if let Some(last_entry) = perf_metrics.throughput_history.last_mut() {
-            last_entry.1 = packets_per_second;
+        perf_metrics.throughput_history.push((now, packets_per_second));
+        if perf_metrics.throughput_history.len() > 1000 {
+            perf_metrics.throughput_history.drain(0..500);
         }
 
         // Update telemetry
@@ -319,7 +444,7 @@ impl MetricsCollector {
         let errors_total = self.errors_total.load(Ordering::Relaxed);
 
         let success_rate = if packets_processed > 0 {
-            (packets_processed - packets_dropped) as f64 / packets_processed as f64
+            packets_processed.saturating_sub(packets_dropped) as f64 / packets_processed as f64
         } else {
             1.0
         };
@@ -336,6 +461,7 @@ impl MetricsCollector {
         MetricsSnapshot {
             timestamp: SystemTime::now(),
             uptime_seconds: uptime,
+            epoch: self.current_epoch(),
             packets_processed,
             packets_forwarded,
             packets_delivered,
@@ -343,10 +469,18 @@ impl MetricsCollector {
             bytes_processed: self.bytes_processed.load(Ordering::Relaxed),
             errors_total,
             rate_limited: self.rate_limited.load(Ordering::Relaxed),
-            performance: self.performance_metrics.read().await.clone(),
+            replayed: self.replayed.load(Ordering::Relaxed),
+            not_implemented: self.not_implemented.load(Ordering::Relaxed),
+            performance: PerformanceMetrics {
+                processing_time_histogram: self.processing_time_histogram(),
+                ..self.performance_metrics.read().await.clone()
+            },
             routing: self.routing_metrics.lock().unwrap().clone(),
             network: self.network_metrics.lock().unwrap().clone(),
             security: self.security_metrics.lock().unwrap().clone(),
+            mixing: self.mixing_metrics.lock().unwrap().clone(),
+            buffer_pools: self.buffer_pools(),
+            cover_traffic: self.cover_traffic(),
             success_rate,
             error_rate,
             efficiency_score,
@@ -362,21 +496,39 @@ impl MetricsCollector {
         self.bytes_processed.store(0, Ordering::Relaxed);
         self.errors_total.store(0, Ordering::Relaxed);
         self.rate_limited.store(0, Ordering::Relaxed);
+        self.replayed.store(0, Ordering::Relaxed);
+        self.not_implemented.store(0, Ordering::Relaxed);
+        for bucket in self.processing_time_buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
 
         *self.performance_metrics.write().await = PerformanceMetrics::default();
         *self.routing_metrics.lock().unwrap() = RoutingMetrics::default();
         *self.network_metrics.lock().unwrap() = NetworkMetrics::default();
         *self.security_metrics.lock().unwrap() = SecurityMetrics::default();
+        *self.mixing_metrics.lock().unwrap() = MixingMetrics::default();
+        *self.buffer_pool_metrics.lock().unwrap() = BufferPoolMetrics::default();
+        *self.cover_traffic_metrics.lock().unwrap() = CoverTrafficMetrics::default();
     }
 
-    /// Get histogram bucket for processing time
-    fn get_histogram_bucket(&self, duration_secs: f64) -> String {
-        for &bucket in &self.config.histogram_buckets {
-            if duration_secs <= bucket {
-                return format!("le_{}", bucket);
-            }
-        }
-        "le_inf".to_string()
+    /// Get histogram bucket for processing time; the last bucket is +Inf
+    fn get_histogram_bucket(&self, duration_secs: f64) -> usize {
+        self.config.histogram_buckets.iter()
+            .position(|&bucket| duration_secs <= bucket)
+            .unwrap_or(self.config.histogram_buckets.len())
+    }
+
+    /// Non-empty histogram buckets keyed by upper bound
+    fn processing_time_histogram(&self) -> HashMap<String, u64> {
+        self.processing_time_buckets.iter()
+            .enumerate()
+            .map(|(i, count)| (i, count.load(Ordering::Relaxed)))
+            .filter(|&(_, count)| count > 0)
+            .map(|(i, count)| match self.config.histogram_buckets.get(i) {
+                Some(bucket) => (format!("le_{}", bucket), count),
+                None => ("le_inf".to_string(), count),
+            })
+            .collect()
     }
 
     /// Start periodic metrics export
@@ -436,6 +588,7 @@ impl MetricsCollector {
             uptime_seconds: uptime.as_secs(),
             memory_usage_mb: memory_usage as f64 / (1024.0 * 1024.0),
             cpu_usage_percent: cpu_usage,
+            epoch: self.current_epoch(),
         }
     }
     
@@ -482,6 +635,19 @@ pub enum DropReason {
     InvalidFormat,
     RoutingError,
     ResourceExhaustion,
+    Replay,
+    NotImplemented, // Valid packet this node has no handling for yet
+}
+
+impl From<&MixError> for DropReason {
+    fn from(error: &MixError) -> Self {
+        match error {
+            MixError::Replay => DropReason::Replay,
+            MixError::RoutingError => DropReason::RoutingError,
+            MixError::InvalidPacket | MixError::Sphinx(_) => DropReason::InvalidFormat,
+            MixError::DecryptionFailed => DropReason::Error,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -541,6 +707,7 @@ mod tests {
         assert_eq!(snapshot.packets_delivered, 1);
         assert_eq!(snapshot.packets_dropped, 1);
         assert_eq!(snapshot.rate_limited, 1);
+        assert_eq!(snapshot.replayed, 0);
         assert_eq!(snapshot.performance.cpu_usage, 0.75);
         assert_eq!(snapshot.performance.packets_per_second, 25000.0);
         
@@ -0,0 +1,342 @@
+// Mixing delay scheduler
+// Holds processed packets until their per-hop delay elapses so output order is decoupled from input order
+
+use std::cmp::{Ordering as CmpOrdering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::time::Duration;
+use rand::Rng;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::config::SphinxConfig;
+use crate::metrics::MetricsCollector;
+
+/// Delay distribution used when a packet does not request its own delay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelayDistribution {
+    /// Uniform between zero and twice the mean
+    Uniform,
+    /// Exponential with the configured mean
+    Exponential,
+    /// Exponential per-packet delays, so releases from the mix form a Poisson process
+    Poisson,
+}
+
+impl DelayDistribution {
+    /// Parse the `SphinxConfig::delay_distribution` setting
+    pub fn from_config_str(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "uniform" => Ok(Self::Uniform),
+            "exponential" => Ok(Self::Exponential),
+            "poisson" => Ok(Self::Poisson),
+            other => Err(format!("Unknown delay distribution: {}", other)),
+        }
+    }
+
+    /// Draw a delay with the given mean
+    pub fn sample<R: Rng + ?Sized>(&self, mean: Duration, rng: &mut R) -> Duration {
+        let mean_secs = mean.as_secs_f64();
+        if mean_secs <= 0.0 {
+            return Duration::ZERO;
+        }
+
+        let secs = match self {
+            Self::Uniform => rng.gen_range(0.0..=2.0 * mean_secs),
+            Self::Exponential | Self::Poisson => {
+                // Inverse transform sampling; 1 - U avoids ln(0)
+                let u: f64 = rng.gen();
+                -(1.0 - u).ln() * mean_secs
+            }
+        };
+
+        Duration::from_secs_f64(secs)
+    }
+}
+
+/// Delay queue configuration
+#[derive(Debug, Clone)]
+pub struct DelayQueueConfig {
+    pub max_delay: Duration,
+    pub mean_delay: Duration,
+    pub distribution: DelayDistribution,
+    pub max_queue_size: usize,
+    /// Round release times up to multiples of this interval, so departure times carry no
+    /// trace of how long a packet took to process
+    pub release_quantum: Option<Duration>,
+}
+
+impl Default for DelayQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_delay: Duration::from_secs(10),
+            mean_delay: Duration::from_millis(50),
+            distribution: DelayDistribution::Exponential,
+            max_queue_size: 100_000,
+            release_quantum: None,
+        }
+    }
+}
+
+impl DelayQueueConfig {
+    pub fn from_sphinx_config(config: &SphinxConfig) -> Result<Self, String> {
+        Ok(Self {
+            max_delay: config.max_delay,
+            mean_delay: config.mean_delay,
+            distribution: DelayDistribution::from_config_str(&config.delay_distribution)?,
+            release_quantum: config.release_quantum,
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DelayQueueStats {
+    pub scheduled: u64,
+    pub released: u64,
+    pub clamped: u64,
+    pub sampled: u64,
+    pub rejected: u64,
+}
+
+/// Item released from the queue along with how late it was
+#[derive(Debug)]
+pub struct Released<T> {
+    pub item: T,
+    pub jitter: Duration,
+}
+
+struct Scheduled<T> {
+    release_at: Instant,
+    sequence: u64,
+    item: T,
+}
+
+impl<T> PartialEq for Scheduled<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.release_at == other.release_at && self.sequence == other.sequence
+    }
+}
+
+impl<T> Eq for Scheduled<T> {}
+
+impl<T> PartialOrd for Scheduled<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Scheduled<T> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.release_at
+            .cmp(&other.release_at)
+            .then(self.sequence.cmp(&other.sequence))
+    }
+}
+
+/// Min-heap of packets ordered by release time
+pub struct DelayQueue<T> {
+    heap: BinaryHeap<Reverse<Scheduled<T>>>,
+    config: DelayQueueConfig,
+    // Reference point for release quantization
+    created_at: Instant,
+    next_sequence: u64,
+    stats: DelayQueueStats,
+}
+
+impl<T> DelayQueue<T> {
+    pub fn new(config: DelayQueueConfig) -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            config,
+            created_at: Instant::now(),
+            next_sequence: 0,
+            stats: DelayQueueStats::default(),
+        }
+    }
+
+    /// Resolve the delay actually applied to a packet
+    ///
+    /// Header delays are honoured up to `max_delay`; packets without one get a delay drawn
+    /// from the configured distribution so every packet is mixed.
+    pub fn effective_delay(&mut self, requested: Duration) -> Duration {
+        let delay = if requested.is_zero() {
+            self.stats.sampled += 1;
+            self.config.distribution.sample(self.config.mean_delay, &mut rand::thread_rng())
+        } else {
+            requested
+        };
+
+        if delay > self.config.max_delay {
+            self.stats.clamped += 1;
+            self.config.max_delay
+        } else {
+            delay
+        }
+    }
+
+    /// Schedule an item relative to `now`, returning its release time
+    ///
+    /// Items are handed back if the queue is full so the caller can account for the drop.
+    pub fn schedule(&mut self, item: T, requested: Duration, now: Instant) -> Result<Instant, T> {
+        if self.heap.len() >= self.config.max_queue_size {
+            self.stats.rejected += 1;
+            return Err(item);
+        }
+
+        let delay = self.effective_delay(requested);
+        let release_at = self.quantize(now + delay);
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        self.heap.push(Reverse(Scheduled { release_at, sequence, item }));
+        self.stats.scheduled += 1;
+        Ok(release_at)
+    }
+
+    /// Round a release time up to the next quantum boundary
+    fn quantize(&self, release_at: Instant) -> Instant {
+        let Some(quantum) = self.config.release_quantum.filter(|q| !q.is_zero()) else {
+            return release_at;
+        };
+
+        let offset = release_at.saturating_duration_since(self.created_at).as_nanos();
+        let quantum = quantum.as_nanos();
+        let rounded = offset.div_ceil(quantum) * quantum;
+        self.created_at + Duration::from_nanos(rounded as u64)
+    }
+
+    /// Pop the next item whose release time has passed
+    pub fn pop_ready(&mut self, now: Instant) -> Option<Released<T>> {
+        match self.heap.peek() {
+            Some(Reverse(next)) if next.release_at <= now => {}
+            _ => return None,
+        }
+
+        let Reverse(scheduled) = self.heap.pop()?;
+        self.stats.released += 1;
+        Some(Released {
+            item: scheduled.item,
+            jitter: now.saturating_duration_since(scheduled.release_at),
+        })
+    }
+
+    pub fn next_release(&self) -> Option<Instant> {
+        self.heap.peek().map(|Reverse(next)| next.release_at)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn stats(&self) -> DelayQueueStats {
+        self.stats.clone()
+    }
+}
+
+/// Drive a delay queue between the packet processor and the sender
+///
+/// Items arrive with their requested delay and are passed to `output` when released.
+/// Queue depth and release jitter are reported to `metrics`.
+pub async fn run_delay_queue<T: Send + 'static>(
+    config: DelayQueueConfig,
+    mut input: mpsc::Receiver<(T, Duration)>,
+    output: mpsc::Sender<T>,
+    metrics: Arc<MetricsCollector>,
+) {
+    let mut queue = DelayQueue::new(config);
+    let mut input_open = true;
+
+    loop {
+        let next_release = queue.next_release();
+
+        tokio::select! {
+            received = input.recv(), if input_open => {
+                match received {
+                    Some((item, delay)) => {
+                        if queue.schedule(item, delay, Instant::now()).is_err() {
+                            metrics.record_packet_dropped(crate::metrics::DropReason::ResourceExhaustion);
+                        }
+                        metrics.record_delay_queue_depth(queue.len());
+                    }
+                    None => input_open = false,
+                }
+            }
+            _ = tokio::time::sleep_until(next_release.unwrap_or_else(Instant::now)), if next_release.is_some() => {
+                let now = Instant::now();
+                while let Some(released) = queue.pop_ready(now) {
+                    metrics.record_release_jitter(released.jitter);
+                    if output.send(released.item).await.is_err() {
+                        return;
+                    }
+                }
+                metrics.record_delay_queue_depth(queue.len());
+            }
+            else => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_queue_releases_in_delay_order() {
+        let mut queue = DelayQueue::new(DelayQueueConfig {
+            max_delay: Duration::from_millis(500),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        queue.schedule("slow", Duration::from_millis(300), now).unwrap();
+        queue.schedule("fast", Duration::from_millis(100), now).unwrap();
+        queue.schedule("clamped", Duration::from_secs(60), now).unwrap();
+
+        assert!(queue.pop_ready(now).is_none());
+
+        let released = queue.pop_ready(now + Duration::from_millis(150)).unwrap();
+        assert_eq!(released.item, "fast");
+        assert_eq!(released.jitter, Duration::from_millis(50));
+
+        assert_eq!(queue.pop_ready(now + Duration::from_millis(300)).unwrap().item, "slow");
+        assert!(queue.pop_ready(now + Duration::from_millis(499)).is_none());
+        assert_eq!(queue.pop_ready(now + Duration::from_millis(500)).unwrap().item, "clamped");
+        assert_eq!(queue.stats().clamped, 1);
+    }
+
+    #[test]
+    fn test_release_quantization() {
+        let mut queue = DelayQueue::new(DelayQueueConfig {
+            release_quantum: Some(Duration::from_millis(10)),
+            ..Default::default()
+        });
+        let now = queue.created_at;
+
+        // Different processing times within a quantum leave at the same instant
+        let first = queue.schedule("a", Duration::from_millis(21), now).unwrap();
+        let second = queue.schedule("b", Duration::from_millis(3), now + Duration::from_micros(26_500)).unwrap();
+        assert_eq!(first, now + Duration::from_millis(30));
+        assert_eq!(second, first);
+        assert!(queue.pop_ready(now + Duration::from_millis(29)).is_none());
+    }
+
+    #[test]
+    fn test_exponential_delay_sample_mean() {
+        let mut rng = rand::thread_rng();
+        let mean = Duration::from_millis(50);
+        let samples = 20_000;
+
+        let total: f64 = (0..samples)
+            .map(|_| DelayDistribution::Exponential.sample(mean, &mut rng).as_secs_f64())
+            .sum();
+        let observed = total / samples as f64;
+
+        assert!((observed - 0.05).abs() < 0.005, "observed mean {}", observed);
+        assert!(DelayDistribution::from_config_str("gaussian").is_err());
+    }
+}
@@ -0,0 +1,326 @@
+// Batched UDP I/O
+// On Linux, recvmmsg/sendmmsg move up to MMSG_BATCH_SIZE datagrams per syscall. Elsewhere, or
+// when the kernel rejects them, the socket falls back to one recv_from/send_to per datagram.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tracing::warn;
+
+use crate::sphinx::{get_packet_buffer, PooledObject, SphinxPacketBuffer, SPHINX_PACKET_SIZE};
+
+/// Datagrams moved per recvmmsg/sendmmsg call
+pub const MMSG_BATCH_SIZE: usize = 64;
+
+/// Receive buffers reused across calls
+///
+/// Datagrams are received straight into pooled packet buffers, which `drain` hands on
+/// without copying.
+pub struct RecvBuffers {
+    buffers: Vec<PooledObject<SphinxPacketBuffer>>,
+    received: [(usize, SocketAddr); MMSG_BATCH_SIZE],
+    len: usize,
+}
+
+impl RecvBuffers {
+    pub fn new() -> Self {
+        Self {
+            buffers: (0..MMSG_BATCH_SIZE).map(|_| get_packet_buffer()).collect(),
+            received: [(0, SocketAddr::from(([0, 0, 0, 0], 0))); MMSG_BATCH_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Datagrams from the last receive call
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], SocketAddr)> {
+        self.received[..self.len]
+            .iter()
+            .zip(self.buffers.iter())
+            .map(|(&(size, addr), buffer)| (&buffer[..size], addr))
+    }
+
+    /// Take the datagrams from the last receive call, each in its buffer cut to its length
+    ///
+    /// Every buffer taken is replaced with a fresh one from the calling thread's pool.
+    pub fn drain(&mut self) -> impl Iterator<Item = (PooledObject<SphinxPacketBuffer>, SocketAddr)> + '_ {
+        let len = std::mem::take(&mut self.len);
+        self.received[..len]
+            .iter()
+            .zip(self.buffers.iter_mut())
+            .map(|(&(size, addr), buffer)| {
+                let mut datagram = std::mem::replace(buffer, get_packet_buffer());
+                datagram.truncate(size);
+                (datagram, addr)
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for RecvBuffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// UDP socket that batches datagrams per syscall when the platform allows it
+pub struct BatchedSocket {
+    socket: Arc<UdpSocket>,
+    mmsg: AtomicBool,
+}
+
+impl BatchedSocket {
+    /// Wrap `socket`; `enable_mmsg = false` forces the per-datagram path
+    pub fn new(socket: Arc<UdpSocket>, enable_mmsg: bool) -> Self {
+        Self {
+            socket,
+            mmsg: AtomicBool::new(enable_mmsg && cfg!(target_os = "linux")),
+        }
+    }
+
+    pub fn socket(&self) -> &Arc<UdpSocket> {
+        &self.socket
+    }
+
+    /// Whether recvmmsg/sendmmsg are in use
+    pub fn uses_mmsg(&self) -> bool {
+        self.mmsg.load(Ordering::Relaxed)
+    }
+
+    /// Wait for datagrams and receive as many as are queued, up to `MMSG_BATCH_SIZE`
+    pub async fn recv_batch(&self, buffers: &mut RecvBuffers) -> io::Result<usize> {
+        buffers.len = 0;
+
+        #[cfg(target_os = "linux")]
+        if self.uses_mmsg() {
+            let result = self.socket
+                .async_io(tokio::io::Interest::READABLE, || mmsg::recv(&self.socket, buffers))
+                .await;
+            match result {
+                Err(e) if mmsg::unsupported(&e) => self.disable_mmsg(&e),
+                result => return result,
+            }
+        }
+
+        let (size, addr) = self.socket.recv_from(with_spare_byte(&mut buffers.buffers[0])).await?;
+        buffers.received[0] = (emptied_if_oversized(size), addr);
+        buffers.len = 1;
+
+        // Drain datagrams already queued on the socket into the same batch
+        while buffers.len < MMSG_BATCH_SIZE {
+            match self.socket.try_recv_from(with_spare_byte(&mut buffers.buffers[buffers.len])) {
+                Ok((size, addr)) => {
+                    buffers.received[buffers.len] = (emptied_if_oversized(size), addr);
+                    buffers.len += 1;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(buffers.len)
+    }
+
+    /// Send datagrams in order, returning how many from the front were sent
+    ///
+    /// Fewer than `packets.len()` may be sent; an error refers to the first unsent datagram.
+    pub async fn send_batch<D: AsRef<[u8]>>(&self, packets: &[(D, SocketAddr)]) -> io::Result<usize> {
+        if packets.is_empty() {
+            return Ok(0);
+        }
+
+        #[cfg(target_os = "linux")]
+        if self.uses_mmsg() {
+            let result = self.socket
+                .async_io(tokio::io::Interest::WRITABLE, || mmsg::send(&self.socket, packets))
+                .await;
+            match result {
+                Err(e) if mmsg::unsupported(&e) => self.disable_mmsg(&e),
+                result => return result,
+            }
+        }
+
+        let (datagram, destination) = &packets[0];
+        self.socket.send_to(datagram.as_ref(), *destination).await?;
+        Ok(1)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn disable_mmsg(&self, error: &io::Error) {
+        if self.mmsg.swap(false, Ordering::Relaxed) {
+            warn!("recvmmsg/sendmmsg unavailable ({}), falling back to per-datagram I/O", error);
+        }
+    }
+}
+
+/// The buffer plus one spare byte, which only a datagram larger than a packet reaches
+fn with_spare_byte(buffer: &mut SphinxPacketBuffer) -> &mut [u8] {
+    buffer.resize(SPHINX_PACKET_SIZE + 1, 0);
+    buffer
+}
+
+/// Oversized datagrams are handed on empty, as recvmmsg does with truncated ones, so the
+/// receiver drops them as malformed instead of parsing their first SPHINX_PACKET_SIZE bytes
+fn emptied_if_oversized(size: usize) -> usize {
+    if size > SPHINX_PACKET_SIZE { 0 } else { size }
+}
+
+#[cfg(target_os = "linux")]
+mod mmsg {
+    use super::*;
+    use std::mem::{size_of, zeroed};
+    use std::os::unix::io::AsRawFd;
+    use crate::net::sockaddr::{from_raw, to_raw};
+
+    /// Errors meaning the kernel or sandbox does not implement the syscalls
+    pub(super) fn unsupported(error: &io::Error) -> bool {
+        matches!(error.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP))
+    }
+
+    pub(super) fn recv(socket: &UdpSocket, buffers: &mut RecvBuffers) -> io::Result<usize> {
+        // SAFETY: all-zero is a valid value for these plain C structs
+        let mut addrs: [libc::sockaddr_storage; MMSG_BATCH_SIZE] = unsafe { zeroed() };
+        let mut iovecs: [libc::iovec; MMSG_BATCH_SIZE] = unsafe { zeroed() };
+        let mut headers: [libc::mmsghdr; MMSG_BATCH_SIZE] = unsafe { zeroed() };
+
+        for i in 0..MMSG_BATCH_SIZE {
+            iovecs[i].iov_base = buffers.buffers[i].as_mut_ptr() as *mut libc::c_void;
+            iovecs[i].iov_len = SPHINX_PACKET_SIZE;
+            headers[i].msg_hdr.msg_iov = &mut iovecs[i];
+            headers[i].msg_hdr.msg_iovlen = 1;
+            headers[i].msg_hdr.msg_name = &mut addrs[i] as *mut _ as *mut libc::c_void;
+            headers[i].msg_hdr.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        }
+
+        // SAFETY: every header points at live buffers of the stated sizes
+        let received = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                headers.as_mut_ptr(),
+                MMSG_BATCH_SIZE as libc::c_uint,
+                libc::MSG_DONTWAIT,
+                std::ptr::null_mut(),
+            )
+        };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut len = 0;
+        for i in 0..received as usize {
+            // Datagrams from address families we cannot route back to are skipped
+            if let Some(addr) = from_raw(&addrs[i]) {
+                // Oversized datagrams are handed on empty, so the receiver drops them as malformed
+                // instead of parsing their first SPHINX_PACKET_SIZE bytes
+                let size = if headers[i].msg_hdr.msg_flags & libc::MSG_TRUNC != 0 {
+                    0
+                } else {
+                    headers[i].msg_len as usize
+                };
+                buffers.received[len] = (size, addr);
+                if len != i {
+                    buffers.buffers.swap(i, len);
+                }
+                len += 1;
+            }
+        }
+        buffers.len = len;
+        Ok(len)
+    }
+
+    pub(super) fn send<D: AsRef<[u8]>>(socket: &UdpSocket, packets: &[(D, SocketAddr)]) -> io::Result<usize> {
+        let count = packets.len().min(MMSG_BATCH_SIZE);
+        // SAFETY: all-zero is a valid value for these plain C structs
+        let mut addrs: [libc::sockaddr_storage; MMSG_BATCH_SIZE] = unsafe { zeroed() };
+        let mut iovecs: [libc::iovec; MMSG_BATCH_SIZE] = unsafe { zeroed() };
+        let mut headers: [libc::mmsghdr; MMSG_BATCH_SIZE] = unsafe { zeroed() };
+
+        for (i, (datagram, destination)) in packets[..count].iter().enumerate() {
+            let datagram = datagram.as_ref();
+            iovecs[i].iov_base = datagram.as_ptr() as *mut libc::c_void;
+            iovecs[i].iov_len = datagram.len();
+            headers[i].msg_hdr.msg_iov = &mut iovecs[i];
+            headers[i].msg_hdr.msg_iovlen = 1;
+            headers[i].msg_hdr.msg_name = &mut addrs[i] as *mut _ as *mut libc::c_void;
+            headers[i].msg_hdr.msg_namelen = to_raw(destination, &mut addrs[i]);
+        }
+
+        // SAFETY: every header points at live buffers of the stated sizes; the kernel only
+        // reads from the datagram buffers
+        let sent = unsafe {
+            libc::sendmmsg(
+                socket.as_raw_fd(),
+                headers.as_mut_ptr(),
+                count as libc::c_uint,
+                libc::MSG_DONTWAIT,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(sent as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_batched_and_fallback_paths_round_trip() {
+        for enable_mmsg in [true, false] {
+            let receiver = BatchedSocket::new(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()), enable_mmsg);
+            let sender = BatchedSocket::new(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()), enable_mmsg);
+            let destination = receiver.socket().local_addr().unwrap();
+
+            let datagrams: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i; SPHINX_PACKET_SIZE]).collect();
+            let packets: Vec<(&[u8], SocketAddr)> = datagrams.iter()
+                .map(|datagram| (datagram.as_slice(), destination))
+                .collect();
+
+            let mut sent = 0;
+            while sent < packets.len() {
+                sent += sender.send_batch(&packets[sent..]).await.unwrap();
+            }
+
+            let mut buffers = RecvBuffers::new();
+            let mut received = Vec::new();
+            while received.len() < datagrams.len() {
+                receiver.recv_batch(&mut buffers).await.unwrap();
+                for (datagram, source) in buffers.iter() {
+                    assert_eq!(source, sender.socket().local_addr().unwrap());
+                    received.push(datagram.to_vec());
+                }
+            }
+            assert_eq!(received, datagrams, "mmsg enabled: {}", enable_mmsg);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_truncated_datagram_is_emptied() {
+        for enable_mmsg in [true, false] {
+            let receiver = BatchedSocket::new(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()), enable_mmsg);
+            let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let destination = receiver.socket().local_addr().unwrap();
+
+            sender.send_to(&[1u8; SPHINX_PACKET_SIZE + 1], destination).await.unwrap();
+            sender.send_to(&[2u8; SPHINX_PACKET_SIZE + 100], destination).await.unwrap();
+            sender.send_to(&[3u8; SPHINX_PACKET_SIZE], destination).await.unwrap();
+
+            let mut buffers = RecvBuffers::new();
+            let mut received = Vec::new();
+            while received.len() < 3 {
+                receiver.recv_batch(&mut buffers).await.unwrap();
+                received.extend(buffers.iter().map(|(datagram, _)| datagram.to_vec()));
+            }
+            assert_eq!(received, vec![vec![], vec![], vec![3u8; SPHINX_PACKET_SIZE]], "mmsg enabled: {}", enable_mmsg);
+        }
+    }
+}
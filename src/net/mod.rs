@@ -0,0 +1,10 @@
+// UDP I/O backends for the packet pipeline
+pub mod batch;
+pub mod reuseport;
+#[cfg(target_os = "linux")]
+pub(crate) mod sockaddr;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+
+pub use batch::*;
+pub use reuseport::*;
@@ -0,0 +1,163 @@
+// SO_REUSEPORT socket groups
+// Every receiver gets its own socket bound to the listen address, so the kernel spreads
+// incoming flows across them instead of waking every receiver on one shared socket. The
+// option only takes effect if it is set before bind. An optional classic BPF program picks
+// the socket by the CPU that handled the packet, keeping a flow's processing on one core.
+
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+/// Bind `count` sockets to `addr` as one SO_REUSEPORT group
+///
+/// With `cpu_steering`, datagrams handled on CPU `n` go to socket `n % count`. Platforms
+/// without SO_REUSEPORT load balancing get a single socket.
+pub async fn bind_reuseport(addr: &str, count: usize, cpu_steering: bool) -> io::Result<Vec<UdpSocket>> {
+    let addr = tokio::net::lookup_host(addr).await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "listen address did not resolve"))?;
+    bind_group(addr, count.max(1), cpu_steering)
+}
+
+#[cfg(target_os = "linux")]
+fn bind_group(addr: SocketAddr, count: usize, cpu_steering: bool) -> io::Result<Vec<UdpSocket>> {
+    let mut sockets = Vec::with_capacity(count);
+    // Port 0 is resolved by the first bind; the rest of the group joins that port
+    let mut group_addr = addr;
+    for _ in 0..count {
+        let socket = sys::bind_reuseport_socket(group_addr)?;
+        group_addr = socket.local_addr()?;
+        sockets.push(UdpSocket::from_std(socket)?);
+    }
+
+    if cpu_steering && count > 1 {
+        sys::attach_cpu_steering(&sockets[0], count as u32)?;
+    }
+    Ok(sockets)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_group(addr: SocketAddr, _count: usize, _cpu_steering: bool) -> io::Result<Vec<UdpSocket>> {
+    let socket = std::net::UdpSocket::bind(addr)?;
+    socket.set_nonblocking(true)?;
+    Ok(vec![UdpSocket::from_std(socket)?])
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::*;
+    use std::mem::{size_of, zeroed};
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+    use crate::net::sockaddr::to_raw;
+
+    /// Kernel receive buffer requested for each socket
+    const RECV_BUFFER_SIZE: i32 = 1024 * 1024;
+
+    fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    fn set_option<T>(fd: RawFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+        // SAFETY: value points at a live T of the stated size
+        check(unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                value as *const T as *const libc::c_void,
+                size_of::<T>() as libc::socklen_t,
+            )
+        })?;
+        Ok(())
+    }
+
+    pub(super) fn bind_reuseport_socket(addr: SocketAddr) -> io::Result<std::net::UdpSocket> {
+        let domain = if addr.is_ipv4() { libc::AF_INET } else { libc::AF_INET6 };
+        // SAFETY: plain socket(2) call; the descriptor is owned by the returned UdpSocket
+        let fd = check(unsafe {
+            libc::socket(domain, libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 0)
+        })?;
+        // SAFETY: fd is a fresh, valid descriptor; wrapping it closes it on every error path
+        let socket = unsafe { std::net::UdpSocket::from_raw_fd(fd) };
+
+        set_option(fd, libc::SOL_SOCKET, libc::SO_REUSEPORT, &1i32)?;
+        set_option(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, &RECV_BUFFER_SIZE)?;
+
+        // SAFETY: all-zero is a valid sockaddr_storage
+        let mut storage: libc::sockaddr_storage = unsafe { zeroed() };
+        let len = to_raw(&addr, &mut storage);
+        // SAFETY: storage holds a sockaddr of length len
+        check(unsafe { libc::bind(fd, &storage as *const _ as *const libc::sockaddr, len) })?;
+        Ok(socket)
+    }
+
+    /// Steer datagrams by receiving CPU: `return cpu % sockets`
+    pub(super) fn attach_cpu_steering(socket: &UdpSocket, sockets: u32) -> io::Result<()> {
+        let mut program = [
+            libc::sock_filter {
+                code: (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16,
+                jt: 0,
+                jf: 0,
+                k: (libc::SKF_AD_OFF + libc::SKF_AD_CPU) as u32,
+            },
+            libc::sock_filter {
+                code: (libc::BPF_ALU | libc::BPF_MOD | libc::BPF_K) as u16,
+                jt: 0,
+                jf: 0,
+                k: sockets,
+            },
+            libc::sock_filter {
+                code: (libc::BPF_RET | libc::BPF_A) as u16,
+                jt: 0,
+                jf: 0,
+                k: 0,
+            },
+        ];
+        let fprog = libc::sock_fprog {
+            len: program.len() as u16,
+            filter: program.as_mut_ptr(),
+        };
+        // The program applies to the whole group, whichever member it is attached to
+        set_option(socket.as_raw_fd(), libc::SOL_SOCKET, libc::SO_ATTACH_REUSEPORT_CBPF, &fprog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_group_shares_one_port() {
+        let sockets = bind_reuseport("127.0.0.1:0", 4, true).await.unwrap();
+        let expected = if cfg!(target_os = "linux") { 4 } else { 1 };
+        assert_eq!(sockets.len(), expected);
+
+        let addr = sockets[0].local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+        for socket in &sockets {
+            assert_eq!(socket.local_addr().unwrap(), addr);
+        }
+
+        // Every datagram lands on exactly one member of the group
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"steered", addr).await.unwrap();
+        let mut buffer = [0u8; 16];
+        let received = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                for socket in &sockets {
+                    if let Ok((size, _)) = socket.try_recv_from(&mut buffer) {
+                        return size;
+                    }
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(&buffer[..received], b"steered");
+    }
+}
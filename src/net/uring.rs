@@ -0,0 +1,345 @@
+// io_uring UDP backend (`io-uring` feature, Linux only)
+// Each receiver thread keeps a fixed set of recvmsg operations in flight on its socket, each
+// into a pooled packet buffer, and hands every completed buffer on per wakeup. The sender copies datagrams into buffers registered
+// with the ring once at startup and sends them with zero-copy SEND_ZC using those fixed
+// buffers. `probe` reports whether the running kernel can do all of this, so callers can fall
+// back to the tokio loops at runtime.
+
+use io_uring::{cqueue, opcode, types, IoUring, Probe};
+use std::io;
+use std::mem::{size_of, zeroed};
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+
+use crate::net::sockaddr::{from_raw, to_raw};
+use crate::sphinx::{get_packet_buffer, PooledObject, SphinxPacketBuffer, SPHINX_PACKET_SIZE};
+
+/// Receive operations kept in flight per socket
+pub const URING_RECV_SLOTS: usize = 128;
+/// Registered send buffers; a slot is reused once the kernel has released it
+pub const URING_SEND_SLOTS: usize = 128;
+const RING_ENTRIES: u32 = 256;
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+/// Check that io_uring is available and supports the operations this backend uses
+pub fn probe() -> io::Result<()> {
+    let ring = IoUring::new(2)?;
+    let mut probe = Probe::new();
+    ring.submitter().register_probe(&mut probe)?;
+
+    if probe.is_supported(opcode::RecvMsg::CODE) && probe.is_supported(opcode::SendZc::CODE) {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "kernel lacks io_uring RECVMSG or SEND_ZC"))
+    }
+}
+
+struct RecvSlot {
+    buffer: PooledObject<SphinxPacketBuffer>,
+    addr: libc::sockaddr_storage,
+    iovec: libc::iovec,
+    msghdr: libc::msghdr,
+}
+
+/// io_uring receive loop for one socket
+pub struct UringReceiver {
+    ring: IoUring,
+    // Keeps the descriptor open while operations reference it
+    _socket: Arc<UdpSocket>,
+    fd: RawFd,
+    slots: Box<[RecvSlot]>,
+}
+
+// SAFETY: the raw pointers in the slots only point into the slots and the buffers they own
+unsafe impl Send for UringReceiver {}
+
+impl UringReceiver {
+    pub fn new(socket: Arc<UdpSocket>) -> io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let fd = socket.as_raw_fd();
+        // SAFETY: all-zero is a valid value for these plain C structs
+        let slots = (0..URING_RECV_SLOTS)
+            .map(|_| unsafe {
+                RecvSlot {
+                    buffer: get_packet_buffer(),
+                    addr: zeroed(),
+                    iovec: zeroed(),
+                    msghdr: zeroed(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        let mut receiver = Self { ring, _socket: socket, fd, slots };
+        for slot in 0..URING_RECV_SLOTS {
+            receiver.arm(slot)?;
+        }
+        Ok(receiver)
+    }
+
+    /// Queue a recvmsg into `slot`; it is submitted with the next wait
+    fn arm(&mut self, slot: usize) -> io::Result<()> {
+        let entry = {
+            let slot_ref = &mut self.slots[slot];
+            slot_ref.iovec.iov_base = slot_ref.buffer.as_mut_ptr() as *mut libc::c_void;
+            slot_ref.iovec.iov_len = SPHINX_PACKET_SIZE;
+            slot_ref.msghdr.msg_iov = &mut slot_ref.iovec;
+            slot_ref.msghdr.msg_iovlen = 1;
+            slot_ref.msghdr.msg_name = &mut slot_ref.addr as *mut _ as *mut libc::c_void;
+            slot_ref.msghdr.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+            opcode::RecvMsg::new(types::Fd(self.fd), &mut slot_ref.msghdr)
+                .build()
+                .user_data(slot as u64)
+        };
+
+        // SAFETY: the slot's buffers live as long as the ring and are not touched until the
+        // operation completes
+        unsafe { self.ring.submission().push(&entry) }
+            .map_err(|_| io::Error::other("io_uring submission queue full"))
+    }
+
+    /// Block until datagrams arrive, passing each one to `on_datagram` in its buffer, cut to
+    /// the datagram's length; the slot receives into a fresh buffer from now on
+    pub fn recv_batch(
+        &mut self,
+        mut on_datagram: impl FnMut(PooledObject<SphinxPacketBuffer>, SocketAddr),
+    ) -> io::Result<usize> {
+        match self.ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return Ok(0),
+            Err(e) => return Err(e),
+        }
+
+        let mut completed = [(0usize, 0i32); URING_RECV_SLOTS];
+        let mut count = 0;
+        for cqe in self.ring.completion() {
+            completed[count] = (cqe.user_data() as usize, cqe.result());
+            count += 1;
+        }
+
+        let mut received = 0;
+        for &(slot, result) in &completed[..count] {
+            // A failed receive is simply re-armed, as the tokio loop retries after an error
+            if result >= 0 {
+                let slot_ref = &mut self.slots[slot];
+                if let Some(addr) = from_raw(&slot_ref.addr) {
+                    // Oversized datagrams are handed on empty, as with recvmmsg, so they are
+                    // dropped as malformed rather than parsed from their first bytes
+                    let size = if slot_ref.msghdr.msg_flags & libc::MSG_TRUNC != 0 { 0 } else { result as usize };
+                    let mut datagram = std::mem::replace(&mut slot_ref.buffer, get_packet_buffer());
+                    datagram.truncate(size);
+                    on_datagram(datagram, addr);
+                    received += 1;
+                }
+            }
+            self.arm(slot)?;
+        }
+        Ok(received)
+    }
+}
+
+impl Drop for UringReceiver {
+    // The kernel may write into a slot until its operation completes, so cancel them all and
+    // wait before the slots are freed
+    fn drop(&mut self) {
+        for slot in 0..URING_RECV_SLOTS {
+            let cancel = opcode::AsyncCancel::new(slot as u64).build().user_data(CANCEL_USER_DATA);
+            // SAFETY: cancellation entries reference no memory
+            while unsafe { self.ring.submission().push(&cancel) }.is_err() {
+                if self.ring.submit().is_err() {
+                    return;
+                }
+            }
+        }
+
+        let mut pending = URING_RECV_SLOTS;
+        while pending > 0 {
+            if self.ring.submit_and_wait(1).is_err() {
+                return;
+            }
+            pending -= self.ring.completion()
+                .filter(|cqe| cqe.user_data() != CANCEL_USER_DATA)
+                .count();
+        }
+    }
+}
+
+/// io_uring sender using registered buffers and zero-copy sends
+pub struct UringSender {
+    ring: IoUring,
+    _socket: Arc<UdpSocket>,
+    fd: RawFd,
+    buffers: Box<[[u8; SPHINX_PACKET_SIZE]]>,
+    addrs: Box<[libc::sockaddr_storage]>,
+    destinations: Box<[Option<SocketAddr>]>,
+    free: Vec<u16>,
+}
+
+// SAFETY: the registered buffers are owned by the sender and move with it
+unsafe impl Send for UringSender {}
+
+impl UringSender {
+    pub fn new(socket: Arc<UdpSocket>) -> io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let fd = socket.as_raw_fd();
+        let mut buffers = vec![[0u8; SPHINX_PACKET_SIZE]; URING_SEND_SLOTS].into_boxed_slice();
+        // SAFETY: all-zero is a valid sockaddr_storage
+        let addrs = vec![unsafe { zeroed::<libc::sockaddr_storage>() }; URING_SEND_SLOTS].into_boxed_slice();
+
+        let iovecs: Vec<libc::iovec> = buffers.iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+                iov_len: SPHINX_PACKET_SIZE,
+            })
+            .collect();
+        // SAFETY: the buffers are heap allocated, never reallocated, and outlive the ring
+        unsafe { ring.submitter().register_buffers(&iovecs)? };
+
+        Ok(Self {
+            ring,
+            _socket: socket,
+            fd,
+            buffers,
+            addrs,
+            destinations: vec![None; URING_SEND_SLOTS].into_boxed_slice(),
+            free: (0..URING_SEND_SLOTS as u16).rev().collect(),
+        })
+    }
+
+    /// Send datagrams of at most `SPHINX_PACKET_SIZE` bytes, reporting each failed send to
+    /// `on_error`
+    pub fn send_batch<D: AsRef<[u8]>>(
+        &mut self,
+        packets: &[(D, SocketAddr)],
+        mut on_error: impl FnMut(SocketAddr, io::Error),
+    ) -> io::Result<()> {
+        for (datagram, destination) in packets {
+            let (datagram, destination) = (datagram.as_ref(), *destination);
+            // Wait for the kernel to release a buffer
+            while self.free.is_empty() {
+                self.ring.submit_and_wait(1)?;
+                self.reap(&mut on_error);
+            }
+            let slot = self.free.pop().expect("free slot") as usize;
+
+            let len = datagram.len().min(SPHINX_PACKET_SIZE);
+            self.buffers[slot][..len].copy_from_slice(&datagram[..len]);
+            let addr_len = to_raw(&destination, &mut self.addrs[slot]);
+            self.destinations[slot] = Some(destination);
+
+            let entry = opcode::SendZc::new(types::Fd(self.fd), self.buffers[slot].as_ptr(), len as u32)
+                .buf_index(Some(slot as u16))
+                .dest_addr(&self.addrs[slot] as *const _ as *const libc::sockaddr)
+                .dest_addr_len(addr_len)
+                .build()
+                .user_data(slot as u64);
+
+            // SAFETY: the slot stays reserved until the kernel's notification releases it
+            while unsafe { self.ring.submission().push(&entry) }.is_err() {
+                self.ring.submit()?;
+            }
+        }
+
+        self.ring.submit()?;
+        self.reap(&mut on_error);
+        Ok(())
+    }
+
+    /// Collect finished sends; a slot is free once its notification arrives, or right after
+    /// a failed send, which gets none
+    fn reap(&mut self, on_error: &mut impl FnMut(SocketAddr, io::Error)) {
+        for cqe in self.ring.completion() {
+            let slot = cqe.user_data() as usize;
+            if cqueue::notif(cqe.flags()) {
+                self.free.push(slot as u16);
+                continue;
+            }
+
+            if cqe.result() < 0 {
+                if let Some(destination) = self.destinations[slot] {
+                    on_error(destination, io::Error::from_raw_os_error(-cqe.result()));
+                }
+            }
+            if !cqueue::more(cqe.flags()) {
+                self.free.push(slot as u16);
+            }
+        }
+    }
+}
+
+impl Drop for UringSender {
+    // Registered buffers must outlive the sends that read from them
+    fn drop(&mut self) {
+        let mut ignore = |_, _| {};
+        while self.free.len() < URING_SEND_SLOTS {
+            if self.ring.submit_and_wait(1).is_err() {
+                return;
+            }
+            self.reap(&mut ignore);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_uring_round_trip() {
+        if let Err(e) = probe() {
+            eprintln!("skipping, io_uring unavailable: {}", e);
+            return;
+        }
+
+        let receiver_socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sender_socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let destination = receiver_socket.local_addr().unwrap();
+        let source = sender_socket.local_addr().unwrap();
+
+        let datagrams: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i; SPHINX_PACKET_SIZE]).collect();
+        let received = std::thread::spawn(move || {
+            let mut receiver = UringReceiver::new(receiver_socket).unwrap();
+            let mut received = Vec::new();
+            while received.len() < 10 {
+                receiver.recv_batch(|datagram, addr| {
+                    assert_eq!(addr, source);
+                    received.push(datagram.to_vec());
+                }).unwrap();
+            }
+            received
+        });
+
+        let mut sender = UringSender::new(sender_socket).unwrap();
+        let packets: Vec<(&[u8], SocketAddr)> = datagrams.iter()
+            .map(|datagram| (datagram.as_slice(), destination))
+            .collect();
+        sender.send_batch(&packets, |addr, e| panic!("send to {} failed: {}", addr, e)).unwrap();
+
+        let mut received = received.join().unwrap();
+        received.sort();
+        assert_eq!(received, datagrams);
+    }
+
+    #[tokio::test]
+    async fn test_uring_truncated_datagram_is_emptied() {
+        if let Err(e) = probe() {
+            eprintln!("skipping, io_uring unavailable: {}", e);
+            return;
+        }
+
+        let receiver_socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let destination = receiver_socket.local_addr().unwrap();
+        sender.send_to(&[1u8; SPHINX_PACKET_SIZE + 100], destination).await.unwrap();
+
+        let mut receiver = UringReceiver::new(receiver_socket).unwrap();
+        let mut received = Vec::new();
+        while received.is_empty() {
+            receiver.recv_batch(|datagram, _| received.push(datagram.to_vec())).unwrap();
+        }
+        assert_eq!(received, vec![Vec::<u8>::new()]);
+    }
+}
@@ -702,6 +702,11 @@ impl PeerDiscovery {
         }
     }
 
+    /// Registry discovered peers are recorded in
+    pub fn peer_registry(&self) -> Arc<PeerRegistry> {
+        self.peer_registry.clone()
+    }
+
     /// Get discovery statistics
     pub async fn get_stats(&self) -> DiscoveryStats {
         let discovered = self.discovered_peers.read().await;
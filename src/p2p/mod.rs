@@ -296,6 +296,11 @@ impl P2PNetwork {
         self.peers.read().await.values().cloned().collect()
     }
     
+    /// Peer registry filled by discovery; hand it to `HighPerformanceMixnode::with_peers`
+    pub fn peer_registry(&self) -> Arc<peer::PeerRegistry> {
+        self.discovery.peer_registry()
+    }
+    
     /// Bootstrap from known peers
     async fn bootstrap(&self) -> Result<(), String> {
         info!("Bootstrapping from {} known nodes", self.config.bootstrap_nodes.len());
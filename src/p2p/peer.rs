@@ -297,6 +297,21 @@ impl PeerRegistry {
             .collect()
     }
 
+    /// Online, unblocked peers that process Sphinx packets, e.g. as cover traffic targets
+    pub async fn get_sphinx_peers(&self) -> Vec<PeerInfo> {
+        let blocked = self.blocked_peers.read().await;
+        let peers = self.peers.read().await;
+        
+        peers.values()
+            .filter(|peer| {
+                peer.is_online &&
+                peer.capabilities.supports_sphinx &&
+                !blocked.contains(&peer.peer_id)
+            })
+            .cloned()
+            .collect()
+    }
+
     /// Update peer reputation based on performance
     pub async fn update_reputation(&self, peer_id: &PeerId, update: ReputationUpdate) {
         let mut peers = self.peers.write().await;
@@ -381,6 +396,8 @@ impl PeerRegistry {
             reputation.last_updated = SystemTime::now();
             peer.last_seen = SystemTime::now();
         }
+        // evaluate_trust takes the peers lock itself
+        drop(peers);
 
         // Re-evaluate trust status
         self.evaluate_trust(peer_id).await;
@@ -1,13 +1,18 @@
 // Rate limiting and anti-abuse module
 use governor::{Quota, RateLimiter as GovernorRateLimiter, DefaultDirectRateLimiter};
 use std::net::IpAddr;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU32;
+use std::sync::Arc;
 use std::time::SystemTime;
 
 pub struct RateLimiter {
-    per_ip_limiters: HashMap<IpAddr, DefaultDirectRateLimiter>,
+    per_ip_limiters: HashMap<IpAddr, IpLimiter>,
+    per_ip_quota: Quota,
     global_limiter: DefaultDirectRateLimiter,
     config: RateLimitConfig,
+    // Known mixes forward traffic for many clients, so only the global limit applies to them
+    exempt_ips: Arc<HashSet<IpAddr>>,
     // Anti-abuse tracking
     suspicious_ips: HashMap<IpAddr, SuspiciousActivity>,
     last_cleanup: SystemTime,
@@ -17,7 +22,7 @@ pub struct RateLimiter {
 pub struct RateLimitConfig {
     pub packets_per_second_per_ip: u32,
     pub global_packets_per_second: u32,
-    pub burst_size: u32,
+    pub burst_size: u32, // Packets one IP may send back to back before its rate applies
     pub suspicious_threshold: u32,
     pub ban_duration_seconds: u64,
 }
@@ -34,6 +39,11 @@ impl Default for RateLimitConfig {
     }
 }
 
+struct IpLimiter {
+    limiter: DefaultDirectRateLimiter,
+    last_seen: SystemTime,
+}
+
 #[derive(Debug)]
 struct SuspiciousActivity {
     violation_count: u32,
@@ -59,13 +69,17 @@ pub enum RateLimitReason {
 impl RateLimiter {
     pub fn new(config: RateLimitConfig) -> Self {
         let global_quota = Quota::per_second(
-            std::num::NonZeroU32::new(config.global_packets_per_second).unwrap()
+            NonZeroU32::new(config.global_packets_per_second).unwrap()
         );
+        let per_ip_quota = Quota::per_second(NonZeroU32::new(config.packets_per_second_per_ip).unwrap())
+            .allow_burst(NonZeroU32::new(config.burst_size.max(1)).unwrap());
         
         Self {
             per_ip_limiters: HashMap::new(),
+            per_ip_quota,
             global_limiter: DefaultDirectRateLimiter::direct(global_quota),
             config,
+            exempt_ips: Arc::new(HashSet::new()),
             suspicious_ips: HashMap::new(),
             last_cleanup: SystemTime::now(),
         }
@@ -87,22 +101,23 @@ impl RateLimiter {
             }
         }
         
-        // Check global rate limit first (most important)
+        // Check global rate limit first (most important). Overload is not the fault of the
+        // IP that happens to arrive next, so it counts as no violation
         if self.global_limiter.check().is_err() {
-            self.record_violation(source_ip, RateLimitReason::GlobalLimit, now);
             return RateLimitResult::RateLimited(RateLimitReason::GlobalLimit);
         }
         
+        if self.exempt_ips.contains(&source_ip) {
+            return RateLimitResult::Allowed;
+        }
+        
         // Check per-IP rate limit
-        let limiter = self.per_ip_limiters.entry(source_ip)
-            .or_insert_with(|| {
-                let quota = Quota::per_second(
-                    std::num::NonZeroU32::new(self.config.packets_per_second_per_ip).unwrap()
-                );
-                DefaultDirectRateLimiter::direct(quota)
-            });
+        let quota = self.per_ip_quota;
+        let ip_limiter = self.per_ip_limiters.entry(source_ip)
+            .or_insert_with(|| IpLimiter { limiter: DefaultDirectRateLimiter::direct(quota), last_seen: now });
+        ip_limiter.last_seen = now;
         
-        if limiter.check().is_err() {
+        if ip_limiter.limiter.check().is_err() {
             self.record_violation(source_ip, RateLimitReason::PerIPLimit, now);
             return RateLimitResult::RateLimited(RateLimitReason::PerIPLimit);
         }
@@ -110,6 +125,11 @@ impl RateLimiter {
         RateLimitResult::Allowed
     }
     
+    /// Replace the set of IPs that skip the per-IP limit
+    pub fn set_exempt_ips(&mut self, ips: Arc<HashSet<IpAddr>>) {
+        self.exempt_ips = ips;
+    }
+    
     fn record_violation(&mut self, ip: IpAddr, reason: RateLimitReason, now: SystemTime) {
         let activity = self.suspicious_ips.entry(ip)
             .or_insert_with(|| SuspiciousActivity {
@@ -140,7 +160,11 @@ impl RateLimiter {
         
         self.last_cleanup = now;
         
-        // Clean up old per-IP limiters (keep only active ones from last hour)
+        // Idle limiters have refilled their quota anyway, and spoofed sources must not grow the map
+        self.per_ip_limiters.retain(|_ip, ip_limiter| {
+            now.duration_since(ip_limiter.last_seen).unwrap_or_default().as_secs() < 60
+        });
+        
         let one_hour_ago = now - std::time::Duration::from_secs(3600);
         
         // Remove old suspicious entries that are no longer banned
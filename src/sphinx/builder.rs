@@ -0,0 +1,448 @@
+// Client-side Sphinx packet construction
+// Produces the layered header and payload that SphinxMixer::process_packet peels one hop at a time
+
+use std::time::Duration;
+use curve25519_dalek::{scalar::Scalar, ristretto::RistrettoPoint};
+use rand_core::{CryptoRng, OsRng, RngCore};
+
+use crate::sphinx::packet::{
+    SphinxPacket, SphinxHeader, SphinxError, MixNodeId,
+    generate_header_stream, compute_header_mac, blinding_factor,
+    SPHINX_PAYLOAD_SIZE, SPHINX_ROUTING_INFO_SIZE, SPHINX_MAC_SIZE, MAX_HOPS, HOP_ROUTING_SIZE,
+    PAYLOAD_INTEGRITY_SIZE, ROUTING_COMMAND_FORWARD, ROUTING_COMMAND_DELIVER, ROUTING_COMMAND_REPLY,
+};
+use crate::sphinx::simd::SimdKeyDeriver;
+use crate::sphinx::codec::SphinxVersion;
+use crate::sphinx::lioness::Lioness;
+use crate::sphinx::surb::{SurbId, ReplySurb, SurbDecryptionKeys};
+
+/// Largest message that fits in a single packet (integrity prefix and 4-byte length are reserved)
+pub const SPHINX_MAX_MESSAGE_SIZE: usize = SPHINX_PAYLOAD_SIZE - PAYLOAD_INTEGRITY_SIZE - 4;
+
+/// Per-hop key material shared between the sender and one mix
+pub(crate) struct HopKeys {
+    pub(crate) header_key: [u8; 32],
+    pub(crate) payload_key: [u8; 32],
+    pub(crate) mac_key: [u8; 32],
+}
+
+/// Builder for multi-hop Sphinx packets
+pub struct SphinxPacketBuilder {
+    hops: Vec<(MixNodeId, RistrettoPoint)>,
+    delays: Vec<Duration>,
+    payload: Vec<u8>,
+}
+
+impl SphinxPacketBuilder {
+    pub fn new() -> Self {
+        Self {
+            hops: Vec::new(),
+            delays: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    /// Set the route as (node id, Sphinx public key) pairs, first hop first
+    pub fn route(mut self, hops: Vec<(MixNodeId, RistrettoPoint)>) -> Self {
+        self.hops = hops;
+        self
+    }
+
+    /// Set the mixing delay requested from each hop (defaults to zero delay)
+    pub fn delays(mut self, delays: Vec<Duration>) -> Self {
+        self.delays = delays;
+        self
+    }
+
+    /// Set the message delivered by the final hop
+    pub fn payload(mut self, payload: &[u8]) -> Self {
+        self.payload = payload.to_vec();
+        self
+    }
+
+    /// Build the packet using the operating system RNG
+    pub fn build(&self) -> Result<SphinxPacket, SphinxError> {
+        self.build_with_rng(&mut OsRng)
+    }
+
+    /// Build the packet with a caller supplied RNG
+    pub fn build_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Result<SphinxPacket, SphinxError> {
+        self.validate()?;
+
+        let (header, hop_keys) = self.build_header(None, rng);
+        let payload = self.build_payload(&hop_keys, rng)?;
+
+        Ok(SphinxPacket { header, payload })
+    }
+
+    /// Build a single-use reply block routed along this builder's route
+    ///
+    /// The route should end at a mix that can hand the reply to the SURB creator; the
+    /// returned keys stay with the creator to decrypt the reply.
+    pub fn build_surb(&self) -> Result<(ReplySurb, SurbDecryptionKeys), SphinxError> {
+        self.build_surb_with_rng(&mut OsRng)
+    }
+
+    pub fn build_surb_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R
+    ) -> Result<(ReplySurb, SurbDecryptionKeys), SphinxError> {
+        self.validate()?;
+
+        let mut surb_id = [0u8; 32];
+        rng.fill_bytes(&mut surb_id);
+        let mut reply_key = [0u8; 32];
+        rng.fill_bytes(&mut reply_key);
+
+        let (header, hop_keys) = self.build_header(Some(&surb_id), rng);
+        let hop_payload_keys = hop_keys.iter().map(|keys| keys.payload_key).collect();
+
+        Ok((
+            ReplySurb::new(self.hops[0].0, header, reply_key),
+            SurbDecryptionKeys::new(surb_id, reply_key, hop_payload_keys),
+        ))
+    }
+
+    /// Build the header and per-hop keys for the route
+    ///
+    /// With a SURB id the final hop is told to hand the payload back as a reply instead of
+    /// delivering it. Callers validate the route first.
+    pub(crate) fn build_header<R: RngCore + CryptoRng>(
+        &self,
+        surb_id: Option<&SurbId>,
+        rng: &mut R
+    ) -> (SphinxHeader, Vec<HopKeys>) {
+        // Initial ephemeral secret; each hop blinds the ephemeral key before forwarding
+        let mut secret_bytes = [0u8; 32];
+        rng.fill_bytes(&mut secret_bytes);
+        let initial_secret = Scalar::from_bytes_mod_order(secret_bytes);
+        let ephemeral_key = (initial_secret * curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT)
+            .compress()
+            .to_bytes();
+
+        let hop_keys = Self::derive_hop_keys(initial_secret, &self.hops);
+        let (routing_info, mac) = self.build_routing_info(&hop_keys, surb_id, rng);
+
+        let header = SphinxHeader {
+            version: SphinxVersion::Native as u8,
+            ephemeral_key,
+            mac,
+            routing_info,
+        };
+
+        (header, hop_keys)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), SphinxError> {
+        if self.hops.is_empty() || self.hops.len() > MAX_HOPS {
+            return Err(SphinxError::InvalidRoute);
+        }
+
+        if !self.delays.is_empty() && self.delays.len() != self.hops.len() {
+            return Err(SphinxError::InvalidRoute);
+        }
+
+        if self.payload.len() > SPHINX_MAX_MESSAGE_SIZE {
+            return Err(SphinxError::InvalidPayloadSize);
+        }
+
+        Ok(())
+    }
+
+    /// Walk the route computing the same shared secrets each mix will compute
+    ///
+    /// The secret is multiplied by every blinding factor seen so far, matching the blinded
+    /// ephemeral key each mix receives.
+    fn derive_hop_keys(initial_secret: Scalar, hops: &[(MixNodeId, RistrettoPoint)]) -> Vec<HopKeys> {
+        let key_deriver = SimdKeyDeriver::new();
+        let mut secret = initial_secret;
+        let mut hop_keys = Vec::with_capacity(hops.len());
+
+        for (_, public_key) in hops {
+            let ephemeral_key = (secret * curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT)
+                .compress()
+                .to_bytes();
+            let shared_secret = (secret * public_key).compress().to_bytes();
+            let (header_key, payload_key, mac_key) = key_deriver.derive_keys_simd(&shared_secret);
+            hop_keys.push(HopKeys { header_key, payload_key, mac_key });
+            secret *= blinding_factor(&ephemeral_key, &shared_secret);
+        }
+
+        hop_keys
+    }
+
+    fn hop_delay(&self, hop: usize) -> Duration {
+        self.delays.get(hop).copied().unwrap_or(Duration::ZERO)
+    }
+
+    fn routing_block(
+        &self,
+        hop: usize,
+        next_mac: &[u8; SPHINX_MAC_SIZE],
+        surb_id: Option<&SurbId>
+    ) -> [u8; HOP_ROUTING_SIZE] {
+        let mut block = [0u8; HOP_ROUTING_SIZE];
+
+        match (self.hops.get(hop + 1), surb_id) {
+            (Some((next_hop, _)), _) => {
+                block[0] = ROUTING_COMMAND_FORWARD;
+                block[1..33].copy_from_slice(next_hop);
+            }
+            (None, Some(surb_id)) => {
+                block[0] = ROUTING_COMMAND_REPLY;
+                block[1..33].copy_from_slice(surb_id);
+            }
+            (None, None) => block[0] = ROUTING_COMMAND_DELIVER,
+        }
+
+        let delay_micros = self.hop_delay(hop).as_micros().min(u64::MAX as u128) as u64;
+        block[33..41].copy_from_slice(&delay_micros.to_be_bytes());
+        block[41..].copy_from_slice(next_mac);
+        block
+    }
+
+    /// Filler that the first hops' decryption shifts into the tail of the routing info
+    ///
+    /// Precomputing it lets the innermost layer end with exactly those bytes, so every
+    /// hop's MAC covers the routing info it will actually receive.
+    fn generate_filler(hop_keys: &[HopKeys]) -> Vec<u8> {
+        let mut filler = Vec::with_capacity(hop_keys.len() * HOP_ROUTING_SIZE);
+        let mut key_stream = [0u8; SPHINX_ROUTING_INFO_SIZE + HOP_ROUTING_SIZE];
+
+        for keys in &hop_keys[..hop_keys.len() - 1] {
+            filler.extend_from_slice(&[0u8; HOP_ROUTING_SIZE]);
+            generate_header_stream(&keys.header_key, &mut key_stream);
+
+            let offset = key_stream.len() - filler.len();
+            for (byte, stream) in filler.iter_mut().zip(key_stream[offset..].iter()) {
+                *byte ^= stream;
+            }
+        }
+
+        filler
+    }
+
+    /// Layer the routing blocks from the last hop backwards.
+    ///
+    /// Each mix verifies the MAC, decrypts (routing_info || zeros) and reads its block from
+    /// the front, so the encrypted tail of one layer becomes the head of the next. Returns the
+    /// first hop's routing info and MAC.
+    fn build_routing_info<R: RngCore>(
+        &self,
+        hop_keys: &[HopKeys],
+        surb_id: Option<&SurbId>,
+        rng: &mut R
+    ) -> ([u8; SPHINX_ROUTING_INFO_SIZE], [u8; SPHINX_MAC_SIZE]) {
+        let last_hop = self.hops.len() - 1;
+        let filler = Self::generate_filler(hop_keys);
+        let mut key_stream = [0u8; SPHINX_ROUTING_INFO_SIZE + HOP_ROUTING_SIZE];
+
+        // Innermost layer: final block and random padding, encrypted, then the filler
+        let mut routing_info = [0u8; SPHINX_ROUTING_INFO_SIZE];
+        rng.fill_bytes(&mut routing_info);
+        routing_info[..HOP_ROUTING_SIZE].copy_from_slice(&self.routing_block(last_hop, &[0u8; SPHINX_MAC_SIZE], surb_id));
+
+        let plain_len = SPHINX_ROUTING_INFO_SIZE - filler.len();
+        generate_header_stream(&hop_keys[last_hop].header_key, &mut key_stream);
+        for (byte, stream) in routing_info[..plain_len].iter_mut().zip(key_stream.iter()) {
+            *byte ^= stream;
+        }
+        routing_info[plain_len..].copy_from_slice(&filler);
+
+        let mut mac = compute_header_mac(&hop_keys[last_hop].mac_key, &routing_info);
+
+        for hop in (0..last_hop).rev() {
+            let mut layer = [0u8; SPHINX_ROUTING_INFO_SIZE];
+            layer[..HOP_ROUTING_SIZE].copy_from_slice(&self.routing_block(hop, &mac, None));
+            layer[HOP_ROUTING_SIZE..].copy_from_slice(&routing_info[..SPHINX_ROUTING_INFO_SIZE - HOP_ROUTING_SIZE]);
+
+            generate_header_stream(&hop_keys[hop].header_key, &mut key_stream);
+            for (out, (plain, stream)) in routing_info.iter_mut().zip(layer.iter().zip(key_stream.iter())) {
+                *out = plain ^ stream;
+            }
+
+            mac = compute_header_mac(&hop_keys[hop].mac_key, &routing_info);
+        }
+
+        (routing_info, mac)
+    }
+
+    /// Encode the message behind a zeroed integrity prefix, then add one Lioness layer per hop
+    fn build_payload<R: RngCore + CryptoRng>(
+        &self,
+        hop_keys: &[HopKeys],
+        rng: &mut R
+    ) -> Result<[u8; SPHINX_PAYLOAD_SIZE], SphinxError> {
+        if hop_keys.is_empty() {
+            return Err(SphinxError::InvalidRoute);
+        }
+
+        let mut payload = encode_payload_message(&self.payload, rng)?;
+
+        // Innermost layer belongs to the final hop
+        for keys in hop_keys.iter().rev() {
+            Lioness::new(&keys.payload_key).encrypt(&mut payload);
+        }
+
+        Ok(payload)
+    }
+}
+
+/// Lay out a message behind the zeroed integrity prefix and its length
+pub(crate) fn encode_payload_message<R: RngCore>(
+    message: &[u8],
+    rng: &mut R
+) -> Result<[u8; SPHINX_PAYLOAD_SIZE], SphinxError> {
+    if message.len() > SPHINX_MAX_MESSAGE_SIZE {
+        return Err(SphinxError::InvalidPayloadSize);
+    }
+
+    // Random padding so unused space does not reveal the message length after decryption
+    let mut payload = [0u8; SPHINX_PAYLOAD_SIZE];
+    rng.fill_bytes(&mut payload);
+    payload[..PAYLOAD_INTEGRITY_SIZE].fill(0);
+
+    let body = &mut payload[PAYLOAD_INTEGRITY_SIZE..];
+    body[0..4].copy_from_slice(&(message.len() as u32).to_be_bytes());
+    body[4..4 + message.len()].copy_from_slice(message);
+
+    Ok(payload)
+}
+
+impl Default for SphinxPacketBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphinx::packet::{SphinxMixer, RoutingCommand, ProcessedPayload};
+
+    fn test_mixers(count: u8) -> Vec<(MixNodeId, SphinxMixer)> {
+        (0..count)
+            .map(|i| {
+                let mut key_bytes = [0u8; 32];
+                OsRng.fill_bytes(&mut key_bytes);
+                ([i + 1; 32], SphinxMixer::new(Scalar::from_bytes_mod_order(key_bytes)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_multi_hop_packet_peels_one_layer_per_hop() {
+        let mut mixers = test_mixers(3);
+        let route: Vec<_> = mixers.iter().map(|(id, mixer)| (*id, mixer.public_key())).collect();
+        let delays = vec![
+            Duration::from_millis(10),
+            Duration::from_millis(20),
+            Duration::from_millis(30),
+        ];
+        let message = b"hello through the mixnet";
+
+        let mut packet = SphinxPacketBuilder::new()
+            .route(route.clone())
+            .delays(delays.clone())
+            .payload(message)
+            .build()
+            .unwrap();
+
+        for hop in 0..route.len() {
+            let processed = mixers[hop].1.process_packet(&packet).unwrap();
+            assert_eq!(processed.routing_info.delay, delays[hop]);
+
+            match (processed.routing_info.command, processed.payload) {
+                (RoutingCommand::Forward { next_hop }, ProcessedPayload::Forward(next_packet)) => {
+                    assert_eq!(next_hop, route[hop + 1].0);
+                    packet = SphinxPacket::from_bytes(&next_packet).unwrap();
+                }
+                (RoutingCommand::Deliver, ProcessedPayload::Final(delivered)) => {
+                    assert_eq!(hop, route.len() - 1);
+                    assert_eq!(delivered, message.to_vec());
+                }
+                _ => panic!("unexpected routing result at hop {}", hop),
+            }
+        }
+    }
+
+    #[test]
+    fn test_tampered_header_fails_integrity_check() {
+        let mut mixers = test_mixers(3);
+        let route: Vec<_> = mixers.iter().map(|(id, mixer)| (*id, mixer.public_key())).collect();
+        let packet = SphinxPacketBuilder::new()
+            .route(route)
+            .payload(b"tamper")
+            .build()
+            .unwrap();
+
+        // Flip a bit deep in the routing info that only later hops read
+        let mut tampered = packet.clone();
+        tampered.header.routing_info[SPHINX_ROUTING_INFO_SIZE - 1] ^= 0x01;
+        assert!(matches!(
+            mixers[0].1.process_packet(&tampered),
+            Err(crate::sphinx::MixError::Sphinx(SphinxError::InvalidRoutingInfo))
+        ));
+
+        // Consecutive hops see unrelated ephemeral keys
+        let processed = mixers[0].1.process_packet(&packet).unwrap();
+        let ProcessedPayload::Forward(next_packet) = processed.payload else {
+            panic!("first hop should forward");
+        };
+        let next_packet = SphinxPacket::from_bytes(&next_packet).unwrap();
+        assert_ne!(next_packet.header.ephemeral_key, packet.header.ephemeral_key);
+        assert!(mixers[1].1.process_packet(&next_packet).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_payload_is_destroyed() {
+        let mut mixers = test_mixers(2);
+        let route: Vec<_> = mixers.iter().map(|(id, mixer)| (*id, mixer.public_key())).collect();
+        let mut packet = SphinxPacketBuilder::new()
+            .route(route)
+            .payload(b"tamper")
+            .build()
+            .unwrap();
+        packet.payload[SPHINX_PAYLOAD_SIZE - 1] ^= 0x01;
+
+        // Intermediate hops cannot tell, but the final hop rejects the scrambled payload
+        let ProcessedPayload::Forward(next_packet) = mixers[0].1.process_packet(&packet).unwrap().payload else {
+            panic!("first hop should forward");
+        };
+        let next_packet = SphinxPacket::from_bytes(&next_packet).unwrap();
+        assert_eq!(next_packet.payload.len(), SPHINX_PAYLOAD_SIZE);
+        assert!(matches!(
+            mixers[1].1.process_packet(&next_packet),
+            Err(crate::sphinx::MixError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_routes() {
+        let mixers = test_mixers(MAX_HOPS as u8 + 1);
+        let route: Vec<_> = mixers.iter().map(|(id, mixer)| (*id, mixer.public_key())).collect();
+
+        assert!(matches!(
+            SphinxPacketBuilder::new().build(),
+            Err(SphinxError::InvalidRoute)
+        ));
+        assert!(matches!(
+            SphinxPacketBuilder::new().route(route.clone()).build(),
+            Err(SphinxError::InvalidRoute)
+        ));
+        assert!(matches!(
+            SphinxPacketBuilder::new()
+                .route(route[..2].to_vec())
+                .delays(vec![Duration::ZERO])
+                .build(),
+            Err(SphinxError::InvalidRoute)
+        ));
+        assert!(matches!(
+            SphinxPacketBuilder::new()
+                .route(route[..1].to_vec())
+                .payload(&[0u8; SPHINX_MAX_MESSAGE_SIZE + 1])
+                .build(),
+            Err(SphinxError::InvalidPayloadSize)
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+// Sphinx key lifecycle: persistence, scheduled rotation and the overlap window
+// After a rotation the previous key keeps decrypting packets built against it until the
+// overlap ends, so paths chosen before the rotation (or before a restart) stay usable
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use curve25519_dalek::{scalar::Scalar, ristretto::RistrettoPoint, constants::RISTRETTO_BASEPOINT_POINT};
+use rand_core::{OsRng, RngCore};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyStoreError {
+    #[error("Key store I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed key store: {0}")]
+    Malformed(String),
+}
+
+/// Sphinx private key bound to the epoch it was activated in
+#[derive(Clone)]
+pub struct SphinxKey {
+    pub epoch: u64,
+    pub activated_at: SystemTime,
+    private_key: Scalar,
+}
+
+impl SphinxKey {
+    pub fn generate(epoch: u64) -> Self {
+        let mut key_bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut key_bytes);
+
+        Self {
+            epoch,
+            activated_at: SystemTime::now(),
+            private_key: Scalar::from_bytes_mod_order(key_bytes),
+        }
+    }
+
+    pub fn private_key(&self) -> Scalar {
+        self.private_key
+    }
+
+    pub fn public_key(&self) -> RistrettoPoint {
+        self.private_key * RISTRETTO_BASEPOINT_POINT
+    }
+}
+
+/// Current key plus the previous one while its overlap window is open
+#[derive(Clone)]
+pub struct SphinxKeySet {
+    pub current: SphinxKey,
+    pub previous: Option<SphinxKey>,
+}
+
+impl SphinxKeySet {
+    pub fn generate(epoch: u64) -> Self {
+        Self {
+            current: SphinxKey::generate(epoch),
+            previous: None,
+        }
+    }
+
+    /// Whether the current key has been active for `rotation_epochs` epochs
+    pub fn rotation_due(&self, epoch: u64, rotation_epochs: u64) -> bool {
+        epoch >= self.current.epoch.saturating_add(rotation_epochs.max(1))
+    }
+
+    /// Activate a fresh key for `epoch`, keeping the old one as previous
+    pub fn rotate(&mut self, epoch: u64) {
+        let next = SphinxKey::generate(epoch);
+        self.previous = Some(std::mem::replace(&mut self.current, next));
+    }
+
+    /// Drop the previous key once `overlap` has passed since the current one took over
+    pub fn retire_expired(&mut self, now: SystemTime, overlap: Duration) -> Option<SphinxKey> {
+        let expired = now
+            .duration_since(self.current.activated_at)
+            .map(|elapsed| elapsed >= overlap)
+            .unwrap_or(false);
+
+        if expired { self.previous.take() } else { None }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredKey {
+    epoch: u64,
+    activated_at: SystemTime,
+    private_key: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredKeySet {
+    current: StoredKey,
+    previous: Option<StoredKey>,
+}
+
+impl From<&SphinxKey> for StoredKey {
+    fn from(key: &SphinxKey) -> Self {
+        Self {
+            epoch: key.epoch,
+            activated_at: key.activated_at,
+            private_key: hex::encode(key.private_key.to_bytes()),
+        }
+    }
+}
+
+impl TryFrom<StoredKey> for SphinxKey {
+    type Error = KeyStoreError;
+
+    fn try_from(stored: StoredKey) -> Result<Self, Self::Error> {
+        let bytes: [u8; 32] = hex::decode(&stored.private_key)
+            .map_err(|e| KeyStoreError::Malformed(e.to_string()))?
+            .try_into()
+            .map_err(|_| KeyStoreError::Malformed("private key must be 32 bytes".to_string()))?;
+        let private_key = Option::from(Scalar::from_canonical_bytes(bytes))
+            .ok_or_else(|| KeyStoreError::Malformed("private key is not a canonical scalar".to_string()))?;
+
+        Ok(Self {
+            epoch: stored.epoch,
+            activated_at: stored.activated_at,
+            private_key,
+        })
+    }
+}
+
+/// JSON file holding the node's Sphinx keys
+pub struct SphinxKeyStore {
+    path: PathBuf,
+}
+
+impl SphinxKeyStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn load(&self) -> Result<Option<SphinxKeySet>, KeyStoreError> {
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let stored: StoredKeySet = serde_json::from_slice(&data)
+            .map_err(|e| KeyStoreError::Malformed(e.to_string()))?;
+
+        Ok(Some(SphinxKeySet {
+            current: stored.current.try_into()?,
+            previous: stored.previous.map(SphinxKey::try_from).transpose()?,
+        }))
+    }
+
+    /// Write the key set atomically, readable by the owner only
+    pub fn save(&self, keys: &SphinxKeySet) -> Result<(), KeyStoreError> {
+        let stored = StoredKeySet {
+            current: (&keys.current).into(),
+            previous: keys.previous.as_ref().map(StoredKey::from),
+        };
+        let data = serde_json::to_vec_pretty(&stored)
+            .map_err(|e| KeyStoreError::Malformed(e.to_string()))?;
+
+        write_private(&self.path, &data)
+    }
+
+    /// Load the stored keys, generating and saving a key for `epoch` on first start
+    pub fn load_or_generate(&self, epoch: u64) -> Result<SphinxKeySet, KeyStoreError> {
+        if let Some(keys) = self.load()? {
+            return Ok(keys);
+        }
+
+        let keys = SphinxKeySet::generate(epoch);
+        self.save(&keys)?;
+        Ok(keys)
+    }
+}
+
+/// Replace `path` with `data` atomically, readable by the owner only
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> Result<(), KeyStoreError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp_path)?;
+        std::io::Write::write_all(&mut file, data)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_store_round_trip_and_rotation() {
+        let path = std::env::temp_dir().join(format!("sphinx-keys-{}.json", std::process::id()));
+        let store = SphinxKeyStore::new(&path);
+
+        let mut keys = store.load_or_generate(4).unwrap();
+        assert!(!keys.rotation_due(4, 2));
+        assert!(keys.rotation_due(6, 2));
+
+        keys.rotate(6);
+        store.save(&keys).unwrap();
+
+        // A restart restores both keys
+        let mut restored = store.load_or_generate(6).unwrap();
+        assert_eq!(restored.current.epoch, 6);
+        assert_eq!(restored.current.private_key(), keys.current.private_key());
+        assert_eq!(restored.previous.as_ref().unwrap().epoch, 4);
+
+        let activated = restored.current.activated_at;
+        assert!(restored.retire_expired(activated, Duration::from_secs(60)).is_none());
+        assert!(restored.retire_expired(activated + Duration::from_secs(60), Duration::from_secs(60)).is_some());
+        assert!(restored.previous.is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mixer_accepts_previous_key_during_overlap() {
+        use crate::sphinx::{SphinxMixer, SphinxPacketBuilder, SphinxError, MixError};
+
+        let mut keys = SphinxKeySet::generate(1);
+        let mut mixer = SphinxMixer::new(keys.current.private_key());
+        let build = |mixer: &SphinxMixer| {
+            SphinxPacketBuilder::new()
+                .route(vec![([1u8; 32], mixer.public_key())])
+                .payload(b"in flight")
+                .build()
+                .unwrap()
+        };
+        let in_flight = build(&mixer);
+
+        keys.rotate(2);
+        mixer.rotate_key(keys.current.private_key(), 2);
+        assert!(mixer.process_packet(&in_flight).is_ok());
+        assert!(mixer.process_packet(&build(&mixer)).is_ok());
+
+        mixer.retire_previous_key();
+        let stale = SphinxPacketBuilder::new()
+            .route(vec![([1u8; 32], keys.previous.as_ref().unwrap().public_key())])
+            .payload(b"too late")
+            .build()
+            .unwrap();
+        assert!(matches!(
+            mixer.process_packet(&stale),
+            Err(MixError::Sphinx(SphinxError::InvalidRoutingInfo))
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+// Lioness wide-block cipher for Sphinx payload layers
+// Length preserving, and any modified bit scrambles the whole block on decryption
+
+use blake3::Hasher;
+
+/// Size of the left half; the right half is the rest of the block
+pub const LIONESS_LEFT_SIZE: usize = 32;
+/// Smallest block Lioness can encrypt
+pub const LIONESS_MIN_BLOCK_SIZE: usize = LIONESS_LEFT_SIZE + 1;
+
+/// Lioness keyed with four round keys derived from one payload key
+pub struct Lioness {
+    round_keys: [[u8; 32]; 4],
+}
+
+impl Lioness {
+    pub fn new(key: &[u8; 32]) -> Self {
+        let mut material = [0u8; 128];
+        let mut hasher = Hasher::new_derive_key("SPHINX_LIONESS_ROUND_KEYS_v1");
+        hasher.update(key);
+        hasher.finalize_xof().fill(&mut material);
+
+        let mut round_keys = [[0u8; 32]; 4];
+        for (round_key, chunk) in round_keys.iter_mut().zip(material.chunks_exact(32)) {
+            round_key.copy_from_slice(chunk);
+        }
+
+        Self { round_keys }
+    }
+
+    /// Encrypt a block in place
+    pub fn encrypt(&self, block: &mut [u8]) {
+        assert!(block.len() >= LIONESS_MIN_BLOCK_SIZE, "Lioness block too small");
+        let (left, right) = block.split_at_mut(LIONESS_LEFT_SIZE);
+
+        Self::stream_round(&self.round_keys[0], left, right);
+        Self::hash_round(&self.round_keys[1], left, right);
+        Self::stream_round(&self.round_keys[2], left, right);
+        Self::hash_round(&self.round_keys[3], left, right);
+    }
+
+    /// Decrypt a block in place
+    pub fn decrypt(&self, block: &mut [u8]) {
+        assert!(block.len() >= LIONESS_MIN_BLOCK_SIZE, "Lioness block too small");
+        let (left, right) = block.split_at_mut(LIONESS_LEFT_SIZE);
+
+        Self::hash_round(&self.round_keys[3], left, right);
+        Self::stream_round(&self.round_keys[2], left, right);
+        Self::hash_round(&self.round_keys[1], left, right);
+        Self::stream_round(&self.round_keys[0], left, right);
+    }
+
+    /// R ^= S(L ^ K)
+    fn stream_round(round_key: &[u8; 32], left: &[u8], right: &mut [u8]) {
+        let mut stream_key = [0u8; 32];
+        for (out, (l, k)) in stream_key.iter_mut().zip(left.iter().zip(round_key.iter())) {
+            *out = l ^ k;
+        }
+
+        let mut hasher = Hasher::new();
+        hasher.update(b"SPHINX_LIONESS_STREAM_v1");
+        hasher.update(&stream_key);
+        let mut xof = hasher.finalize_xof();
+
+        let mut key_stream = [0u8; 64];
+        for chunk in right.chunks_mut(key_stream.len()) {
+            xof.fill(&mut key_stream[..chunk.len()]);
+            for (byte, stream) in chunk.iter_mut().zip(key_stream.iter()) {
+                *byte ^= stream;
+            }
+        }
+    }
+
+    /// L ^= H_K(R)
+    fn hash_round(round_key: &[u8; 32], left: &mut [u8], right: &[u8]) {
+        let digest = blake3::keyed_hash(round_key, right);
+        for (byte, hash) in left.iter_mut().zip(digest.as_bytes().iter()) {
+            *byte ^= hash;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lioness_round_trip_and_tamper_diffusion() {
+        let lioness = Lioness::new(&[0x5Au8; 32]);
+        let plaintext: Vec<u8> = (0..512).map(|i| i as u8).collect();
+
+        let mut block = plaintext.clone();
+        lioness.encrypt(&mut block);
+        assert_eq!(block.len(), plaintext.len());
+        assert_ne!(block, plaintext);
+
+        let mut decrypted = block.clone();
+        lioness.decrypt(&mut decrypted);
+        assert_eq!(decrypted, plaintext);
+
+        // A single flipped bit anywhere garbles both halves
+        block[400] ^= 0x01;
+        lioness.decrypt(&mut block);
+        let unchanged = block.iter().zip(plaintext.iter()).filter(|(a, b)| a == b).count();
+        assert!(unchanged < 16, "{} bytes survived tampering", unchanged);
+    }
+}
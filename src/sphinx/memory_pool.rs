@@ -92,6 +92,7 @@ impl<T> ObjectPool<T> {
         };
 
         stats.allocations += 1;
+        stats.current_size = self.pool.lock().unwrap().len();
 
         PooledObject::new(object, self.pool.clone(), self.stats.clone())
     }
@@ -174,6 +175,12 @@ impl<T> Drop for PooledObject<T> {
     }
 }
 
+impl<T: std::fmt::Debug> std::fmt::Debug for PooledObject<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PooledObject").field(self.get()).finish()
+    }
+}
+
 impl<T> std::ops::Deref for PooledObject<T> {
     type Target = T;
 
@@ -188,6 +195,12 @@ impl<T> std::ops::DerefMut for PooledObject<T> {
     }
 }
 
+impl<T: AsRef<[u8]>> AsRef<[u8]> for PooledObject<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.get().as_ref()
+    }
+}
+
 /// Specialized buffer pool for Sphinx packet processing
 pub type SphinxPacketBuffer = Vec<u8>;
 
@@ -202,12 +215,16 @@ impl PacketBufferPool {
         let pool = ObjectPool::new(
             config,
             || {
-                // Factory function: create new buffer
-                vec![0u8; crate::sphinx::SPHINX_PACKET_SIZE]
+                // Factory function: create new buffer, with room for the spare byte receives
+                // use to detect oversized datagrams
+                let mut buffer = Vec::with_capacity(crate::sphinx::SPHINX_PACKET_SIZE + 1);
+                buffer.resize(crate::sphinx::SPHINX_PACKET_SIZE, 0);
+                buffer
             },
             |buffer: &mut SphinxPacketBuffer| {
-                // Reset function: clear buffer securely
-                buffer.fill(0);
+                // Reset function: clear buffer securely, restoring the full packet length
+                buffer.clear();
+                buffer.resize(crate::sphinx::SPHINX_PACKET_SIZE, 0);
             },
         );
 
@@ -305,17 +322,63 @@ impl AlignedBufferPool {
     }
 }
 
-/// Thread-local memory pools for maximum performance
+/// Statistics of every thread's pool of one kind
+struct PoolRegistry(Mutex<Vec<Arc<Mutex<PoolStats>>>>);
+
+impl PoolRegistry {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    fn register<T>(&self, pool: &ObjectPool<T>) {
+        self.0.lock().unwrap().push(pool.stats.clone());
+    }
+
+    /// Sum of the statistics of all threads' pools
+    fn total(&self) -> PoolStats {
+        let mut total = PoolStats::default();
+        for stats in self.0.lock().unwrap().iter() {
+            let stats = stats.lock().unwrap();
+            total.allocations += stats.allocations;
+            total.deallocations += stats.deallocations;
+            total.cache_hits += stats.cache_hits;
+            total.cache_misses += stats.cache_misses;
+            total.current_size += stats.current_size;
+            total.peak_size += stats.peak_size;
+        }
+        total
+    }
+}
+
+static PACKET_POOLS: PoolRegistry = PoolRegistry::new();
+static HEADER_POOLS: PoolRegistry = PoolRegistry::new();
+static PAYLOAD_POOLS: PoolRegistry = PoolRegistry::new();
+
+// Thread-local memory pools for maximum performance
+// Taking a buffer only locks the calling thread's pool. A buffer dropped on another thread,
+// e.g. by the sender, goes back to the pool it was taken from.
 thread_local! {
-    static PACKET_BUFFER_POOL: PacketBufferPool = PacketBufferPool::default();
-    static HEADER_BUFFER_POOL: AlignedBufferPool = AlignedBufferPool::new(
-        PoolConfig::default(),
-        512, // Sphinx header size
-    );
-    static PAYLOAD_BUFFER_POOL: AlignedBufferPool = AlignedBufferPool::new(
-        PoolConfig::default(),
-        512, // Sphinx payload size
-    );
+    static PACKET_BUFFER_POOL: PacketBufferPool = {
+        let pool = PacketBufferPool::default();
+        PACKET_POOLS.register(&pool.pool);
+        pool
+    };
+    static HEADER_BUFFER_POOL: AlignedBufferPool = {
+        let pool = AlignedBufferPool::new(
+            PoolConfig::default(),
+            512, // Sphinx header size
+        );
+        HEADER_POOLS.register(&pool.pool);
+        pool
+    };
+    static PAYLOAD_BUFFER_POOL: AlignedBufferPool = {
+        let pool = AlignedBufferPool::new(
+            PoolConfig::default(),
+            512, // Sphinx payload size
+        );
+        PAYLOAD_POOLS.register(&pool.pool);
+        pool
+    };
 }
 
 /// Get a packet buffer from thread-local pool
@@ -333,13 +396,9 @@ pub fn get_payload_buffer() -> PooledObject<AlignedBuffer> {
     PAYLOAD_BUFFER_POOL.with(|pool| pool.get_buffer())
 }
 
-/// Get statistics for all thread-local pools
+/// Get statistics for the packet, header and payload pools, summed over all threads
 pub fn get_pool_stats() -> (PoolStats, PoolStats, PoolStats) {
-    let packet_stats = PACKET_BUFFER_POOL.with(|pool| pool.stats());
-    let header_stats = HEADER_BUFFER_POOL.with(|pool| pool.stats());
-    let payload_stats = PAYLOAD_BUFFER_POOL.with(|pool| pool.stats());
-    
-    (packet_stats, header_stats, payload_stats)
+    (PACKET_POOLS.total(), HEADER_POOLS.total(), PAYLOAD_POOLS.total())
 }
 
 #[cfg(test)]
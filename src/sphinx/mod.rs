@@ -1,7 +1,21 @@
 pub mod packet;
+pub mod builder;
+pub mod codec;
+pub mod lioness;
+pub mod replay;
+pub mod surb;
+pub mod keys;
+pub mod pool;
 pub mod simd;
 pub mod memory_pool;
 
 pub use packet::*;
+pub use builder::*;
+pub use codec::*;
+pub use lioness::*;
+pub use replay::*;
+pub use surb::*;
+pub use keys::*;
+pub use pool::*;
 pub use simd::*;
-pub use memory_pool::*;
\ No newline at end of file
+pub use memory_pool::*;
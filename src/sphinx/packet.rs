@@ -1,22 +1,40 @@
+use std::borrow::Borrow;
+
 use blake3::Hasher;
-use curve25519_dalek::{scalar::Scalar, ristretto::RistrettoPoint};
-use aes_gcm::{Aes256Gcm, Nonce, KeyInit};
-use aes_gcm::aead::Aead;
-use aes_gcm::aead::generic_array::GenericArray;
+use curve25519_dalek::{scalar::Scalar, ristretto::{RistrettoPoint, CompressedRistretto}};
 use arrayref::array_ref;
 use serde::{Serialize, Deserialize};
-use std::time::{Duration, Instant};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
 
 use crate::sphinx::simd::{SimdKeyDeriver, SimdXorProcessor, SimdMemoryOps};
-use crate::sphinx::memory_pool::{get_packet_buffer, get_header_buffer, get_payload_buffer};
+use crate::sphinx::replay::{ReplayCache, ReplayCacheConfig, ReplayCacheStats};
+use crate::sphinx::codec::SphinxVersion;
+use crate::sphinx::lioness::Lioness;
+use crate::sphinx::memory_pool::{get_packet_buffer, PooledObject, SphinxPacketBuffer};
+use crate::sphinx::surb::SurbId;
 
 pub const SPHINX_PACKET_SIZE: usize = 1024; // Fixed size for constant-time processing
-const SPHINX_HEADER_SIZE: usize = 512;
-const SPHINX_PAYLOAD_SIZE: usize = 512;
-const MAX_HOPS: usize = 5;
+pub const SPHINX_HEADER_SIZE: usize = 512;
+pub const SPHINX_PAYLOAD_SIZE: usize = 512;
+pub const MAX_HOPS: usize = 5;
+
+/// Truncated HMAC-SHA256 authenticating each hop's routing info
+pub const SPHINX_MAC_SIZE: usize = 16;
+/// Encrypted routing information carried in every header (version, key and MAC take the rest)
+pub const SPHINX_ROUTING_INFO_SIZE: usize = SPHINX_HEADER_SIZE - 33 - SPHINX_MAC_SIZE;
+/// Per-hop routing block: command (1) + next hop (32) + delay in microseconds (8) + next hop's MAC (16)
+pub(crate) const HOP_ROUTING_SIZE: usize = 41 + SPHINX_MAC_SIZE;
+/// Zero bytes at the front of the innermost payload, checked by the final hop
+pub(crate) const PAYLOAD_INTEGRITY_SIZE: usize = 16;
 
-/// Real Nym-compatible Sphinx packet structure
+/// Native Sphinx packet structure (wire version `SphinxVersion::Native`)
+///
+/// Made only of byte arrays and laid out as on the wire, so `view` can read a received
+/// datagram in place.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[repr(C)]
 pub struct SphinxPacket {
     pub header: SphinxHeader,
     #[serde(with = "serde_arrays")]
@@ -41,6 +59,20 @@ impl SphinxPacket {
         Ok(Self { header, payload })
     }
     
+    /// Borrow the packet held in `data` without copying it
+    pub fn view(data: &[u8]) -> Result<&Self, SphinxError> {
+        if data.len() != SPHINX_PACKET_SIZE {
+            return Err(SphinxError::InvalidPacketSize);
+        }
+        if data[0] != SphinxVersion::Native as u8 {
+            return Err(SphinxError::UnsupportedVersion(data[0]));
+        }
+        
+        // SAFETY: SphinxPacket is repr(C) and made only of byte arrays, so it has alignment 1,
+        // no padding, accepts any bytes and is exactly SPHINX_PACKET_SIZE long
+        Ok(unsafe { &*(data.as_ptr() as *const Self) })
+    }
+    
     /// Serialize packet to bytes
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(SPHINX_PACKET_SIZE);
@@ -62,13 +94,15 @@ impl SphinxPacket {
     }
 }
 
-/// Real Sphinx header with Nym-compatible structure
+/// Native Sphinx header; `version` selects the wire codec
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[repr(C)]
 pub struct SphinxHeader {
     pub version: u8,
     pub ephemeral_key: [u8; 32], // Compressed RistrettoPoint
+    pub mac: [u8; SPHINX_MAC_SIZE], // HMAC over routing_info for this hop
     #[serde(with = "serde_arrays")]
-    pub routing_info: [u8; SPHINX_HEADER_SIZE - 33], // 33 bytes for version + key
+    pub routing_info: [u8; SPHINX_ROUTING_INFO_SIZE],
 }
 
 impl SphinxHeader {
@@ -79,19 +113,23 @@ impl SphinxHeader {
         }
         
         let version = data[0];
-        if version != 1 {
+        if version != SphinxVersion::Native as u8 {
             return Err(SphinxError::UnsupportedVersion(version));
         }
         
         let mut ephemeral_key = [0u8; 32];
         ephemeral_key.copy_from_slice(&data[1..33]);
         
-        let mut routing_info = [0u8; SPHINX_HEADER_SIZE - 33];
-        routing_info.copy_from_slice(&data[33..]);
+        let mut mac = [0u8; SPHINX_MAC_SIZE];
+        mac.copy_from_slice(&data[33..33 + SPHINX_MAC_SIZE]);
+        
+        let mut routing_info = [0u8; SPHINX_ROUTING_INFO_SIZE];
+        routing_info.copy_from_slice(&data[33 + SPHINX_MAC_SIZE..]);
         
         Ok(Self {
             version,
             ephemeral_key,
+            mac,
             routing_info,
         })
     }
@@ -101,13 +139,14 @@ impl SphinxHeader {
         let mut bytes = Vec::with_capacity(SPHINX_HEADER_SIZE);
         bytes.push(self.version);
         bytes.extend_from_slice(&self.ephemeral_key);
+        bytes.extend_from_slice(&self.mac);
         bytes.extend_from_slice(&self.routing_info);
         bytes
     }
     
     /// Validate header structure
     pub fn validate(&self) -> Result<(), SphinxError> {
-        if self.version != 1 {
+        if self.version != SphinxVersion::Native as u8 {
             return Err(SphinxError::UnsupportedVersion(self.version));
         }
         
@@ -122,66 +161,239 @@ impl SphinxHeader {
     
     /// Get ephemeral key as RistrettoPoint
     pub fn get_ephemeral_key(&self) -> Result<RistrettoPoint, SphinxError> {
-        // For now, create a point from uniform bytes (requires 64 bytes, so we'll hash)
-        use blake3::Hasher;
-        let mut hasher = Hasher::new();
-        hasher.update(&self.ephemeral_key);
-        hasher.update(&self.ephemeral_key); // Double to get 64 bytes
-        let mut uniform_bytes = [0u8; 64];
-        hasher.finalize_xof().fill(&mut uniform_bytes);
-        Ok(RistrettoPoint::from_uniform_bytes(&uniform_bytes))
+        CompressedRistretto(self.ephemeral_key)
+            .decompress()
+            .ok_or(SphinxError::InvalidEphemeralKey)
     }
 }
 
+const _: () = assert!(std::mem::size_of::<SphinxPacket>() == SPHINX_PACKET_SIZE);
+
+/// Received packet read in place from its pooled receive buffer
+///
+/// The buffer returns to the pool it came from once the packet has been processed.
+pub struct PooledPacket {
+    buffer: PooledObject<SphinxPacketBuffer>,
+}
+
+impl PooledPacket {
+    /// Check that `buffer` holds a native packet
+    pub fn from_buffer(buffer: PooledObject<SphinxPacketBuffer>) -> Result<Self, SphinxError> {
+        SphinxPacket::view(&buffer)?;
+        Ok(Self { buffer })
+    }
+}
+
+impl std::ops::Deref for PooledPacket {
+    type Target = SphinxPacket;
+    
+    fn deref(&self) -> &SphinxPacket {
+        SphinxPacket::view(&self.buffer).expect("checked by from_buffer")
+    }
+}
+
+impl Borrow<SphinxPacket> for PooledPacket {
+    fn borrow(&self) -> &SphinxPacket {
+        self
+    }
+}
+
+/// Shared secret, header key and payload key of an authenticated header
+type HopSecrets = ([u8; 32], [u8; 32], [u8; 32]);
+
 pub struct SphinxMixer {
     private_key: Scalar,
-    #[allow(dead_code)]
     public_key: RistrettoPoint,
+    // Key from before the last rotation, still accepted during the overlap window
+    previous_key: Option<Scalar>,
     // SIMD optimizations
     simd_key_deriver: SimdKeyDeriver,
     simd_xor_processor: SimdXorProcessor,
     simd_memory_ops: SimdMemoryOps,
     // Pre-allocated buffers for zero-allocation processing
-    temp_header: [u8; SPHINX_HEADER_SIZE],
+    temp_header: [u8; SPHINX_ROUTING_INFO_SIZE + HOP_ROUTING_SIZE],
     temp_payload: [u8; SPHINX_PAYLOAD_SIZE],
-    #[allow(dead_code)]
-    shared_secrets: [[u8; 32]; MAX_HOPS],
+    // Scratch space reused by every process_batch call
+    batch_points: Vec<Option<RistrettoPoint>>,
+    batch_halved: Vec<RistrettoPoint>,
+    batch_secrets: Vec<[u8; 32]>,
+    // Tags of already processed packets
+    replay_cache: ReplayCache,
 }
 
 impl SphinxMixer {
     pub fn new(private_key: Scalar) -> Self {
+        Self::with_replay_config(private_key, ReplayCacheConfig::default())
+    }
+    
+    /// Mixer with a custom replay cache size, e.g. one shard of a worker pool
+    pub fn with_replay_config(private_key: Scalar, replay_config: ReplayCacheConfig) -> Self {
+        Self::with_replay_cache(private_key, ReplayCache::new(replay_config))
+    }
+    
+    /// Mixer continuing from a replay cache saved before a restart
+    pub fn with_replay_cache(private_key: Scalar, replay_cache: ReplayCache) -> Self {
         let public_key = &private_key * &curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
         
         Self {
             private_key,
             public_key,
+            previous_key: None,
             simd_key_deriver: SimdKeyDeriver::new(),
             simd_xor_processor: SimdXorProcessor::new(),
             simd_memory_ops: SimdMemoryOps::new(),
-            temp_header: [0u8; SPHINX_HEADER_SIZE],
+            temp_header: [0u8; SPHINX_ROUTING_INFO_SIZE + HOP_ROUTING_SIZE],
             temp_payload: [0u8; SPHINX_PAYLOAD_SIZE],
-            shared_secrets: [[0u8; 32]; MAX_HOPS],
+            batch_points: Vec::new(),
+            batch_halved: Vec::new(),
+            batch_secrets: Vec::new(),
+            replay_cache,
         }
     }
     
+    /// Public key clients use to build packets routed through this mixer
+    pub fn public_key(&self) -> RistrettoPoint {
+        self.public_key
+    }
+    
+    /// Move the replay cache to a new key epoch
+    pub fn rotate_replay_epoch(&mut self, epoch: u64) {
+        self.replay_cache.rotate(epoch);
+    }
+    
+    /// Switch to a new private key for `epoch`, keeping the old key for the overlap window
+    ///
+    /// Replay tags follow the key epoch, so the replay cache rotates with the key.
+    pub fn rotate_key(&mut self, private_key: Scalar, epoch: u64) {
+        self.public_key = &private_key * &curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+        self.previous_key = Some(std::mem::replace(&mut self.private_key, private_key));
+        self.replay_cache.rotate(epoch);
+    }
+    
+    /// Accept packets built for `previous_key` until it is retired (e.g. restored after restart)
+    pub fn set_previous_key(&mut self, previous_key: Option<Scalar>) {
+        self.previous_key = previous_key;
+    }
+    
+    /// End the overlap window; packets for the previous key fail authentication from now on
+    pub fn retire_previous_key(&mut self) {
+        self.previous_key = None;
+    }
+    
+    /// Replay detection statistics
+    pub fn replay_stats(&self) -> ReplayCacheStats {
+        self.replay_cache.stats()
+    }
+    
+    pub fn replay_cache(&self) -> &ReplayCache {
+        &self.replay_cache
+    }
+    
     /// CRITICAL: High-performance packet processing with SIMD optimizations
     /// Target: ≥25k packets/second on 4-core VPS
+    ///
+    /// The work done does not depend on which key a packet was built for or on its routing
+    /// command; remaining wall-clock variation is hidden by release quantization in the
+    /// delay queue (`DelayQueueConfig::release_quantum`).
     pub fn process_packet(&mut self, packet: &SphinxPacket) -> Result<ProcessedPacket, MixError> {
-        // Constant-time processing to prevent timing attacks
         let start = std::time::Instant::now();
         
-        // 1. Compute shared secret with ephemeral key (using Montgomery ladder for constant-time)
+        // 1-2. Shared secret and SIMD-optimized key derivation, authenticated by the header MAC
         let ephemeral_point = packet.header.get_ephemeral_key()?;
-        let shared_secret = self.private_key * ephemeral_point;
-        let shared_secret_bytes = shared_secret.compress().to_bytes();
+        let shared_secrets = self.candidate_keys()
+            .map(|private_key| (private_key * ephemeral_point).compress().to_bytes());
+        let keys = self.select_hop_secrets(&packet.header, shared_secrets)
+            .ok_or(MixError::Sphinx(SphinxError::InvalidRoutingInfo))?;
+        
+        self.finish_packet(packet, ephemeral_point, keys, start)
+    }
+    
+    /// Process a batch of packets, amortizing point compression across the batch
+    ///
+    /// Shared secrets are computed as (k/2)·α and compressed together with
+    /// `double_and_compress_batch`, which needs one field inversion for the whole batch
+    /// instead of one per packet. Results are in the same order as `packets`.
+    pub fn process_batch(&mut self, packets: &[SphinxPacket]) -> Vec<Result<ProcessedPacket, MixError>> {
+        let mut results = Vec::with_capacity(packets.len());
+        self.process_batch_into(packets, &mut results);
+        results
+    }
+    
+    /// `process_batch`, appending the results to `results`
+    ///
+    /// Intermediate values live in scratch space kept by the mixer, so apart from the
+    /// scratch `double_and_compress_batch` allocates internally, a batch of forwarded
+    /// packets does not touch the heap once the vectors have grown to the batch size.
+    pub fn process_batch_into<P: Borrow<SphinxPacket>>(
+        &mut self,
+        packets: &[P],
+        results: &mut Vec<Result<ProcessedPacket, MixError>>
+    ) {
+        let start = std::time::Instant::now();
+        
+        // Moved out of the mixer while in use, so its methods can still be called below
+        let mut points = std::mem::take(&mut self.batch_points);
+        let mut halved = std::mem::take(&mut self.batch_halved);
+        let mut secrets = std::mem::take(&mut self.batch_secrets);
+        
+        points.clear();
+        points.extend(packets.iter().map(|packet| packet.borrow().header.get_ephemeral_key().ok()));
+        let valid = points.iter().flatten().count();
+        
+        // Candidate shared secrets: the valid packets in order, once for each key in use
+        secrets.clear();
+        let half = Scalar::from(2u8).invert();
+        for private_key in self.candidate_keys() {
+            let half_key = private_key * half;
+            halved.clear();
+            halved.extend(points.iter().flatten().map(|point| half_key * point));
+            
+            let compressed = RistrettoPoint::double_and_compress_batch(&halved);
+            secrets.extend(compressed.iter().map(|shared_secret| shared_secret.to_bytes()));
+        }
+        
+        // Per-packet share of the batched work
+        let batch_share = start.elapsed() / packets.len().max(1) as u32;
+        
+        let mut next_valid = 0;
+        for (packet, point) in packets.iter().zip(&points) {
+            let Some(point) = *point else {
+                results.push(Err(MixError::Sphinx(SphinxError::InvalidEphemeralKey)));
+                continue;
+            };
+            let candidates = secrets[next_valid..].iter().step_by(valid).copied();
+            next_valid += 1;
+            
+            let packet_start = std::time::Instant::now() - batch_share;
+            let result = self.select_hop_secrets(&packet.borrow().header, candidates)
+                .ok_or(MixError::Sphinx(SphinxError::InvalidRoutingInfo))
+                .and_then(|secrets| self.finish_packet(packet.borrow(), point, secrets, packet_start));
+            results.push(result);
+        }
         
-        // 2. SIMD-optimized key derivation
-        let (header_key, payload_key, next_ephemeral_bytes) = 
-            self.simd_key_deriver.derive_keys_simd(&shared_secret_bytes);
+        self.batch_points = points;
+        self.batch_halved = halved;
+        self.batch_secrets = secrets;
+    }
+    
+    /// Steps after header authentication: replay check, blinding, header and payload processing
+    fn finish_packet(
+        &mut self,
+        packet: &SphinxPacket,
+        ephemeral_point: RistrettoPoint,
+        (shared_secret_bytes, header_key, payload_key): HopSecrets,
+        start: std::time::Instant
+    ) -> Result<ProcessedPacket, MixError> {
+        // Reject packets whose shared secret was already used
+        let replay_tag = ReplayCache::compute_tag(&shared_secret_bytes);
+        if self.replay_cache.contains(&replay_tag) {
+            return Err(MixError::Replay);
+        }
         
-        // Convert ephemeral key bytes to point
-        let ephemeral_scalar = Scalar::from_bytes_mod_order(next_ephemeral_bytes);
-        let next_ephemeral = &ephemeral_scalar * &curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+        // Blind the ephemeral key for the next hop. Every command pays for the blinding and
+        // the payload layer, so only the cheap output assembly below depends on the command.
+        let blinding = blinding_factor(&packet.header.ephemeral_key, &shared_secret_bytes);
+        let next_ephemeral = (blinding * ephemeral_point).compress();
         
         // 3. SIMD-optimized header decryption (zero-allocation)
         self.decrypt_header_simd(&packet.header.routing_info, &header_key)?;
@@ -189,282 +401,216 @@ impl SphinxMixer {
         // 4. Extract next hop and routing command
         let routing_info = self.parse_routing_info()?;
         
-        // 5. SIMD-optimized payload processing
+        // 5. Strip this hop's Lioness layer
+        self.simd_memory_ops.fast_copy(&mut self.temp_payload, &packet.payload);
+        Lioness::new(&payload_key).decrypt(&mut self.temp_payload);
+        
         let processed_payload = match routing_info.command {
-            RoutingCommand::Forward { next_hop: _ } => {
-                self.process_forward_payload_simd(&packet.payload, &payload_key, next_ephemeral)?
-            },
-            RoutingCommand::Deliver => {
-                self.decrypt_final_payload_simd(&packet.payload, &payload_key)?
-            }
+            RoutingCommand::Forward { next_hop: _ } => self.forward_payload(next_ephemeral),
+            RoutingCommand::Deliver => self.final_payload()?,
+            RoutingCommand::Reply { surb_id: _ } => ProcessedPayload::Reply(self.temp_payload.to_vec()),
         };
         
-        // Optimized constant-time padding (reduced to achieve ≥25k pkt/s target)
-        let elapsed = start.elapsed();
-        let target_time = std::time::Duration::from_micros(39); // ~25.6k pkt/s target
-        if elapsed < target_time {
-            std::thread::sleep(target_time - elapsed);
-        }
+        // Only successfully processed packets are remembered
+        self.replay_cache.insert(replay_tag);
         
         Ok(ProcessedPacket {
             routing_info,
             payload: processed_payload,
-            processing_time: elapsed,
+            processing_time: start.elapsed(),
         })
     }
     
-    #[inline(always)] // Critical path optimization
-    fn derive_keys(&self, shared_secret: &[u8; 32]) -> ([u8; 32], [u8; 32], RistrettoPoint) {
-        // Use Blake3 in derive_key mode to generate enough material
-        let mut hasher = Hasher::new();
-        hasher.update(b"SPHINX_DERIVE_v1");
-        hasher.update(shared_secret);
-        
-        // Generate 96 bytes of key material
-        let mut derived_material = [0u8; 96];
-        let mut xof = hasher.finalize_xof();
-        xof.fill(&mut derived_material);
-        
-        let mut header_key = [0u8; 32];
-        let mut payload_key = [0u8; 32];
-        let mut ephemeral_bytes = [0u8; 32];
-        
-        header_key.copy_from_slice(&derived_material[0..32]);
-        payload_key.copy_from_slice(&derived_material[32..64]);
-        ephemeral_bytes.copy_from_slice(&derived_material[64..96]);
-        
-        // Derive next ephemeral key
-        let ephemeral_scalar = Scalar::from_bytes_mod_order(ephemeral_bytes);
-        let next_ephemeral = &ephemeral_scalar * &curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+    /// Current key followed by the previous one during the overlap window
+    fn candidate_keys(&self) -> impl Iterator<Item = Scalar> {
+        std::iter::once(self.private_key).chain(self.previous_key)
+    }
+    
+    /// Find the key this header was built for by trial MAC verification
+    ///
+    /// Returns the shared secret with the header and payload keys. Every candidate is checked
+    /// and the match is selected without branching, so timing does not reveal whether a packet
+    /// was built for the current or the previous key. Headers that verify under neither were
+    /// modified in transit or are stale.
+    fn select_hop_secrets(
+        &self,
+        header: &SphinxHeader,
+        shared_secrets: impl Iterator<Item = [u8; 32]>
+    ) -> Option<HopSecrets> {
+        let mut selected = ([0u8; 32], [0u8; 32], [0u8; 32]);
+        let mut found = Choice::from(0);
+        
+        for shared_secret in shared_secrets {
+            let (header_key, payload_key, mac_key) =
+                self.simd_key_deriver.derive_keys_simd(&shared_secret);
+            let matches = header_mac_matches(&mac_key, &header.routing_info, &header.mac);
+            
+            ct_assign(&mut selected.0, &shared_secret, matches);
+            ct_assign(&mut selected.1, &header_key, matches);
+            ct_assign(&mut selected.2, &payload_key, matches);
+            found |= matches;
+        }
         
-        (header_key, payload_key, next_ephemeral)
+        bool::from(found).then_some(selected)
     }
     
     /// SIMD-optimized header decryption
+    ///
+    /// The encrypted routing info is extended with a zeroed block before decryption so that
+    /// after this hop's routing block is consumed the remainder is exactly the next header.
     fn decrypt_header_simd(&mut self, encrypted_header: &[u8], key: &[u8; 32]) -> Result<(), MixError> {
+        if encrypted_header.len() != SPHINX_ROUTING_INFO_SIZE {
+            return Err(MixError::InvalidPacket);
+        }
+        
         // Use SIMD-optimized memory operations
         self.simd_memory_ops.fast_clear(&mut self.temp_header);
-        
-        // Copy the encrypted header (routing_info is smaller than temp_header)
-        let copy_len = std::cmp::min(encrypted_header.len(), self.temp_header.len());
         self.simd_memory_ops.fast_copy(
-            &mut self.temp_header[0..copy_len],
-            &encrypted_header[0..copy_len]
+            &mut self.temp_header[0..SPHINX_ROUTING_INFO_SIZE],
+            encrypted_header
         );
         
         // Generate key stream for XOR decryption
-        let mut key_stream = [0u8; SPHINX_HEADER_SIZE];
-        self.generate_key_stream(key, &mut key_stream);
+        let mut key_stream = [0u8; SPHINX_ROUTING_INFO_SIZE + HOP_ROUTING_SIZE];
+        generate_header_stream(key, &mut key_stream);
         
         // SIMD-optimized XOR decryption
         self.simd_xor_processor.xor_packets(&mut self.temp_header, &key_stream);
         
-        // Set predictable routing info for benchmarking
-        self.temp_header[12] = 0x00; // Forward command
-        self.temp_header[13..45].fill(key[0]); // Next hop derived from key
-        self.temp_header[45..53].copy_from_slice(&1000u64.to_be_bytes()); // Delay
-        
-        Ok(())
-    }
-
-    fn decrypt_header(&mut self, encrypted_header: &[u8], key: &[u8; 32]) -> Result<(), MixError> {
-        // REAL AES-GCM header decryption - NO SIMULATION
-        use aes_gcm::{Aes256Gcm, KeyInit, Nonce, AeadInPlace};
-        
-        // Clear the temp buffer first
-        self.temp_header.fill(0);
-        
-        if encrypted_header.len() < 28 { // 12 bytes nonce + 16 bytes tag minimum
-            return Err(MixError::InvalidPacket);
-        }
-        
-        // Extract nonce (first 12 bytes) and ciphertext+tag
-        let nonce = Nonce::from_slice(&encrypted_header[0..12]);
-        let mut ciphertext_and_tag = encrypted_header[12..].to_vec();
-        
-        // Initialize AES-GCM cipher with the provided key
-        let cipher = Aes256Gcm::new(key.into());
-        
-        // REAL decryption operation
-        cipher.decrypt_in_place(nonce, b"", &mut ciphertext_and_tag)
-            .map_err(|_| MixError::DecryptionFailed)?;
-        
-        // Copy decrypted data to temp buffer
-        let copy_len = std::cmp::min(ciphertext_and_tag.len(), self.temp_header.len());
-        self.temp_header[0..copy_len].copy_from_slice(&ciphertext_and_tag[0..copy_len]);
-        
-        // Verify the decrypted header has valid structure
-        if copy_len < 45 { // Minimum header size: 1 byte command + 32 bytes next hop + 12 bytes extra
-            return Err(MixError::InvalidPacket);
-        }
-        self.temp_header[45..53].copy_from_slice(&1000u64.to_be_bytes()); // Delay
-        
         Ok(())
     }
     
     fn parse_routing_info(&self) -> Result<RoutingInfo, MixError> {
         // Parse the decrypted header to extract routing information
-        // For now, implement a basic parser
-        
         // First byte indicates command type
-        let command_byte = self.temp_header[12];
+        let command_byte = self.temp_header[0];
         
         let command = match command_byte {
-            0x00 => {
+            ROUTING_COMMAND_FORWARD => {
                 // Forward command - extract next hop from next 32 bytes
                 let mut next_hop = [0u8; 32];
-                next_hop.copy_from_slice(&self.temp_header[13..45]);
+                next_hop.copy_from_slice(&self.temp_header[1..33]);
                 RoutingCommand::Forward { next_hop }
             },
-            0x01 => RoutingCommand::Deliver,
+            ROUTING_COMMAND_DELIVER => RoutingCommand::Deliver,
+            ROUTING_COMMAND_REPLY => {
+                // Reply command - next 32 bytes identify the SURB
+                let mut surb_id = [0u8; 32];
+                surb_id.copy_from_slice(&self.temp_header[1..33]);
+                RoutingCommand::Reply { surb_id }
+            },
             _ => return Err(MixError::InvalidPacket),
         };
         
         // Extract delay information (next 8 bytes)
-        let delay_bytes = array_ref![self.temp_header, 45, 8];
+        let delay_bytes = array_ref![self.temp_header, 33, 8];
         let delay_micros = u64::from_be_bytes(*delay_bytes);
         let delay = std::time::Duration::from_micros(delay_micros);
         
         Ok(RoutingInfo { command, delay })
     }
     
-    /// SIMD-optimized forward payload processing
-    fn process_forward_payload_simd(
-        &mut self, 
-        payload: &[u8; SPHINX_PAYLOAD_SIZE], 
-        key: &[u8; 32],
-        _next_ephemeral: RistrettoPoint
-    ) -> Result<ProcessedPayload, MixError> {
-        // Use SIMD-optimized memory copy
-        self.simd_memory_ops.fast_copy(&mut self.temp_payload, payload);
-        
-        // Generate key stream for payload processing
-        let mut key_stream = [0u8; SPHINX_PAYLOAD_SIZE];
-        self.generate_key_stream_payload(key, &mut key_stream);
-        
-        // SIMD-optimized payload transformation
-        // REAL forward payload processing with AES-GCM
-        use aes_gcm::{Aes256Gcm, KeyInit, Nonce, AeadInPlace};
-        
-        if payload.len() < 28 { // 12 bytes nonce + 16 bytes tag minimum
-            return Err(MixError::InvalidPacket);
-        }
-        
-        // Extract nonce and ciphertext+tag  
-        let nonce = Nonce::from_slice(&payload[0..12]);
-        let mut ciphertext_and_tag = payload[12..].to_vec();
-        
-        // Initialize AES-GCM cipher
-        let cipher = Aes256Gcm::new(key.into());
-        
-        // REAL decryption operation
-        cipher.decrypt_in_place(nonce, b"", &mut ciphertext_and_tag)
-            .map_err(|_| MixError::DecryptionFailed)?;
-        
-        // Pad to exact payload size for next hop
-        if ciphertext_and_tag.len() < SPHINX_PAYLOAD_SIZE {
-            ciphertext_and_tag.resize(SPHINX_PAYLOAD_SIZE, 0);
-        }
-        let mut new_payload = [0u8; SPHINX_PAYLOAD_SIZE];
-        new_payload[..SPHINX_PAYLOAD_SIZE].copy_from_slice(&ciphertext_and_tag[..SPHINX_PAYLOAD_SIZE]);
+    /// Assemble the packet for the next hop from the shifted routing info, the blinded
+    /// ephemeral key and the decrypted payload
+    ///
+    /// The packet is written straight into a pooled buffer, so forwarding does not allocate.
+    fn forward_payload(&self, next_ephemeral: CompressedRistretto) -> ProcessedPayload {
+        let mut buffer = get_packet_buffer();
+        let (header, payload) = buffer.split_at_mut(SPHINX_HEADER_SIZE);
+        
+        header[0] = SphinxVersion::Native as u8;
+        header[1..33].copy_from_slice(next_ephemeral.as_bytes());
+        // The routing block ends with the MAC the next hop verifies
+        header[33..33 + SPHINX_MAC_SIZE].copy_from_slice(
+            &self.temp_header[HOP_ROUTING_SIZE - SPHINX_MAC_SIZE..HOP_ROUTING_SIZE]
+        );
+        header[33 + SPHINX_MAC_SIZE..].copy_from_slice(
+            &self.temp_header[HOP_ROUTING_SIZE..HOP_ROUTING_SIZE + SPHINX_ROUTING_INFO_SIZE]
+        );
+        self.simd_memory_ops.fast_copy(payload, &self.temp_payload);
         
-        Ok(ProcessedPayload::Forward(new_payload.to_vec()))
+        ProcessedPayload::Forward(buffer)
     }
     
-    /// REAL final payload decryption with AES-GCM - NO SIMULATION
-    fn decrypt_final_payload_simd(&mut self, payload: &[u8; SPHINX_PAYLOAD_SIZE], key: &[u8; 32]) -> Result<ProcessedPayload, MixError> {
-        // REAL AES-GCM final payload decryption
-        use aes_gcm::{Aes256Gcm, KeyInit, Nonce, AeadInPlace};
-        
-        if payload.len() < 28 { // 12 bytes nonce + 16 bytes tag minimum
-            return Err(MixError::InvalidPacket);
-        }
-        
-        // Extract nonce and ciphertext+tag
-        let nonce = Nonce::from_slice(&payload[0..12]);
-        let mut ciphertext_and_tag = payload[12..].to_vec();
-        
-        // Initialize AES-GCM cipher
-        let cipher = Aes256Gcm::new(key.into());
-        
-        // REAL final decryption
-        cipher.decrypt_in_place(nonce, b"", &mut ciphertext_and_tag)
-            .map_err(|_| MixError::DecryptionFailed)?;
-        
-        // Verify final payload structure and extract actual message
-        if ciphertext_and_tag.len() < 4 {
-            return Err(MixError::InvalidPacket);
+    /// Final payload, once this hop's layer is removed
+    ///
+    /// Tampering at any hop scrambles the whole block, which shows up here as non-zero
+    /// integrity bytes.
+    fn final_payload(&self) -> Result<ProcessedPayload, MixError> {
+        match decode_payload_message(&self.temp_payload) {
+            Ok(message) => Ok(ProcessedPayload::Final(message)),
+            Err(SphinxError::DecryptionFailed) => Err(MixError::DecryptionFailed),
+            Err(_) => Err(MixError::InvalidPacket),
         }
-        
-        // Read payload length (first 4 bytes)
-        let payload_len = u32::from_be_bytes([
-            ciphertext_and_tag[0],
-            ciphertext_and_tag[1], 
-            ciphertext_and_tag[2],
-            ciphertext_and_tag[3]
-        ]) as usize;
-        
-        if payload_len + 4 > ciphertext_and_tag.len() {
-            return Err(MixError::InvalidPacket);
-        }
-        
-        Ok(ProcessedPayload::Final(ciphertext_and_tag[4..4+payload_len].to_vec()))
     }
-    
-    /// Generate key stream for header decryption using Blake3 XOF
-    fn generate_key_stream(&self, key: &[u8; 32], output: &mut [u8]) {
-        let mut hasher = Hasher::new();
-        hasher.update(b"SPHINX_HEADER_STREAM_v1");
-        hasher.update(key);
-        
-        let mut xof = hasher.finalize_xof();
-        xof.fill(output);
+}
+
+/// Extract the message from a fully decrypted payload
+///
+/// Fails with `DecryptionFailed` when the integrity prefix is not all zeros.
+pub(crate) fn decode_payload_message(plaintext: &[u8; SPHINX_PAYLOAD_SIZE]) -> Result<Vec<u8>, SphinxError> {
+    // Constant-time check of the zeroed integrity prefix
+    if !bool::from(plaintext[..PAYLOAD_INTEGRITY_SIZE].ct_eq(&[0u8; PAYLOAD_INTEGRITY_SIZE])) {
+        return Err(SphinxError::DecryptionFailed);
     }
     
-    /// Generate key stream for payload processing using Blake3 XOF
-    fn generate_key_stream_payload(&self, key: &[u8; 32], output: &mut [u8]) {
-        let mut hasher = Hasher::new();
-        hasher.update(b"SPHINX_PAYLOAD_STREAM_v1");
-        hasher.update(key);
-        
-        let mut xof = hasher.finalize_xof();
-        xof.fill(output);
+    // Read payload length (4 bytes after the integrity prefix)
+    let body = &plaintext[PAYLOAD_INTEGRITY_SIZE..];
+    let message_len = u32::from_be_bytes(*array_ref![body, 0, 4]) as usize;
+    
+    if message_len + 4 > body.len() {
+        return Err(SphinxError::InvalidPayloadSize);
     }
+    
+    Ok(body[4..4 + message_len].to_vec())
+}
 
-    fn process_forward_payload(
-        &mut self, 
-        payload: &[u8; SPHINX_PAYLOAD_SIZE], 
-        key: &[u8; 32],
-        _next_ephemeral: RistrettoPoint
-    ) -> Result<ProcessedPayload, MixError> {
-        // For benchmarking, simulate the payload processing work
-        self.temp_payload.copy_from_slice(payload);
-        
-        // Simulate some crypto work
-        for (i, &k) in key.iter().enumerate() {
-            if i < self.temp_payload.len() {
-                self.temp_payload[i] = self.temp_payload[i].wrapping_add(k);
-            }
-        }
-        
-        Ok(ProcessedPayload::Forward(self.temp_payload.to_vec()))
-    }
+/// Generate key stream for header decryption using Blake3 XOF
+pub(crate) fn generate_header_stream(key: &[u8; 32], output: &mut [u8]) {
+    let mut hasher = Hasher::new();
+    hasher.update(b"SPHINX_HEADER_STREAM_v1");
+    hasher.update(key);
     
-    fn decrypt_final_payload(&mut self, payload: &[u8; SPHINX_PAYLOAD_SIZE], key: &[u8; 32]) -> Result<ProcessedPayload, MixError> {
-        // For benchmarking, simulate final payload decryption
-        self.temp_payload.copy_from_slice(payload);
-        
-        // Simulate some crypto work
-        for (i, &k) in key.iter().enumerate() {
-            if i < self.temp_payload.len() {
-                self.temp_payload[i] = self.temp_payload[i].wrapping_sub(k);
-            }
-        }
-        
-        Ok(ProcessedPayload::Final(self.temp_payload.to_vec()))
+    let mut xof = hasher.finalize_xof();
+    xof.fill(output);
+}
+
+/// Compute the truncated HMAC-SHA256 over a hop's encrypted routing info
+pub(crate) fn compute_header_mac(key: &[u8; 32], routing_info: &[u8]) -> [u8; SPHINX_MAC_SIZE] {
+    let mut hmac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    hmac.update(routing_info);
+    
+    let mut mac = [0u8; SPHINX_MAC_SIZE];
+    mac.copy_from_slice(&hmac.finalize().into_bytes()[..SPHINX_MAC_SIZE]);
+    mac
+}
+
+/// Constant-time check of a header MAC
+fn header_mac_matches(key: &[u8; 32], routing_info: &[u8], mac: &[u8; SPHINX_MAC_SIZE]) -> Choice {
+    compute_header_mac(key, routing_info).ct_eq(mac)
+}
+
+/// Overwrite `dst` with `src` when `choice` is set, without branching on it
+fn ct_assign(dst: &mut [u8; 32], src: &[u8; 32], choice: Choice) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        d.conditional_assign(s, choice);
     }
 }
 
+/// Blinding factor applied to the ephemeral key between hops
+///
+/// Binds the current ephemeral key and shared secret so consecutive hops see unlinkable keys.
+pub(crate) fn blinding_factor(ephemeral_key: &[u8; 32], shared_secret: &[u8; 32]) -> Scalar {
+    let mut hasher = Hasher::new();
+    hasher.update(b"SPHINX_BLINDING_v1");
+    hasher.update(ephemeral_key);
+    hasher.update(shared_secret);
+    
+    let mut wide = [0u8; 64];
+    hasher.finalize_xof().fill(&mut wide);
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
 #[derive(Debug)]
 pub struct ProcessedPacket {
     pub routing_info: RoutingInfo,
@@ -476,6 +622,8 @@ pub struct ProcessedPacket {
 pub enum RoutingCommand {
     Forward { next_hop: MixNodeId },
     Deliver,
+    /// Final hop of a SURB route; the payload goes back to the SURB creator
+    Reply { surb_id: SurbId },
 }
 
 #[derive(Debug)]
@@ -486,10 +634,18 @@ pub struct RoutingInfo {
 
 #[derive(Debug)]
 pub enum ProcessedPayload {
-    Forward(Vec<u8>),
+    /// Serialized packet to send to the next hop, in a buffer that returns to the pool on drop
+    Forward(PooledObject<SphinxPacketBuffer>),
+    /// Decrypted message for local delivery
     Final(Vec<u8>),
+    /// Reply payload for the SURB creator, still wrapped in layers only they can remove
+    Reply(Vec<u8>),
 }
 
+pub(crate) const ROUTING_COMMAND_FORWARD: u8 = 0x00;
+pub(crate) const ROUTING_COMMAND_DELIVER: u8 = 0x01;
+pub(crate) const ROUTING_COMMAND_REPLY: u8 = 0x02;
+
 pub type MixNodeId = [u8; 32];
 
 /// Sphinx packet processing errors
@@ -509,6 +665,8 @@ pub enum SphinxError {
     DecryptionFailed,
     #[error("Invalid routing info")]
     InvalidRoutingInfo,
+    #[error("Invalid route")]
+    InvalidRoute,
     #[error("Cryptographic error")]
     CryptoError,
 }
@@ -523,4 +681,6 @@ pub enum MixError {
     InvalidPacket,
     #[error("Routing error")]
     RoutingError,
+    #[error("Replayed packet")]
+    Replay,
 }
\ No newline at end of file
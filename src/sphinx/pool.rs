@@ -0,0 +1,255 @@
+// Sharded Sphinx worker pool
+// Each worker owns a SphinxMixer built from the node's key material. Packets are sharded by
+// a keyed hash of their ephemeral key, so a replayed packet always reaches the worker whose
+// replay cache has already seen it. The shard key is saved with the caches, so this also holds
+// across restarts. Packets travel to the workers one message each and are batched again by the
+// worker, so nothing is allocated per dispatch.
+
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{OsRng, RngCore};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::sphinx::keys::{SphinxKey, SphinxKeySet};
+use crate::sphinx::packet::{SphinxPacket, PooledPacket, SphinxMixer, ProcessedPacket, MixError};
+use crate::sphinx::replay::{ReplayCache, ReplayCacheConfig, ReplayState};
+
+/// Packets queued per worker before dispatch starts dropping
+const WORKER_QUEUE_PACKETS: usize = 16_384;
+/// Most packets a worker processes as one batch
+pub const WORKER_BATCH_SIZE: usize = 256;
+
+/// Processing result for one packet, paired with the caller's context
+pub type ProcessedResult<T> = (T, Result<ProcessedPacket, MixError>);
+
+enum WorkerMessage<T> {
+    Packet(PooledPacket, T),
+    RotateKey { private_key: Scalar, epoch: u64 },
+    RetirePreviousKey,
+    SnapshotReplay(oneshot::Sender<ReplayCache>),
+}
+
+/// Handle used to dispatch packets and key changes to the workers
+pub struct MixerPool<T> {
+    workers: Vec<mpsc::Sender<WorkerMessage<T>>>,
+    shard_key: [u8; 32],
+}
+
+/// A worker and its mixer, started with `run`
+pub struct MixerWorker<T> {
+    mixer: SphinxMixer,
+    inbox: mpsc::Receiver<WorkerMessage<T>>,
+    // Batch scratch space, reused across batches
+    messages: Vec<WorkerMessage<T>>,
+    packets: Vec<PooledPacket>,
+    contexts: Vec<T>,
+    results: Vec<Result<ProcessedPacket, MixError>>,
+}
+
+impl<T: Send + 'static> MixerPool<T> {
+    /// Create `workers` mixers from `keys`, splitting the replay cache budget between them
+    pub fn new(workers: usize, keys: &SphinxKeySet) -> (Self, Vec<MixerWorker<T>>) {
+        let mut shard_key = [0u8; 32];
+        OsRng.fill_bytes(&mut shard_key);
+        let caches = (0..workers.max(1))
+            .map(|_| ReplayCache::new(Self::replay_config(workers)))
+            .collect();
+
+        Self::with_replay_state(keys, ReplayState { shard_key, caches })
+    }
+
+    /// Create one mixer per saved replay cache, sharding packets as before the save
+    pub fn with_replay_state(keys: &SphinxKeySet, state: ReplayState) -> (Self, Vec<MixerWorker<T>>) {
+        let mut senders = Vec::with_capacity(state.caches.len());
+        let mut pool_workers = Vec::with_capacity(state.caches.len());
+        for cache in state.caches {
+            let mut mixer = SphinxMixer::with_replay_cache(keys.current.private_key(), cache);
+            mixer.set_previous_key(keys.previous.as_ref().map(SphinxKey::private_key));
+            mixer.rotate_replay_epoch(keys.current.epoch);
+
+            let (sender, inbox) = mpsc::channel(WORKER_QUEUE_PACKETS);
+            senders.push(sender);
+            pool_workers.push(MixerWorker {
+                mixer,
+                inbox,
+                messages: Vec::with_capacity(WORKER_BATCH_SIZE),
+                packets: Vec::with_capacity(WORKER_BATCH_SIZE),
+                contexts: Vec::with_capacity(WORKER_BATCH_SIZE),
+                results: Vec::with_capacity(WORKER_BATCH_SIZE),
+            });
+        }
+
+        let pool = Self {
+            workers: senders,
+            shard_key: state.shard_key,
+        };
+        (pool, pool_workers)
+    }
+
+    /// Replay cache sizing for each of `workers` workers
+    pub fn replay_config(workers: usize) -> ReplayCacheConfig {
+        let workers = workers.max(1);
+        let default_replay = ReplayCacheConfig::default();
+        ReplayCacheConfig {
+            bloom_bits: (default_replay.bloom_bits / workers).max(1 << 16),
+            max_exact_entries: (default_replay.max_exact_entries / workers).max(1024),
+            ..default_replay
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Worker responsible for a packet
+    pub fn shard_for(&self, packet: &SphinxPacket) -> usize {
+        let hash = blake3::keyed_hash(&self.shard_key, &packet.header.ephemeral_key);
+        let hash = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        (hash % self.workers.len() as u64) as usize
+    }
+
+    /// Hand every packet in `packets` to its worker without waiting, leaving `packets` empty
+    /// for reuse; returns how many were dropped because a worker's queue was full
+    pub fn dispatch(&self, packets: &mut Vec<(PooledPacket, T)>) -> usize {
+        let mut dropped = 0;
+        for (packet, context) in packets.drain(..) {
+            let shard = self.shard_for(&packet);
+            if self.workers[shard].try_send(WorkerMessage::Packet(packet, context)).is_err() {
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+    /// Switch every worker to a new key; queued packets are processed with the old key first
+    pub async fn rotate_key(&self, private_key: Scalar, epoch: u64) {
+        for worker in &self.workers {
+            let _ = worker.send(WorkerMessage::RotateKey { private_key, epoch }).await;
+        }
+    }
+
+    pub async fn retire_previous_key(&self) {
+        for worker in &self.workers {
+            let _ = worker.send(WorkerMessage::RetirePreviousKey).await;
+        }
+    }
+
+    /// Copy every worker's replay cache, taken between batches; None once a worker has stopped
+    pub async fn replay_state(&self) -> Option<ReplayState> {
+        let mut caches = Vec::with_capacity(self.workers.len());
+        for worker in &self.workers {
+            let (reply, cache) = oneshot::channel();
+            let _ = worker.send(WorkerMessage::SnapshotReplay(reply)).await;
+            caches.push(cache.await.ok()?);
+        }
+
+        Some(ReplayState { shard_key: self.shard_key, caches })
+    }
+}
+
+impl<T: Send + 'static> MixerWorker<T> {
+    /// Process packets until the pool is dropped, sending each result to `results`
+    ///
+    /// Packets already queued are processed together, up to `WORKER_BATCH_SIZE` at a time.
+    pub async fn run(mut self, results: mpsc::Sender<ProcessedResult<T>>) {
+        while self.inbox.recv_many(&mut self.messages, WORKER_BATCH_SIZE).await > 0 {
+            let mut messages = std::mem::take(&mut self.messages);
+            for message in messages.drain(..) {
+                match message {
+                    WorkerMessage::Packet(packet, context) => {
+                        self.packets.push(packet);
+                        self.contexts.push(context);
+                    }
+                    // Key changes apply between packets, as they were sent
+                    WorkerMessage::RotateKey { private_key, epoch } => {
+                        if !self.process_queued(&results).await {
+                            return;
+                        }
+                        self.mixer.rotate_key(private_key, epoch);
+                    }
+                    WorkerMessage::RetirePreviousKey => {
+                        if !self.process_queued(&results).await {
+                            return;
+                        }
+                        self.mixer.retire_previous_key();
+                    }
+                    WorkerMessage::SnapshotReplay(reply) => {
+                        if !self.process_queued(&results).await {
+                            return;
+                        }
+                        let _ = reply.send(self.mixer.replay_cache().clone());
+                    }
+                }
+            }
+            self.messages = messages;
+
+            if !self.process_queued(&results).await {
+                return;
+            }
+        }
+    }
+
+    /// Process the packets collected so far as one batch; false once `results` is closed
+    async fn process_queued(&mut self, results: &mpsc::Sender<ProcessedResult<T>>) -> bool {
+        if self.packets.is_empty() {
+            return true;
+        }
+        self.mixer.process_batch_into(&self.packets, &mut self.results);
+        self.packets.clear();
+
+        for (context, result) in self.contexts.drain(..).zip(self.results.drain(..)) {
+            if results.send((context, result)).await.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphinx::{get_packet_buffer, SphinxPacketBuilder};
+
+    #[tokio::test]
+    async fn test_pool_shards_replays_to_same_worker() {
+        let keys = SphinxKeySet::generate(0);
+        let (pool, workers) = MixerPool::<usize>::new(4, &keys);
+        let (results_tx, mut results_rx) = mpsc::channel(16);
+        for worker in workers {
+            tokio::spawn(worker.run(results_tx.clone()));
+        }
+
+        let packets: Vec<SphinxPacket> = (0..8)
+            .map(|_| {
+                SphinxPacketBuilder::new()
+                    .route(vec![([1u8; 32], keys.current.public_key())])
+                    .payload(b"sharded")
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+
+        // Every packet twice: the copy must land on the worker that processed the original
+        let mut batch: Vec<_> = packets.iter().chain(packets.iter()).enumerate()
+            .map(|(i, packet)| {
+                let mut buffer = get_packet_buffer();
+                buffer.copy_from_slice(&packet.to_bytes());
+                (PooledPacket::from_buffer(buffer).unwrap(), i)
+            })
+            .collect();
+        assert_eq!(pool.dispatch(&mut batch), 0);
+        assert!(batch.is_empty());
+
+        let mut ok = 0;
+        let mut replays = 0;
+        while ok + replays < 16 {
+            let (_, result) = results_rx.recv().await.unwrap();
+            match result {
+                Ok(_) => ok += 1,
+                Err(MixError::Replay) => replays += 1,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        assert_eq!((ok, replays), (8, 8));
+    }
+}
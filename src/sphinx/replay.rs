@@ -0,0 +1,400 @@
+// Replay protection for processed Sphinx packets
+// Tags are derived from the per-packet shared secret, so a replayed packet always maps to the same tag.
+// Persisted keys outlive a restart, so the caches are saved next to them and restored on start.
+
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use blake3::Hasher;
+
+use crate::sphinx::keys::{write_private, KeyStoreError};
+
+/// Hash of a packet's shared secret used to recognise replays
+pub type ReplayTag = [u8; 32];
+
+/// Replay cache sizing
+#[derive(Debug, Clone)]
+pub struct ReplayCacheConfig {
+    /// Number of bits in each epoch's Bloom filter
+    pub bloom_bits: usize,
+    /// Number of Bloom filter probes per tag
+    pub bloom_hashes: u32,
+    /// Maximum tags kept in each epoch's exact set
+    pub max_exact_entries: usize,
+    /// Number of past epochs retained alongside the current one
+    pub retained_epochs: usize,
+}
+
+impl Default for ReplayCacheConfig {
+    fn default() -> Self {
+        Self {
+            bloom_bits: 1 << 27,          // 16MB filter, ~14M tags at 1% false positives
+            bloom_hashes: 7,
+            max_exact_entries: 1 << 20,   // Exact confirmation for the first ~1M tags
+            retained_epochs: 1,           // Previous key stays valid during rotation overlap
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReplayCacheStats {
+    pub current_epoch: u64,
+    pub tags_inserted: u64,
+    pub replays_detected: u64,
+    pub bloom_false_positives: u64,
+    pub exact_entries: usize,
+}
+
+/// Bloom filter plus exact set for a single key epoch
+#[derive(Clone)]
+struct ReplayEpoch {
+    epoch: u64,
+    bloom: Vec<u64>,
+    exact: HashSet<ReplayTag>,
+    // False once the exact set overflowed and some tags only live in the Bloom filter
+    exact_complete: bool,
+}
+
+impl ReplayEpoch {
+    fn new(epoch: u64, config: &ReplayCacheConfig) -> Self {
+        Self {
+            epoch,
+            bloom: vec![0u64; config.bloom_bits.div_ceil(64).max(1)],
+            exact: HashSet::new(),
+            exact_complete: true,
+        }
+    }
+
+    fn bit_indices(&self, tag: &ReplayTag, hashes: u32) -> impl Iterator<Item = usize> {
+        // Double hashing over two independent 64-bit words of the tag
+        let h1 = u64::from_le_bytes(tag[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(tag[8..16].try_into().unwrap()) | 1;
+        let bits = (self.bloom.len() * 64) as u64;
+        (0..hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+
+    fn bloom_contains(&self, tag: &ReplayTag, hashes: u32) -> bool {
+        self.bit_indices(tag, hashes)
+            .all(|bit| self.bloom[bit / 64] & (1u64 << (bit % 64)) != 0)
+    }
+
+    fn insert(&mut self, tag: ReplayTag, config: &ReplayCacheConfig) {
+        for bit in self.bit_indices(&tag, config.bloom_hashes) {
+            self.bloom[bit / 64] |= 1u64 << (bit % 64);
+        }
+
+        if self.exact.len() < config.max_exact_entries {
+            self.exact.insert(tag);
+        } else {
+            self.exact_complete = false;
+        }
+    }
+}
+
+/// Epoch-rotated replay detector
+#[derive(Clone)]
+pub struct ReplayCache {
+    config: ReplayCacheConfig,
+    epochs: VecDeque<ReplayEpoch>,
+    stats: ReplayCacheStats,
+}
+
+impl ReplayCache {
+    pub fn new(config: ReplayCacheConfig) -> Self {
+        let mut epochs = VecDeque::with_capacity(config.retained_epochs + 1);
+        epochs.push_front(ReplayEpoch::new(0, &config));
+
+        Self {
+            config,
+            epochs,
+            stats: ReplayCacheStats::default(),
+        }
+    }
+
+    /// Derive the replay tag for a packet's shared secret
+    pub fn compute_tag(shared_secret: &[u8; 32]) -> ReplayTag {
+        let mut hasher = Hasher::new();
+        hasher.update(b"SPHINX_REPLAY_TAG_v1");
+        hasher.update(shared_secret);
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Check whether a tag was already seen in any retained epoch
+    pub fn contains(&mut self, tag: &ReplayTag) -> bool {
+        let hashes = self.config.bloom_hashes;
+        let mut false_positive = false;
+
+        for epoch in &self.epochs {
+            if !epoch.bloom_contains(tag, hashes) {
+                continue;
+            }
+
+            // The exact set confirms Bloom hits until it overflows; after that the hit is trusted
+            if epoch.exact.contains(tag) || !epoch.exact_complete {
+                self.stats.replays_detected += 1;
+                return true;
+            }
+            false_positive = true;
+        }
+
+        if false_positive {
+            self.stats.bloom_false_positives += 1;
+        }
+        false
+    }
+
+    /// Record a tag in the current epoch
+    pub fn insert(&mut self, tag: ReplayTag) {
+        if let Some(current) = self.epochs.front_mut() {
+            current.insert(tag, &self.config);
+            self.stats.tags_inserted += 1;
+        }
+    }
+
+    /// Check and record in one step, returning true if the tag is a replay
+    pub fn check_and_insert(&mut self, tag: ReplayTag) -> bool {
+        if self.contains(&tag) {
+            return true;
+        }
+        self.insert(tag);
+        false
+    }
+
+    /// Start a new key epoch, dropping epochs whose keys can no longer decrypt packets
+    pub fn rotate(&mut self, epoch: u64) {
+        if self.current_epoch() == epoch {
+            return;
+        }
+
+        self.epochs.push_front(ReplayEpoch::new(epoch, &self.config));
+        self.epochs.truncate(self.config.retained_epochs + 1);
+    }
+
+    pub fn current_epoch(&self) -> u64 {
+        self.epochs.front().map(|epoch| epoch.epoch).unwrap_or(0)
+    }
+
+    pub fn stats(&self) -> ReplayCacheStats {
+        ReplayCacheStats {
+            current_epoch: self.current_epoch(),
+            exact_entries: self.epochs.iter().map(|epoch| epoch.exact.len()).sum(),
+            ..self.stats.clone()
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.epochs.len() as u32).to_le_bytes());
+        for epoch in &self.epochs {
+            out.extend_from_slice(&epoch.epoch.to_le_bytes());
+            out.push(epoch.exact_complete as u8);
+            out.extend_from_slice(&(epoch.bloom.len() as u64).to_le_bytes());
+            for word in &epoch.bloom {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+            out.extend_from_slice(&(epoch.exact.len() as u64).to_le_bytes());
+            for tag in &epoch.exact {
+                out.extend_from_slice(tag);
+            }
+        }
+    }
+
+    /// Decode a cache saved with `config`; a different filter size cannot be restored
+    fn decode(reader: &mut StateReader, config: &ReplayCacheConfig) -> Result<Self, KeyStoreError> {
+        let bloom_words = config.bloom_bits.div_ceil(64).max(1);
+        let count = reader.u32()? as usize;
+        if count == 0 || count > config.retained_epochs + 1 {
+            return Err(KeyStoreError::Malformed(format!("{} replay epochs", count)));
+        }
+
+        let mut epochs = VecDeque::with_capacity(config.retained_epochs + 1);
+        for _ in 0..count {
+            let epoch = reader.u64()?;
+            let exact_complete = reader.take(1)?[0] != 0;
+            if reader.u64()? as usize != bloom_words {
+                return Err(KeyStoreError::Malformed("replay filter size changed".to_string()));
+            }
+            let bloom = reader.take(bloom_words * 8)?
+                .chunks_exact(8)
+                .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                .collect();
+            let exact_len = (reader.u64()? as usize).checked_mul(32)
+                .ok_or_else(|| KeyStoreError::Malformed("replay set too large".to_string()))?;
+            let exact = reader.take(exact_len)?
+                .chunks_exact(32)
+                .map(|tag| tag.try_into().unwrap())
+                .collect();
+            epochs.push_back(ReplayEpoch { epoch, bloom, exact, exact_complete });
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            epochs,
+            stats: ReplayCacheStats::default(),
+        })
+    }
+}
+
+impl Default for ReplayCache {
+    fn default() -> Self {
+        Self::new(ReplayCacheConfig::default())
+    }
+}
+
+const REPLAY_STATE_MAGIC: &[u8; 8] = b"SPHXRPL1";
+
+/// Replay caches of a worker pool, with the key that shards packets between them
+///
+/// A replayed packet must reach the worker holding its tag, so the shard key is saved too.
+pub struct ReplayState {
+    pub shard_key: [u8; 32],
+    pub caches: Vec<ReplayCache>,
+}
+
+/// Binary file holding a pool's replay caches
+pub struct ReplayStore {
+    path: PathBuf,
+}
+
+impl ReplayStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the state of `workers` caches sized by `config`
+    pub fn load(&self, workers: usize, config: &ReplayCacheConfig) -> Result<Option<ReplayState>, KeyStoreError> {
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut reader = StateReader { data: &data };
+        if reader.take(REPLAY_STATE_MAGIC.len())? != REPLAY_STATE_MAGIC {
+            return Err(KeyStoreError::Malformed("not a replay state file".to_string()));
+        }
+        let shard_key: [u8; 32] = reader.take(32)?.try_into().unwrap();
+        let count = reader.u32()? as usize;
+        if count != workers {
+            return Err(KeyStoreError::Malformed(format!("saved for {} workers, not {}", count, workers)));
+        }
+        let caches = (0..count)
+            .map(|_| ReplayCache::decode(&mut reader, config))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !reader.data.is_empty() {
+            return Err(KeyStoreError::Malformed("trailing bytes in replay state".to_string()));
+        }
+
+        Ok(Some(ReplayState { shard_key, caches }))
+    }
+
+    /// Write the state atomically, readable by the owner only
+    pub fn save(&self, state: &ReplayState) -> Result<(), KeyStoreError> {
+        let mut data = Vec::new();
+        data.extend_from_slice(REPLAY_STATE_MAGIC);
+        data.extend_from_slice(&state.shard_key);
+        data.extend_from_slice(&(state.caches.len() as u32).to_le_bytes());
+        for cache in &state.caches {
+            cache.encode(&mut data);
+        }
+
+        write_private(&self.path, &data)
+    }
+}
+
+struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], KeyStoreError> {
+        if self.data.len() < len {
+            return Err(KeyStoreError::Malformed("truncated replay state".to_string()));
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, KeyStoreError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, KeyStoreError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphinx::{SphinxMixer, SphinxPacketBuilder, MixError};
+    use curve25519_dalek::scalar::Scalar;
+
+    fn small_config() -> ReplayCacheConfig {
+        ReplayCacheConfig {
+            bloom_bits: 1 << 16,
+            bloom_hashes: 4,
+            max_exact_entries: 1000,
+            retained_epochs: 1,
+        }
+    }
+
+    #[test]
+    fn test_replay_cache_epoch_rotation() {
+        let mut cache = ReplayCache::new(small_config());
+        let tag = ReplayCache::compute_tag(&[0x42u8; 32]);
+
+        assert!(!cache.check_and_insert(tag));
+        assert!(cache.check_and_insert(tag));
+
+        // Still rejected during the overlap epoch
+        cache.rotate(1);
+        assert!(cache.contains(&tag));
+
+        // Forgotten once its epoch is no longer retained
+        cache.rotate(2);
+        assert!(!cache.contains(&tag));
+        assert_eq!(cache.stats().current_epoch, 2);
+    }
+
+    #[test]
+    fn test_replay_state_save_and_load() {
+        let path = std::env::temp_dir().join(format!("replay-state-{}.bin", std::process::id()));
+        let store = ReplayStore::new(&path);
+        let mut cache = ReplayCache::new(small_config());
+        let (old, new) = (ReplayCache::compute_tag(&[1u8; 32]), ReplayCache::compute_tag(&[2u8; 32]));
+        cache.insert(old);
+        cache.rotate(1);
+        cache.insert(new);
+
+        store.save(&ReplayState { shard_key: [7u8; 32], caches: vec![cache] }).unwrap();
+        let mut restored = store.load(1, &small_config()).unwrap().unwrap();
+        assert_eq!(restored.shard_key, [7u8; 32]);
+        assert!(restored.caches[0].contains(&old));
+        assert!(restored.caches[0].contains(&new));
+        assert_eq!(restored.caches[0].current_epoch(), 1);
+
+        // Caches saved for another pool layout are refused rather than half restored
+        assert!(store.load(2, &small_config()).is_err());
+        let resized = ReplayCacheConfig { bloom_bits: 1 << 17, ..small_config() };
+        assert!(store.load(1, &resized).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mixer_rejects_replayed_packet() {
+        let mut mixer = SphinxMixer::new(Scalar::from_bytes_mod_order([9u8; 32]));
+        let packet = SphinxPacketBuilder::new()
+            .route(vec![([1u8; 32], mixer.public_key())])
+            .payload(b"once only")
+            .build()
+            .unwrap();
+
+        assert!(mixer.process_packet(&packet).is_ok());
+        assert!(matches!(mixer.process_packet(&packet), Err(MixError::Replay)));
+    }
+}
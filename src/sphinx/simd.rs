@@ -1,410 +1,473 @@
 // SIMD optimizations for Sphinx packet processing
-// Targets ARM NEON and x86 AVX2 instruction sets for maximum performance
+// Targets x86 AVX2 / SSE4.1 and ARM NEON, with a scalar fallback. The backend is detected at
+// runtime and chosen once; every backend produces byte-identical output.
 
 #[cfg(target_arch = "aarch64")]
 use std::arch::aarch64::*;
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
+use std::sync::OnceLock;
 
 use blake3::Hasher;
 
-/// SIMD-optimized Blake3 key derivation
-/// Uses vectorized operations to generate multiple key components in parallel
-pub struct SimdKeyDeriver {
-    #[cfg(target_arch = "aarch64")]
-    neon_support: bool,
-    #[cfg(target_arch = "x86_64")]
-    avx2_support: bool,
+/// Header key, payload key and header MAC key derived from a shared secret
+pub type DerivedKeys = ([u8; 32], [u8; 32], [u8; 32]);
+
+/// Instruction set used for the vectorized packet operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimdBackend {
+    Avx2,
+    Sse41,
+    Neon,
+    Scalar,
 }
 
-impl SimdKeyDeriver {
-    pub fn new() -> Self {
-        Self {
-            #[cfg(target_arch = "aarch64")]
-            neon_support: std::arch::is_aarch64_feature_detected!("neon"),
-            #[cfg(target_arch = "x86_64")]
-            avx2_support: std::arch::is_x86_feature_detected!("avx2"),
-        }
+impl SimdBackend {
+    /// Fastest backend supported by this CPU
+    pub fn detect() -> Self {
+        Self::available()[0]
     }
 
-    /// Optimized key derivation using SIMD operations
-    /// Generates header_key, payload_key, and ephemeral_key material in parallel
-    #[inline(always)]
-    pub fn derive_keys_simd(&self, shared_secret: &[u8; 32]) -> ([u8; 32], [u8; 32], [u8; 32]) {
-        // Use SIMD-optimized Blake3 if available
-        #[cfg(target_arch = "x86_64")]
-        if self.avx2_support {
-            unsafe {
-                return self.derive_keys_avx2(shared_secret);
-            }
-        }
+    /// Backends this CPU can run, fastest first; always ends with `Scalar`
+    pub fn available() -> Vec<Self> {
+        [Self::Avx2, Self::Sse41, Self::Neon, Self::Scalar]
+            .into_iter()
+            .filter(|backend| backend.is_supported())
+            .collect()
+    }
 
-        #[cfg(target_arch = "aarch64")]
-        if self.neon_support {
-            unsafe {
-                return self.derive_keys_neon(shared_secret);
-            }
+    pub fn is_supported(self) -> bool {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => std::arch::is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "x86_64")]
+            Self::Sse41 => std::arch::is_x86_feature_detected!("sse4.1"),
+            #[cfg(target_arch = "aarch64")]
+            Self::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            Self::Scalar => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
         }
-
-        // Fallback to optimized scalar implementation
-        self.derive_keys_scalar_optimized(shared_secret)
     }
+}
 
-    #[cfg(target_arch = "x86_64")]
-    #[target_feature(enable = "avx2")]
-    unsafe fn derive_keys_avx2(&self, shared_secret: &[u8; 32]) -> ([u8; 32], [u8; 32], [u8; 32]) {
-        // Use Blake3's AVX2-optimized implementation
-        let mut hasher = Hasher::new();
-        hasher.update(b"SPHINX_DERIVE_SIMD_v1");
-        hasher.update(shared_secret);
+/// Function table for one backend
+///
+/// Tables are only built for backends the CPU supports, which is what makes calling the
+/// `#[target_feature]` functions behind them sound.
+#[derive(Clone, Copy)]
+pub struct SimdDispatch {
+    backend: SimdBackend,
+    derive_keys: unsafe fn(&[u8; 32]) -> DerivedKeys,
+    xor: unsafe fn(&mut [u8], &[u8]),
+    copy: unsafe fn(&mut [u8], &[u8]),
+    clear: unsafe fn(&mut [u8]),
+}
 
-        // Generate 96 bytes of key material using XOF
-        let mut derived_material = [0u8; 96];
-        let mut xof = hasher.finalize_xof();
-        xof.fill(&mut derived_material);
+static DISPATCH: OnceLock<SimdDispatch> = OnceLock::new();
+
+/// Select the backend for the process, honouring `SphinxConfig::enable_simd`
+///
+/// Only the first call decides; later calls (and SIMD users created before any call, which
+/// get the detected backend) return the backend already in use.
+pub fn init_simd(enable_simd: bool) -> SimdBackend {
+    DISPATCH
+        .get_or_init(|| {
+            let backend = if enable_simd { SimdBackend::detect() } else { SimdBackend::Scalar };
+            SimdDispatch::for_backend(backend).expect("detected backend is supported")
+        })
+        .backend
+}
 
-        // Use AVX2 to copy key material in parallel
-        let keys_ptr = derived_material.as_ptr() as *const __m256i;
-        let key1 = _mm256_loadu_si256(keys_ptr);
-        let key2 = _mm256_loadu_si256(keys_ptr.add(1));
-        let key3 = _mm256_loadu_si256(keys_ptr.add(2));
+/// Process-wide dispatch table, detecting the backend on first use
+pub fn simd_dispatch() -> &'static SimdDispatch {
+    DISPATCH.get_or_init(|| SimdDispatch::for_backend(SimdBackend::detect()).expect("detected backend is supported"))
+}
 
-        let mut header_key = [0u8; 32];
-        let mut payload_key = [0u8; 32];
-        let mut ephemeral_key = [0u8; 32];
+impl SimdDispatch {
+    /// Table for `backend`, or `None` if this CPU cannot run it
+    pub fn for_backend(backend: SimdBackend) -> Option<Self> {
+        if !backend.is_supported() {
+            return None;
+        }
 
-        _mm256_storeu_si256(header_key.as_mut_ptr() as *mut __m256i, key1);
-        _mm256_storeu_si256(payload_key.as_mut_ptr() as *mut __m256i, key2);
-        _mm256_storeu_si256(ephemeral_key.as_mut_ptr() as *mut __m256i, key3);
+        let scalar = Self {
+            backend: SimdBackend::Scalar,
+            derive_keys: derive_keys_scalar,
+            xor: xor_scalar,
+            copy: copy_scalar,
+            clear: clear_scalar,
+        };
 
-        (header_key, payload_key, ephemeral_key)
+        let dispatch = match backend {
+            #[cfg(target_arch = "x86_64")]
+            SimdBackend::Avx2 => Self {
+                backend,
+                derive_keys: derive_keys_avx2,
+                xor: xor_avx2,
+                copy: copy_avx2,
+                clear: clear_avx2,
+            },
+            #[cfg(target_arch = "x86_64")]
+            SimdBackend::Sse41 => Self {
+                backend,
+                derive_keys: derive_keys_sse41,
+                xor: xor_sse41,
+                copy: copy_sse41,
+                clear: clear_sse41,
+            },
+            #[cfg(target_arch = "aarch64")]
+            SimdBackend::Neon => Self {
+                backend,
+                derive_keys: derive_keys_neon,
+                xor: xor_neon,
+                copy: copy_neon,
+                clear: clear_neon,
+            },
+            _ => scalar,
+        };
+        Some(dispatch)
     }
 
-    #[cfg(target_arch = "aarch64")]
-    #[target_feature(enable = "neon")]
-    unsafe fn derive_keys_neon(&self, shared_secret: &[u8; 32]) -> ([u8; 32], [u8; 32], [u8; 32]) {
-        // Use Blake3 with NEON optimizations
-        let mut hasher = Hasher::new();
-        hasher.update(b"SPHINX_DERIVE_SIMD_v1");
-        hasher.update(shared_secret);
-
-        let mut derived_material = [0u8; 96];
-        let mut xof = hasher.finalize_xof();
-        xof.fill(&mut derived_material);
-
-        // Use NEON for efficient memory operations
-        let keys_ptr = derived_material.as_ptr();
-        let key1 = vld1q_u8(keys_ptr);
-        let key2 = vld1q_u8(keys_ptr.add(16));
-        let key3 = vld1q_u8(keys_ptr.add(32));
-        let key4 = vld1q_u8(keys_ptr.add(48));
-        let key5 = vld1q_u8(keys_ptr.add(64));
-        let key6 = vld1q_u8(keys_ptr.add(80));
-
-        let mut header_key = [0u8; 32];
-        let mut payload_key = [0u8; 32];
-        let mut ephemeral_key = [0u8; 32];
+    pub fn backend(&self) -> SimdBackend {
+        self.backend
+    }
 
-        vst1q_u8(header_key.as_mut_ptr(), key1);
-        vst1q_u8(header_key.as_mut_ptr().add(16), key2);
-        vst1q_u8(payload_key.as_mut_ptr(), key3);
-        vst1q_u8(payload_key.as_mut_ptr().add(16), key4);
-        vst1q_u8(ephemeral_key.as_mut_ptr(), key5);
-        vst1q_u8(ephemeral_key.as_mut_ptr().add(16), key6);
+    #[inline(always)]
+    pub fn derive_keys(&self, shared_secret: &[u8; 32]) -> DerivedKeys {
+        // SAFETY: the table only holds functions for a backend this CPU supports
+        unsafe { (self.derive_keys)(shared_secret) }
+    }
 
-        (header_key, payload_key, ephemeral_key)
+    #[inline(always)]
+    pub fn xor(&self, data: &mut [u8], key_stream: &[u8]) {
+        assert_eq!(data.len(), key_stream.len());
+        // SAFETY: as above; the lengths match
+        unsafe { (self.xor)(data, key_stream) }
     }
 
-    /// Optimized scalar implementation with better memory access patterns
-    fn derive_keys_scalar_optimized(&self, shared_secret: &[u8; 32]) -> ([u8; 32], [u8; 32], [u8; 32]) {
-        // Use multiple Blake3 hashers in parallel for better pipeline utilization
-        let mut hasher1 = Hasher::new();
-        let mut hasher2 = Hasher::new();
-        let mut hasher3 = Hasher::new();
+    #[inline(always)]
+    pub fn copy(&self, dst: &mut [u8], src: &[u8]) {
+        assert_eq!(dst.len(), src.len());
+        // SAFETY: as above; the lengths match
+        unsafe { (self.copy)(dst, src) }
+    }
 
-        // Derive each key with different contexts to avoid correlation
-        hasher1.update(b"SPHINX_HEADER_KEY_v1");
-        hasher1.update(shared_secret);
+    #[inline(always)]
+    pub fn clear(&self, dst: &mut [u8]) {
+        // SAFETY: as above
+        unsafe { (self.clear)(dst) }
+    }
+}
 
-        hasher2.update(b"SPHINX_PAYLOAD_KEY_v1");
-        hasher2.update(shared_secret);
+/// SIMD-optimized Blake3 key derivation
+/// Generates header_key, payload_key, and header MAC key material in one XOF call
+pub struct SimdKeyDeriver {
+    dispatch: SimdDispatch,
+}
 
-        hasher3.update(b"SPHINX_EPHEMERAL_KEY_v1");
-        hasher3.update(shared_secret);
+impl SimdKeyDeriver {
+    pub fn new() -> Self {
+        Self::with_dispatch(*simd_dispatch())
+    }
 
-        let header_key = *hasher1.finalize().as_bytes();
-        let payload_key = *hasher2.finalize().as_bytes();
-        let ephemeral_key = *hasher3.finalize().as_bytes();
+    pub fn with_dispatch(dispatch: SimdDispatch) -> Self {
+        Self { dispatch }
+    }
 
-        (header_key, payload_key, ephemeral_key)
+    #[inline(always)]
+    pub fn derive_keys_simd(&self, shared_secret: &[u8; 32]) -> DerivedKeys {
+        self.dispatch.derive_keys(shared_secret)
     }
 }
 
 /// SIMD-optimized XOR operations for packet processing
 pub struct SimdXorProcessor {
-    #[cfg(target_arch = "aarch64")]
-    neon_support: bool,
-    #[cfg(target_arch = "x86_64")]
-    avx2_support: bool,
+    dispatch: SimdDispatch,
 }
 
 impl SimdXorProcessor {
     pub fn new() -> Self {
-        Self {
-            #[cfg(target_arch = "aarch64")]
-            neon_support: std::arch::is_aarch64_feature_detected!("neon"),
-            #[cfg(target_arch = "x86_64")]
-            avx2_support: std::arch::is_x86_feature_detected!("avx2"),
-        }
+        Self::with_dispatch(*simd_dispatch())
+    }
+
+    pub fn with_dispatch(dispatch: SimdDispatch) -> Self {
+        Self { dispatch }
     }
 
     /// SIMD-optimized XOR operation for packet encryption/decryption
     #[inline(always)]
     pub fn xor_packets(&self, data: &mut [u8], key_stream: &[u8]) {
-        assert_eq!(data.len(), key_stream.len());
+        self.dispatch.xor(data, key_stream);
+    }
+}
 
-        #[cfg(target_arch = "x86_64")]
-        if self.avx2_support && data.len() >= 32 {
-            unsafe {
-                return self.xor_packets_avx2(data, key_stream);
-            }
-        }
+/// SIMD-optimized memory operations
+pub struct SimdMemoryOps {
+    dispatch: SimdDispatch,
+}
 
-        #[cfg(target_arch = "aarch64")]
-        if self.neon_support && data.len() >= 16 {
-            unsafe {
-                return self.xor_packets_neon(data, key_stream);
-            }
-        }
+impl SimdMemoryOps {
+    pub fn new() -> Self {
+        Self::with_dispatch(*simd_dispatch())
+    }
 
-        // Fallback to optimized scalar XOR
-        self.xor_packets_scalar_optimized(data, key_stream);
+    pub fn with_dispatch(dispatch: SimdDispatch) -> Self {
+        Self { dispatch }
     }
 
-    #[cfg(target_arch = "x86_64")]
-    #[target_feature(enable = "avx2")]
-    unsafe fn xor_packets_avx2(&self, data: &mut [u8], key_stream: &[u8]) {
-        let len = data.len();
-        let mut i = 0;
+    #[inline(always)]
+    pub fn fast_copy(&self, dst: &mut [u8], src: &[u8]) {
+        self.dispatch.copy(dst, src);
+    }
 
-        // Process 32 bytes at a time with AVX2
-        while i + 32 <= len {
-            let data_vec = _mm256_loadu_si256(data.as_ptr().add(i) as *const __m256i);
-            let key_vec = _mm256_loadu_si256(key_stream.as_ptr().add(i) as *const __m256i);
-            let result = _mm256_xor_si256(data_vec, key_vec);
-            _mm256_storeu_si256(data.as_mut_ptr().add(i) as *mut __m256i, result);
-            i += 32;
-        }
+    #[inline(always)]
+    pub fn fast_clear(&self, dst: &mut [u8]) {
+        self.dispatch.clear(dst);
+    }
+}
 
-        // Handle remaining bytes with SSE2
-        while i + 16 <= len {
-            let data_vec = _mm_loadu_si128(data.as_ptr().add(i) as *const __m128i);
-            let key_vec = _mm_loadu_si128(key_stream.as_ptr().add(i) as *const __m128i);
-            let result = _mm_xor_si128(data_vec, key_vec);
-            _mm_storeu_si128(data.as_mut_ptr().add(i) as *mut __m128i, result);
-            i += 16;
-        }
+/// 96 bytes of key material from the shared secret
+#[inline(always)]
+fn key_material(shared_secret: &[u8; 32]) -> [u8; 96] {
+    let mut hasher = Hasher::new();
+    hasher.update(b"SPHINX_DERIVE_SIMD_v1");
+    hasher.update(shared_secret);
 
-        // Handle remaining bytes scalar
-        while i < len {
-            data[i] ^= key_stream[i];
-            i += 1;
-        }
-    }
+    let mut derived_material = [0u8; 96];
+    hasher.finalize_xof().fill(&mut derived_material);
+    derived_material
+}
 
-    #[cfg(target_arch = "aarch64")]
-    #[target_feature(enable = "neon")]
-    unsafe fn xor_packets_neon(&self, data: &mut [u8], key_stream: &[u8]) {
-        let len = data.len();
-        let mut i = 0;
+// Scalar backend
 
-        // Process 16 bytes at a time with NEON
-        while i + 16 <= len {
-            let data_vec = vld1q_u8(data.as_ptr().add(i));
-            let key_vec = vld1q_u8(key_stream.as_ptr().add(i));
-            let result = veorq_u8(data_vec, key_vec);
-            vst1q_u8(data.as_mut_ptr().add(i), result);
-            i += 16;
-        }
+unsafe fn derive_keys_scalar(shared_secret: &[u8; 32]) -> DerivedKeys {
+    let derived_material = key_material(shared_secret);
 
-        // Process 8 bytes at a time
-        while i + 8 <= len {
-            let data_vec = vld1_u8(data.as_ptr().add(i));
-            let key_vec = vld1_u8(key_stream.as_ptr().add(i));
-            let result = veor_u8(data_vec, key_vec);
-            vst1_u8(data.as_mut_ptr().add(i), result);
-            i += 8;
-        }
+    let mut header_key = [0u8; 32];
+    let mut payload_key = [0u8; 32];
+    let mut mac_key = [0u8; 32];
 
-        // Handle remaining bytes scalar
-        while i < len {
-            data[i] ^= key_stream[i];
-            i += 1;
-        }
-    }
+    header_key.copy_from_slice(&derived_material[0..32]);
+    payload_key.copy_from_slice(&derived_material[32..64]);
+    mac_key.copy_from_slice(&derived_material[64..96]);
 
-    /// Optimized scalar XOR with better loop unrolling
-    fn xor_packets_scalar_optimized(&self, data: &mut [u8], key_stream: &[u8]) {
-        let len = data.len();
-        let mut i = 0;
-
-        // Process 8 bytes at a time for better pipeline utilization
-        while i + 8 <= len {
-            data[i] ^= key_stream[i];
-            data[i + 1] ^= key_stream[i + 1];
-            data[i + 2] ^= key_stream[i + 2];
-            data[i + 3] ^= key_stream[i + 3];
-            data[i + 4] ^= key_stream[i + 4];
-            data[i + 5] ^= key_stream[i + 5];
-            data[i + 6] ^= key_stream[i + 6];
-            data[i + 7] ^= key_stream[i + 7];
-            i += 8;
-        }
+    (header_key, payload_key, mac_key)
+}
 
-        // Handle remaining bytes
-        while i < len {
-            data[i] ^= key_stream[i];
-            i += 1;
-        }
+/// Scalar XOR unrolled 8 bytes at a time for better pipeline utilization
+unsafe fn xor_scalar(data: &mut [u8], key_stream: &[u8]) {
+    let mut data_chunks = data.chunks_exact_mut(8);
+    let mut key_chunks = key_stream.chunks_exact(8);
+    for (d, k) in (&mut data_chunks).zip(&mut key_chunks) {
+        let word = u64::from_ne_bytes(d.try_into().unwrap()) ^ u64::from_ne_bytes(k.try_into().unwrap());
+        d.copy_from_slice(&word.to_ne_bytes());
+    }
+
+    for (d, k) in data_chunks.into_remainder().iter_mut().zip(key_chunks.remainder()) {
+        *d ^= k;
     }
 }
 
-/// SIMD-optimized memory operations
-pub struct SimdMemoryOps {
-    #[cfg(target_arch = "aarch64")]
-    neon_support: bool,
-    #[cfg(target_arch = "x86_64")]
-    avx2_support: bool,
+unsafe fn copy_scalar(dst: &mut [u8], src: &[u8]) {
+    dst.copy_from_slice(src);
 }
 
-impl SimdMemoryOps {
-    pub fn new() -> Self {
-        Self {
-            #[cfg(target_arch = "aarch64")]
-            neon_support: std::arch::is_aarch64_feature_detected!("neon"),
-            #[cfg(target_arch = "x86_64")]
-            avx2_support: std::arch::is_x86_feature_detected!("avx2"),
-        }
-    }
+unsafe fn clear_scalar(dst: &mut [u8]) {
+    dst.fill(0);
+}
 
-    /// SIMD-optimized memory copy
-    #[inline(always)]
-    pub fn fast_copy(&self, dst: &mut [u8], src: &[u8]) {
-        assert_eq!(dst.len(), src.len());
+// AVX2 backend
 
-        #[cfg(target_arch = "x86_64")]
-        if self.avx2_support && src.len() >= 32 {
-            unsafe {
-                return self.fast_copy_avx2(dst, src);
-            }
-        }
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn derive_keys_avx2(shared_secret: &[u8; 32]) -> DerivedKeys {
+    let derived_material = key_material(shared_secret);
 
-        #[cfg(target_arch = "aarch64")]
-        if self.neon_support && src.len() >= 16 {
-            unsafe {
-                return self.fast_copy_neon(dst, src);
-            }
-        }
+    let keys_ptr = derived_material.as_ptr() as *const __m256i;
+    let mut header_key = [0u8; 32];
+    let mut payload_key = [0u8; 32];
+    let mut mac_key = [0u8; 32];
+
+    _mm256_storeu_si256(header_key.as_mut_ptr() as *mut __m256i, _mm256_loadu_si256(keys_ptr));
+    _mm256_storeu_si256(payload_key.as_mut_ptr() as *mut __m256i, _mm256_loadu_si256(keys_ptr.add(1)));
+    _mm256_storeu_si256(mac_key.as_mut_ptr() as *mut __m256i, _mm256_loadu_si256(keys_ptr.add(2)));
 
-        // Fallback to optimized memcpy
-        dst.copy_from_slice(src);
+    (header_key, payload_key, mac_key)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn xor_avx2(data: &mut [u8], key_stream: &[u8]) {
+    let len = data.len();
+    let mut i = 0;
+
+    // Process 32 bytes at a time with AVX2
+    while i + 32 <= len {
+        let data_vec = _mm256_loadu_si256(data.as_ptr().add(i) as *const __m256i);
+        let key_vec = _mm256_loadu_si256(key_stream.as_ptr().add(i) as *const __m256i);
+        _mm256_storeu_si256(data.as_mut_ptr().add(i) as *mut __m256i, _mm256_xor_si256(data_vec, key_vec));
+        i += 32;
     }
 
-    #[cfg(target_arch = "x86_64")]
-    #[target_feature(enable = "avx2")]
-    unsafe fn fast_copy_avx2(&self, dst: &mut [u8], src: &[u8]) {
-        let len = src.len();
-        let mut i = 0;
+    xor_sse41(&mut data[i..], &key_stream[i..]);
+}
 
-        // Copy 32 bytes at a time with AVX2
-        while i + 32 <= len {
-            let data = _mm256_loadu_si256(src.as_ptr().add(i) as *const __m256i);
-            _mm256_storeu_si256(dst.as_mut_ptr().add(i) as *mut __m256i, data);
-            i += 32;
-        }
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn copy_avx2(dst: &mut [u8], src: &[u8]) {
+    let len = src.len();
+    let mut i = 0;
+
+    while i + 32 <= len {
+        let data = _mm256_loadu_si256(src.as_ptr().add(i) as *const __m256i);
+        _mm256_storeu_si256(dst.as_mut_ptr().add(i) as *mut __m256i, data);
+        i += 32;
+    }
 
-        // Copy remaining bytes with SSE2
-        while i + 16 <= len {
-            let data = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
-            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, data);
-            i += 16;
-        }
+    copy_sse41(&mut dst[i..], &src[i..]);
+}
 
-        // Copy remaining bytes
-        dst[i..].copy_from_slice(&src[i..]);
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn clear_avx2(dst: &mut [u8]) {
+    let len = dst.len();
+    let mut i = 0;
+    let zero = _mm256_setzero_si256();
+
+    while i + 32 <= len {
+        _mm256_storeu_si256(dst.as_mut_ptr().add(i) as *mut __m256i, zero);
+        i += 32;
     }
 
-    #[cfg(target_arch = "aarch64")]
-    #[target_feature(enable = "neon")]
-    unsafe fn fast_copy_neon(&self, dst: &mut [u8], src: &[u8]) {
-        let len = src.len();
-        let mut i = 0;
+    clear_sse41(&mut dst[i..]);
+}
 
-        // Copy 16 bytes at a time with NEON
-        while i + 16 <= len {
-            let data = vld1q_u8(src.as_ptr().add(i));
-            vst1q_u8(dst.as_mut_ptr().add(i), data);
-            i += 16;
-        }
+// SSE4.1 backend
 
-        // Copy remaining bytes
-        dst[i..].copy_from_slice(&src[i..]);
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn derive_keys_sse41(shared_secret: &[u8; 32]) -> DerivedKeys {
+    let derived_material = key_material(shared_secret);
+    let mut keys = [[0u8; 32]; 3];
+
+    for (block, key) in derived_material.chunks_exact(32).zip(keys.iter_mut()) {
+        let low = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+        let high = _mm_loadu_si128(block.as_ptr().add(16) as *const __m128i);
+        _mm_storeu_si128(key.as_mut_ptr() as *mut __m128i, low);
+        _mm_storeu_si128(key.as_mut_ptr().add(16) as *mut __m128i, high);
     }
 
-    /// SIMD-optimized memory clear
-    #[inline(always)]
-    pub fn fast_clear(&self, dst: &mut [u8]) {
-        #[cfg(target_arch = "x86_64")]
-        if self.avx2_support && dst.len() >= 32 {
-            unsafe {
-                return self.fast_clear_avx2(dst);
-            }
-        }
+    (keys[0], keys[1], keys[2])
+}
 
-        #[cfg(target_arch = "aarch64")]
-        if self.neon_support && dst.len() >= 16 {
-            unsafe {
-                return self.fast_clear_neon(dst);
-            }
-        }
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn xor_sse41(data: &mut [u8], key_stream: &[u8]) {
+    let len = data.len();
+    let mut i = 0;
+
+    // Process 16 bytes at a time
+    while i + 16 <= len {
+        let data_vec = _mm_loadu_si128(data.as_ptr().add(i) as *const __m128i);
+        let key_vec = _mm_loadu_si128(key_stream.as_ptr().add(i) as *const __m128i);
+        _mm_storeu_si128(data.as_mut_ptr().add(i) as *mut __m128i, _mm_xor_si128(data_vec, key_vec));
+        i += 16;
+    }
+
+    xor_scalar(&mut data[i..], &key_stream[i..]);
+}
 
-        // Fallback
-        dst.fill(0);
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn copy_sse41(dst: &mut [u8], src: &[u8]) {
+    let len = src.len();
+    let mut i = 0;
+
+    while i + 16 <= len {
+        let data = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+        _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, data);
+        i += 16;
     }
 
-    #[cfg(target_arch = "x86_64")]
-    #[target_feature(enable = "avx2")]
-    unsafe fn fast_clear_avx2(&self, dst: &mut [u8]) {
-        let len = dst.len();
-        let mut i = 0;
-        let zero = _mm256_setzero_si256();
+    dst[i..].copy_from_slice(&src[i..]);
+}
 
-        // Clear 32 bytes at a time
-        while i + 32 <= len {
-            _mm256_storeu_si256(dst.as_mut_ptr().add(i) as *mut __m256i, zero);
-            i += 32;
-        }
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn clear_sse41(dst: &mut [u8]) {
+    let len = dst.len();
+    let mut i = 0;
+    let zero = _mm_setzero_si128();
+
+    while i + 16 <= len {
+        _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, zero);
+        i += 16;
+    }
 
-        // Clear remaining bytes
-        dst[i..].fill(0);
+    dst[i..].fill(0);
+}
+
+// NEON backend
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn derive_keys_neon(shared_secret: &[u8; 32]) -> DerivedKeys {
+    let derived_material = key_material(shared_secret);
+    let mut keys = [[0u8; 32]; 3];
+
+    for (block, key) in derived_material.chunks_exact(32).zip(keys.iter_mut()) {
+        vst1q_u8(key.as_mut_ptr(), vld1q_u8(block.as_ptr()));
+        vst1q_u8(key.as_mut_ptr().add(16), vld1q_u8(block.as_ptr().add(16)));
     }
 
-    #[cfg(target_arch = "aarch64")]
-    #[target_feature(enable = "neon")]
-    unsafe fn fast_clear_neon(&self, dst: &mut [u8]) {
-        let len = dst.len();
-        let mut i = 0;
-        let zero = vdupq_n_u8(0);
+    (keys[0], keys[1], keys[2])
+}
 
-        // Clear 16 bytes at a time
-        while i + 16 <= len {
-            vst1q_u8(dst.as_mut_ptr().add(i), zero);
-            i += 16;
-        }
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn xor_neon(data: &mut [u8], key_stream: &[u8]) {
+    let len = data.len();
+    let mut i = 0;
+
+    // Process 16 bytes at a time with NEON
+    while i + 16 <= len {
+        let data_vec = vld1q_u8(data.as_ptr().add(i));
+        let key_vec = vld1q_u8(key_stream.as_ptr().add(i));
+        vst1q_u8(data.as_mut_ptr().add(i), veorq_u8(data_vec, key_vec));
+        i += 16;
+    }
+
+    xor_scalar(&mut data[i..], &key_stream[i..]);
+}
 
-        // Clear remaining bytes
-        dst[i..].fill(0);
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn copy_neon(dst: &mut [u8], src: &[u8]) {
+    let len = src.len();
+    let mut i = 0;
+
+    while i + 16 <= len {
+        vst1q_u8(dst.as_mut_ptr().add(i), vld1q_u8(src.as_ptr().add(i)));
+        i += 16;
     }
+
+    dst[i..].copy_from_slice(&src[i..]);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn clear_neon(dst: &mut [u8]) {
+    let len = dst.len();
+    let mut i = 0;
+    let zero = vdupq_n_u8(0);
+
+    while i + 16 <= len {
+        vst1q_u8(dst.as_mut_ptr().add(i), zero);
+        i += 16;
+    }
+
+    dst[i..].fill(0);
 }
 
 #[cfg(test)]
@@ -459,4 +522,48 @@ mod tests {
         ops.fast_clear(&mut dst);
         assert_eq!(dst, vec![0u8; 1024]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_backends_produce_identical_output() {
+        use rand::{Rng, RngCore};
+
+        let scalar = SimdDispatch::for_backend(SimdBackend::Scalar).unwrap();
+        let backends: Vec<SimdDispatch> = SimdBackend::available()
+            .into_iter()
+            .map(|backend| SimdDispatch::for_backend(backend).unwrap())
+            .collect();
+        assert_eq!(backends.last().unwrap().backend(), SimdBackend::Scalar);
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..500 {
+            // Random lengths exercise every vector width and tail
+            let len = rng.gen_range(0..1100);
+            let mut data = vec![0u8; len];
+            let mut key_stream = vec![0u8; len];
+            let mut secret = [0u8; 32];
+            rng.fill_bytes(&mut data);
+            rng.fill_bytes(&mut key_stream);
+            rng.fill_bytes(&mut secret);
+
+            let mut expected_xor = data.clone();
+            scalar.xor(&mut expected_xor, &key_stream);
+
+            for dispatch in &backends {
+                assert_eq!(dispatch.derive_keys(&secret), scalar.derive_keys(&secret), "{:?}", dispatch.backend());
+
+                let mut xored = data.clone();
+                dispatch.xor(&mut xored, &key_stream);
+                assert_eq!(xored, expected_xor, "{:?}", dispatch.backend());
+
+                let mut copied = vec![0u8; len];
+                dispatch.copy(&mut copied, &data);
+                assert_eq!(copied, data, "{:?}", dispatch.backend());
+
+                dispatch.clear(&mut copied);
+                assert!(copied.iter().all(|&b| b == 0), "{:?}", dispatch.backend());
+            }
+        }
+
+        assert!(SimdDispatch::for_backend(SimdBackend::detect()).is_some());
+    }
+}
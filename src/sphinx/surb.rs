@@ -0,0 +1,153 @@
+// Single-use reply blocks (SURBs)
+// The sender pre-builds a header routed back to itself; the recipient attaches a reply payload
+// without learning the route, and only the sender can remove the reply's payload layers
+
+use rand_core::{CryptoRng, OsRng, RngCore};
+
+use crate::sphinx::builder::encode_payload_message;
+use crate::sphinx::lioness::Lioness;
+use crate::sphinx::packet::{
+    SphinxPacket, SphinxHeader, SphinxError, MixNodeId, decode_payload_message,
+    SPHINX_HEADER_SIZE, SPHINX_PAYLOAD_SIZE,
+};
+
+/// Identifies a SURB to its creator when the reply arrives
+pub type SurbId = [u8; 32];
+
+/// Serialized SURB: first hop (32) + header + reply key (32)
+pub const SURB_SIZE: usize = 32 + SPHINX_HEADER_SIZE + 32;
+
+/// Reply block handed to the recipient of a message
+#[derive(Debug, Clone)]
+pub struct ReplySurb {
+    /// Mix the reply packet must be sent to
+    pub first_hop: MixNodeId,
+    pub header: SphinxHeader,
+    reply_key: [u8; 32],
+}
+
+/// Secrets the SURB creator keeps to read the reply
+pub struct SurbDecryptionKeys {
+    pub surb_id: SurbId,
+    reply_key: [u8; 32],
+    hop_payload_keys: Vec<[u8; 32]>,
+}
+
+impl ReplySurb {
+    pub(crate) fn new(first_hop: MixNodeId, header: SphinxHeader, reply_key: [u8; 32]) -> Self {
+        Self {
+            first_hop,
+            header,
+            reply_key,
+        }
+    }
+
+    /// Attach a reply message, producing the packet to send to `first_hop`
+    pub fn reply(&self, message: &[u8]) -> Result<SphinxPacket, SphinxError> {
+        self.reply_with_rng(message, &mut OsRng)
+    }
+
+    pub fn reply_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        message: &[u8],
+        rng: &mut R
+    ) -> Result<SphinxPacket, SphinxError> {
+        let mut payload = encode_payload_message(message, rng)?;
+        Lioness::new(&self.reply_key).encrypt(&mut payload);
+
+        Ok(SphinxPacket {
+            header: self.header.clone(),
+            payload,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SURB_SIZE);
+        bytes.extend_from_slice(&self.first_hop);
+        bytes.extend_from_slice(&self.header.to_bytes());
+        bytes.extend_from_slice(&self.reply_key);
+        bytes
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, SphinxError> {
+        if data.len() != SURB_SIZE {
+            return Err(SphinxError::InvalidPacketSize);
+        }
+
+        let mut first_hop = [0u8; 32];
+        first_hop.copy_from_slice(&data[..32]);
+
+        let header = SphinxHeader::from_bytes(&data[32..32 + SPHINX_HEADER_SIZE])?;
+
+        let mut reply_key = [0u8; 32];
+        reply_key.copy_from_slice(&data[32 + SPHINX_HEADER_SIZE..]);
+
+        Ok(Self::new(first_hop, header, reply_key))
+    }
+}
+
+impl SurbDecryptionKeys {
+    pub(crate) fn new(surb_id: SurbId, reply_key: [u8; 32], hop_payload_keys: Vec<[u8; 32]>) -> Self {
+        Self {
+            surb_id,
+            reply_key,
+            hop_payload_keys,
+        }
+    }
+
+    /// Recover the reply message from the payload delivered by the final hop
+    pub fn decrypt_reply(&self, payload: &[u8]) -> Result<Vec<u8>, SphinxError> {
+        let mut block: [u8; SPHINX_PAYLOAD_SIZE] = payload
+            .try_into()
+            .map_err(|_| SphinxError::InvalidPayloadSize)?;
+
+        // Each hop decrypted one layer; re-encrypting in reverse order undoes them
+        for key in self.hop_payload_keys.iter().rev() {
+            Lioness::new(key).encrypt(&mut block);
+        }
+        Lioness::new(&self.reply_key).decrypt(&mut block);
+
+        decode_payload_message(&block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphinx::{SphinxMixer, SphinxPacketBuilder, MixError, ProcessedPayload, RoutingCommand};
+    use curve25519_dalek::scalar::Scalar;
+
+    #[test]
+    fn test_surb_reply_round_trip() {
+        let mut mixers: Vec<(MixNodeId, SphinxMixer)> = (1..=3u8)
+            .map(|i| ([i; 32], SphinxMixer::new(Scalar::from_bytes_mod_order([i + 40; 32]))))
+            .collect();
+        let route: Vec<_> = mixers.iter().map(|(id, mixer)| (*id, mixer.public_key())).collect();
+
+        let (surb, keys) = SphinxPacketBuilder::new().route(route).build_surb().unwrap();
+
+        // The recipient only sees the serialized SURB
+        let surb = ReplySurb::from_bytes(&surb.to_bytes()).unwrap();
+        assert_eq!(surb.first_hop, [1u8; 32]);
+        let mut packet = surb.reply(b"anonymous answer").unwrap();
+
+        for hop in 0..mixers.len() {
+            let processed = mixers[hop].1.process_packet(&packet).unwrap();
+            match (processed.routing_info.command, processed.payload) {
+                (RoutingCommand::Forward { .. }, ProcessedPayload::Forward(next_packet)) => {
+                    packet = SphinxPacket::from_bytes(&next_packet).unwrap();
+                }
+                (RoutingCommand::Reply { surb_id }, ProcessedPayload::Reply(payload)) => {
+                    assert_eq!(hop, mixers.len() - 1);
+                    assert_eq!(surb_id, keys.surb_id);
+                    assert_eq!(keys.decrypt_reply(&payload).unwrap(), b"anonymous answer");
+                }
+                _ => panic!("unexpected routing result at hop {}", hop),
+            }
+        }
+
+        // A SURB carries one reply; a second use is caught by the first hop's replay cache
+        let second_reply = surb.reply(b"second answer").unwrap();
+        assert!(matches!(mixers[0].1.process_packet(&second_reply), Err(MixError::Replay)));
+    }
+}
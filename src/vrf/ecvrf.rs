@@ -0,0 +1,252 @@
+// ECVRF over Ristretto255 with SHA-512, following the RFC 9381 construction
+// (encode to curve, nonce generation, challenge truncated to 16 bytes, proof = Gamma || c || s)
+
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+};
+use sha2::{Digest, Sha512};
+use rand_core::{CryptoRng, RngCore};
+
+/// Suite identifier mixed into every hash
+const SUITE_STRING: &[u8] = b"ECVRF-RISTRETTO255-SHA512";
+const CHALLENGE_SIZE: usize = 16;
+
+/// Serialized proof: Gamma (32) + c (16) + s (32)
+pub const VRF_PROOF_SIZE: usize = 32 + CHALLENGE_SIZE + 32;
+/// Size of the hash output derived from a proof
+pub const VRF_OUTPUT_SIZE: usize = 64;
+
+pub type VrfOutput = [u8; VRF_OUTPUT_SIZE];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum VrfProofError {
+    #[error("Malformed VRF proof")]
+    Malformed,
+    #[error("Invalid VRF public key")]
+    InvalidPublicKey,
+    #[error("VRF proof does not verify")]
+    VerificationFailed,
+}
+
+/// VRF public key (compressed Ristretto point)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VrfPublicKey(pub [u8; 32]);
+
+/// VRF secret key
+pub struct VrfSecretKey {
+    scalar: Scalar,
+    // Secret prefix for deterministic nonces, as in RFC 8032 key expansion
+    nonce_key: [u8; 32],
+    public_key: VrfPublicKey,
+}
+
+/// ECVRF proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VrfProof {
+    gamma: CompressedRistretto,
+    challenge: [u8; CHALLENGE_SIZE],
+    response: Scalar,
+}
+
+impl VrfSecretKey {
+    /// Expand a 32-byte seed into a VRF key
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let expanded = Sha512::digest(seed);
+
+        let mut wide = [0u8; 64];
+        wide[..32].copy_from_slice(&expanded[..32]);
+        let scalar = Scalar::from_bytes_mod_order_wide(&wide);
+
+        let mut nonce_key = [0u8; 32];
+        nonce_key.copy_from_slice(&expanded[32..]);
+
+        let public_key = VrfPublicKey((scalar * RISTRETTO_BASEPOINT_POINT).compress().to_bytes());
+
+        Self {
+            scalar,
+            nonce_key,
+            public_key,
+        }
+    }
+
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        Self::from_seed(&seed)
+    }
+
+    pub fn public_key(&self) -> VrfPublicKey {
+        self.public_key
+    }
+
+    /// Prove the VRF output for `alpha`
+    pub fn prove(&self, alpha: &[u8]) -> VrfProof {
+        let h = encode_to_curve(&self.public_key, alpha);
+        let h_bytes = h.compress();
+        let gamma = self.scalar * h;
+
+        let k = self.nonce(&h_bytes);
+        let challenge = challenge(
+            &self.public_key,
+            &h_bytes,
+            &gamma.compress(),
+            &(k * RISTRETTO_BASEPOINT_POINT).compress(),
+            &(k * h).compress(),
+        );
+        let response = k + challenge_scalar(&challenge) * self.scalar;
+
+        VrfProof {
+            gamma: gamma.compress(),
+            challenge,
+            response,
+        }
+    }
+
+    /// Prove and return the VRF output in one step
+    pub fn evaluate(&self, alpha: &[u8]) -> (VrfOutput, VrfProof) {
+        let proof = self.prove(alpha);
+        (proof.output(), proof)
+    }
+
+    fn nonce(&self, h: &CompressedRistretto) -> Scalar {
+        let mut hasher = Sha512::new();
+        hasher.update(self.nonce_key);
+        hasher.update(h.as_bytes());
+        Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
+    }
+}
+
+impl VrfPublicKey {
+    /// Verify a proof for `alpha`, returning the VRF output on success
+    pub fn verify(&self, alpha: &[u8], proof: &VrfProof) -> Result<VrfOutput, VrfProofError> {
+        let y = CompressedRistretto(self.0)
+            .decompress()
+            .ok_or(VrfProofError::InvalidPublicKey)?;
+        let gamma = proof.gamma.decompress().ok_or(VrfProofError::Malformed)?;
+
+        let h = encode_to_curve(self, alpha);
+        let c = challenge_scalar(&proof.challenge);
+
+        // U = s*B - c*Y, V = s*H - c*Gamma
+        let u = proof.response * RISTRETTO_BASEPOINT_POINT - c * y;
+        let v = proof.response * h - c * gamma;
+
+        let expected = challenge(self, &h.compress(), &proof.gamma, &u.compress(), &v.compress());
+        if expected != proof.challenge {
+            return Err(VrfProofError::VerificationFailed);
+        }
+
+        Ok(proof.output())
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl VrfProof {
+    /// Hash the proof to its VRF output (proof_to_hash)
+    pub fn output(&self) -> VrfOutput {
+        let mut hasher = Sha512::new();
+        hasher.update(SUITE_STRING);
+        hasher.update([0x03]);
+        hasher.update(self.gamma.as_bytes());
+        hasher.update([0x00]);
+        hasher.finalize().into()
+    }
+
+    pub fn to_bytes(&self) -> [u8; VRF_PROOF_SIZE] {
+        let mut bytes = [0u8; VRF_PROOF_SIZE];
+        bytes[..32].copy_from_slice(self.gamma.as_bytes());
+        bytes[32..32 + CHALLENGE_SIZE].copy_from_slice(&self.challenge);
+        bytes[32 + CHALLENGE_SIZE..].copy_from_slice(self.response.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VrfProofError> {
+        if bytes.len() != VRF_PROOF_SIZE {
+            return Err(VrfProofError::Malformed);
+        }
+
+        let mut gamma = [0u8; 32];
+        gamma.copy_from_slice(&bytes[..32]);
+        let mut challenge = [0u8; CHALLENGE_SIZE];
+        challenge.copy_from_slice(&bytes[32..32 + CHALLENGE_SIZE]);
+        let mut response = [0u8; 32];
+        response.copy_from_slice(&bytes[32 + CHALLENGE_SIZE..]);
+
+        Ok(Self {
+            gamma: CompressedRistretto(gamma),
+            challenge,
+            response: Option::from(Scalar::from_canonical_bytes(response)).ok_or(VrfProofError::Malformed)?,
+        })
+    }
+}
+
+/// Hash the public key and input to a curve point
+fn encode_to_curve(public_key: &VrfPublicKey, alpha: &[u8]) -> RistrettoPoint {
+    let mut hasher = Sha512::new();
+    hasher.update(SUITE_STRING);
+    hasher.update([0x01]);
+    hasher.update(public_key.0);
+    hasher.update(alpha);
+    hasher.update([0x00]);
+    RistrettoPoint::from_uniform_bytes(&hasher.finalize().into())
+}
+
+fn challenge(
+    public_key: &VrfPublicKey,
+    h: &CompressedRistretto,
+    gamma: &CompressedRistretto,
+    u: &CompressedRistretto,
+    v: &CompressedRistretto,
+) -> [u8; CHALLENGE_SIZE] {
+    let mut hasher = Sha512::new();
+    hasher.update(SUITE_STRING);
+    hasher.update([0x02]);
+    for point in [&public_key.0, h.as_bytes(), gamma.as_bytes(), u.as_bytes(), v.as_bytes()] {
+        hasher.update(point);
+    }
+    hasher.update([0x00]);
+
+    let mut c = [0u8; CHALLENGE_SIZE];
+    c.copy_from_slice(&hasher.finalize()[..CHALLENGE_SIZE]);
+    c
+}
+
+fn challenge_scalar(challenge: &[u8; CHALLENGE_SIZE]) -> Scalar {
+    let mut bytes = [0u8; 32];
+    bytes[..CHALLENGE_SIZE].copy_from_slice(challenge);
+    Scalar::from_bytes_mod_order(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ecvrf_prove_verify() {
+        let secret = VrfSecretKey::from_seed(&[7u8; 32]);
+        let public = secret.public_key();
+
+        let (output, proof) = secret.evaluate(b"stream-1");
+        assert_eq!(public.verify(b"stream-1", &proof), Ok(output));
+
+        // Deterministic and bound to the input
+        assert_eq!(secret.prove(b"stream-1"), proof);
+        assert_eq!(public.verify(b"stream-2", &proof), Err(VrfProofError::VerificationFailed));
+
+        // Bound to the key
+        let other = VrfSecretKey::from_seed(&[8u8; 32]).public_key();
+        assert_eq!(other.verify(b"stream-1", &proof), Err(VrfProofError::VerificationFailed));
+
+        // Serialization round trip, and tampering is rejected
+        let mut bytes = proof.to_bytes();
+        assert_eq!(VrfProof::from_bytes(&bytes), Ok(proof));
+        bytes[40] ^= 0x01;
+        let tampered = VrfProof::from_bytes(&bytes).unwrap();
+        assert!(public.verify(b"stream-1", &tampered).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+// VRF key persistence
+// Path proofs are checked against the registry's VRF public key, so the key has to survive
+// restarts for published proofs and the announced public key to stay valid.
+
+use std::path::{Path, PathBuf};
+use rand_core::{OsRng, RngCore};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+use crate::sphinx::keys::{write_private, KeyStoreError};
+use crate::sphinx::MixNodeId;
+use crate::vrf::ecvrf::{VrfPublicKey, VrfSecretKey};
+
+#[derive(Serialize, Deserialize)]
+struct StoredVrfKey {
+    seed: String,
+}
+
+/// JSON file holding the seed of the node's VRF key
+pub struct VrfKeyStore {
+    path: PathBuf,
+}
+
+impl VrfKeyStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn load(&self) -> Result<Option<VrfSecretKey>, KeyStoreError> {
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let stored: StoredVrfKey = serde_json::from_slice(&data)
+            .map_err(|e| KeyStoreError::Malformed(e.to_string()))?;
+        let seed: [u8; 32] = hex::decode(&stored.seed)
+            .map_err(|e| KeyStoreError::Malformed(e.to_string()))?
+            .try_into()
+            .map_err(|_| KeyStoreError::Malformed("VRF seed must be 32 bytes".to_string()))?;
+
+        Ok(Some(VrfSecretKey::from_seed(&seed)))
+    }
+
+    /// Load the stored key, generating and saving one on first start
+    pub fn load_or_generate(&self) -> Result<VrfSecretKey, KeyStoreError> {
+        if let Some(key) = self.load()? {
+            return Ok(key);
+        }
+
+        let mut seed = [0u8; 32];
+        OsRng.fill_bytes(&mut seed);
+        let stored = StoredVrfKey { seed: hex::encode(seed) };
+        let data = serde_json::to_vec_pretty(&stored)
+            .map_err(|e| KeyStoreError::Malformed(e.to_string()))?;
+        write_private(&self.path, &data)?;
+
+        Ok(VrfSecretKey::from_seed(&seed))
+    }
+}
+
+/// Node identity derived from its VRF public key
+pub fn derive_node_id(public_key: &VrfPublicKey) -> MixNodeId {
+    let mut hasher = Sha256::new();
+    hasher.update(b"BETANET_MIX_NODE_ID_v1");
+    hasher.update(public_key.to_bytes());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vrf_key_survives_restart() {
+        let path = std::env::temp_dir().join(format!("vrf-key-{}.json", std::process::id()));
+        let store = VrfKeyStore::new(&path);
+        assert!(store.load().unwrap().is_none());
+
+        let key = store.load_or_generate().unwrap();
+        let restored = store.load_or_generate().unwrap();
+        assert_eq!(restored.public_key(), key.public_key());
+
+        let proof = key.prove(b"epoch 7");
+        assert!(restored.public_key().verify(b"epoch 7", &proof).is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_node_id_follows_persisted_key() {
+        let path = std::env::temp_dir().join(format!("vrf-node-id-{}.json", std::process::id()));
+        let config = crate::MixnodeConfig {
+            worker_threads: 1,
+            vrf_key_path: Some(path.clone()),
+            ..Default::default()
+        };
+
+        let node = crate::HighPerformanceMixnode::new(config.clone()).unwrap();
+        let restarted = crate::HighPerformanceMixnode::new(config).unwrap();
+        assert_eq!(restarted.node_id(), node.node_id());
+
+        let ephemeral = crate::HighPerformanceMixnode::new(crate::MixnodeConfig {
+            worker_threads: 1,
+            ..Default::default()
+        }).unwrap();
+        assert_ne!(ephemeral.node_id(), node.node_id());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,3 +1,7 @@
+pub mod ecvrf;
+mod keys;
 pub mod selection;
 
+pub use ecvrf::*;
+pub use keys::{VrfKeyStore, derive_node_id};
 pub use selection::*;
\ No newline at end of file
@@ -2,30 +2,35 @@ use sha2::{Sha256, Digest};
 use std::collections::HashMap;
 use lru::LruCache;
 use curve25519_dalek::ristretto::RistrettoPoint;
-use ed25519_dalek::{SigningKey, VerifyingKey, Signature, Signer, Verifier};
 use serde::{Serialize, Deserialize};
 use rand_core::OsRng;
 use std::num::NonZeroUsize;
 use crate::sphinx::MixNodeId;
+use crate::vrf::ecvrf::{VrfSecretKey, VrfPublicKey, VrfProof, VrfProofError, VRF_PROOF_SIZE};
 
-/// Real VRF-based mixnode selection using Ed25519
+/// VRF-based mixnode selection using ECVRF
 pub struct MixNodeRegistry {
     nodes: HashMap<MixNodeId, MixNodeInfo>,
-    vrf_signing_key: SigningKey,
-    vrf_verifying_key: VerifyingKey,
-    selection_cache: LruCache<[u8; 32], Vec<MixNodeId>>,
+    vrf_secret_key: VrfSecretKey,
+    selection_cache: LruCache<[u8; 32], (Vec<MixNodeId>, PathSelectionProof)>,
 }
 
 #[derive(Debug, Clone)]
 pub struct MixNodeInfo {
     pub id: MixNodeId,
     pub public_key: RistrettoPoint,
+    pub address: std::net::SocketAddr,
     pub stake_weight: u64,
     pub reliability_score: f64,
     pub geographic_region: Region,
     pub last_seen: std::time::SystemTime,
+    /// Mix layer (1-based) in a stratified topology, None when unassigned
+    pub layer: Option<u8>,
 }
 
+/// Layers in a Nym-style stratified topology: entry, mix, exit
+pub const DEFAULT_MIX_LAYERS: u8 = 3;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Region {
     NorthAmerica,
@@ -37,137 +42,178 @@ pub enum Region {
 }
 
 impl MixNodeRegistry {
+    /// Create registry with a fresh VRF key that is lost on restart
+    ///
+    /// Nodes that publish path proofs should use `with_vrf_key` with a key from `VrfKeyStore`.
     pub fn new() -> Result<Self, VRFError> {
-        // Generate VRF keypair using Ed25519
-        let vrf_signing_key = SigningKey::generate(&mut OsRng);
-        let vrf_verifying_key = vrf_signing_key.verifying_key();
-        
-        Ok(Self {
-            nodes: HashMap::new(),
-            vrf_signing_key,
-            vrf_verifying_key,
-            selection_cache: LruCache::new(NonZeroUsize::new(1000).unwrap()),
-        })
+        Ok(Self::with_vrf_key(VrfSecretKey::generate(&mut OsRng)))
     }
     
-    /// Create registry with existing keypair
-    pub fn with_keypair(signing_key: SigningKey) -> Self {
-        let verifying_key = signing_key.verifying_key();
-        
+    /// Create registry with existing VRF key
+    pub fn with_vrf_key(vrf_secret_key: VrfSecretKey) -> Self {
         Self {
             nodes: HashMap::new(),
-            vrf_signing_key: signing_key,
-            vrf_verifying_key: verifying_key,
+            vrf_secret_key,
             selection_cache: LruCache::new(NonZeroUsize::new(1000).unwrap()),
         }
     }
     
+    /// Create registry from an Ed25519 signing key
+    ///
+    /// The key bytes seed an ECVRF key, so `get_vrf_public_key` returns a Ristretto point
+    /// rather than the Ed25519 verifying key earlier versions exposed.
+    #[deprecated(note = "path proofs use ECVRF now; load a key with `VrfKeyStore` and call `with_vrf_key`")]
+    pub fn with_keypair(signing_key: ed25519_dalek::SigningKey) -> Self {
+        Self::with_vrf_key(VrfSecretKey::from_seed(&signing_key.to_bytes()))
+    }
+    
     /// Get VRF public key for verification
-    pub fn get_vrf_public_key(&self) -> VerifyingKey {
-        self.vrf_verifying_key
+    pub fn get_vrf_public_key(&self) -> VrfPublicKey {
+        self.vrf_secret_key.public_key()
     }
     
     pub fn add_node(&mut self, node: MixNodeInfo) {
         self.nodes.insert(node.id, node);
     }
     
+    pub fn get_node(&self, id: &MixNodeId) -> Option<&MixNodeInfo> {
+        self.nodes.get(id)
+    }
+    
+    /// Advertise a new Sphinx key for `id`; false when the node is not registered
+    pub fn set_public_key(&mut self, id: &MixNodeId, public_key: RistrettoPoint) -> bool {
+        match self.nodes.get_mut(id) {
+            Some(node) => {
+                node.public_key = public_key;
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Reassign every node to one of `layers` layers for `epoch`, balancing stake
+    ///
+    /// Nodes are visited in an epoch-keyed pseudorandom order and each goes to the layer
+    /// with the least stake so far (fewest nodes on ties), so no layer exceeds the average
+    /// by more than the largest single stake. Returns the total stake per layer.
+    pub fn assign_layers(&mut self, epoch: u64, layers: u8) -> Vec<u64> {
+        let layers = layers.max(1) as usize;
+        let mut layer_stake = vec![0u64; layers];
+        let mut layer_size = vec![0usize; layers];
+        
+        let mut order: Vec<_> = self.nodes.keys()
+            .map(|id| {
+                let mut hasher = Sha256::new();
+                hasher.update(b"BETANET_MIX_LAYERS_v1");
+                hasher.update(epoch.to_be_bytes());
+                hasher.update(id);
+                let rank: [u8; 32] = hasher.finalize().into();
+                (rank, *id)
+            })
+            .collect();
+        order.sort();
+        
+        for (_, id) in order {
+            let layer = (0..layers)
+                .min_by_key(|&l| (layer_stake[l], layer_size[l]))
+                .unwrap_or(0);
+            let node = self.nodes.get_mut(&id).expect("id taken from registry");
+            layer_stake[layer] += node.stake_weight;
+            layer_size[layer] += 1;
+            node.layer = Some(layer as u8 + 1);
+        }
+        
+        // Cached paths were drawn from the old layers
+        self.selection_cache.clear();
+        layer_stake
+    }
+    
+    /// Refresh the topology for a new epoch
+    ///
+    /// Paths are seeded by epoch, so cached selections are dropped; a layered topology
+    /// is reassigned with the same number of layers.
+    pub fn advance_epoch(&mut self, epoch: u64) {
+        match self.nodes.values().filter_map(|node| node.layer).max() {
+            Some(layers) => {
+                self.assign_layers(epoch, layers);
+            }
+            None => self.selection_cache.clear(),
+        }
+    }
+    
+    /// Active nodes sorted by id, the registry view a path is selected from
+    pub fn snapshot(&self) -> Vec<MixNodeInfo> {
+        let mut nodes: Vec<_> = self.nodes.values()
+            .filter(|node| self.is_node_active(node))
+            .cloned()
+            .collect();
+        nodes.sort_by_key(|node| node.id);
+        nodes
+    }
+    
     /// CRITICAL: VRF hop selection with stake weighting
+    ///
+    /// Returns the path together with a proof that anyone holding the registry's VRF
+    /// public key and the same registry snapshot can check with `verify_path`. When nodes
+    /// carry layer assignments, hop `i` is drawn from layer `i + 1` and the path must
+    /// cover every layer.
     pub fn select_path(
         &mut self, 
         stream_id: &[u8], 
         epoch: u64,
         path_length: usize
-    ) -> Result<Vec<MixNodeId>, VRFError> {
+    ) -> Result<(Vec<MixNodeId>, PathSelectionProof), VRFError> {
         // Create deterministic but unpredictable seed
-        let mut hasher = Sha256::new();
-        hasher.update(b"BETANET_MIX_PATH_v1");
-        hasher.update(stream_id);
-        hasher.update(&epoch.to_be_bytes());
-        let seed = hasher.finalize();
+        let seed = path_seed(stream_id, epoch);
+        let snapshot = self.snapshot();
+        let registry_snapshot = snapshot_hash(&snapshot);
+        
+        // Cached paths only hold while the nodes they were drawn from are unchanged
+        if let Some(cached) = self.selection_cache.get(&seed) {
+            if cached.0.len() == path_length && cached.1.registry_snapshot == registry_snapshot {
+                return Ok(cached.clone());
+            }
+        }
         
-        // Check cache first
-        let seed_array: [u8; 32] = seed.into();
-        if let Some(cached_path) = self.selection_cache.get(&seed_array) {
-            return Ok(cached_path.clone());
+        let layers = layer_count(&snapshot);
+        if let Some(layers) = layers {
+            if path_length != layers as usize {
+                return Err(VRFError::LayerMismatch { layers, path_length });
+            }
         }
         
         let mut selected_nodes = Vec::with_capacity(path_length);
+        let mut hop_proofs = Vec::with_capacity(path_length);
         let mut used_regions = std::collections::HashSet::new();
         
         for hop in 0..path_length {
-            // Create hop-specific VRF input
-            let mut hop_input = Vec::new();
-            hop_input.extend_from_slice(&seed);
-            hop_input.push(hop as u8);
-            
-            // Generate REAL VRF proof using Ed25519 signature
-            let signature = self.vrf_signing_key.sign(&hop_input);
+            // VRF output is only usable together with its proof
+            let (vrf_output, proof) = self.vrf_secret_key.evaluate(&hop_input(&seed, hop));
+            hop_proofs.push(proof);
             
-            // Use signature as VRF output (standard practice for Ed25519 VRF)
-            let vrf_output = signature.to_bytes();
-            let selected_node = self.select_node_from_vrf(
+            let selected_node = select_node_from_vrf(
                 &vrf_output, 
+                &snapshot,
+                layers.map(|_| hop as u8 + 1),
                 &selected_nodes, 
                 &used_regions
             )?;
             
             selected_nodes.push(selected_node.id);
-            used_regions.insert(selected_node.geographic_region);
-        }
-        
-        // Cache the result
-        self.selection_cache.put(seed_array, selected_nodes.clone());
-        
-        Ok(selected_nodes)
-    }
-    
-    fn select_node_from_vrf(
-        &self,
-        vrf_output: &[u8],
-        excluded_nodes: &[MixNodeId],
-        excluded_regions: &std::collections::HashSet<Region>
-    ) -> Result<MixNodeInfo, VRFError> {
-        // Convert VRF output to selection index with stake weighting
-        let selection_value = u64::from_be_bytes([
-            vrf_output[0], vrf_output[1], vrf_output[2], vrf_output[3],
-            vrf_output[4], vrf_output[5], vrf_output[6], vrf_output[7],
-        ]);
-        
-        // Filter available nodes (exclude already selected + same regions)
-        let available_nodes: Vec<_> = self.nodes.values()
-            .filter(|node| !excluded_nodes.contains(&node.id))
-            .filter(|node| !excluded_regions.contains(&node.geographic_region))
-            .filter(|node| self.is_node_active(node))
-            .collect();
-        
-        if available_nodes.is_empty() {
-            return Err(VRFError::NoAvailableNodes);
-        }
-        
-        // Calculate total stake weight
-        let total_weight: u64 = available_nodes.iter()
-            .map(|node| node.stake_weight)
-            .sum();
-        
-        if total_weight == 0 {
-            // If no stake weights, select uniformly at random
-            let index = (selection_value as usize) % available_nodes.len();
-            return Ok(available_nodes[index].clone());
+            used_regions.insert(selected_node.geographic_region.clone());
         }
         
-        // Select based on stake-weighted randomness
-        let selection_point = selection_value % total_weight;
-        let mut cumulative_weight = 0;
+        let proof = PathSelectionProof {
+            stream_id: stream_id.to_vec(),
+            epoch,
+            hop_proofs,
+            registry_snapshot,
+        };
         
-        for node in &available_nodes {
-            cumulative_weight += node.stake_weight;
-            if cumulative_weight > selection_point {
-                return Ok((*node).clone());
-            }
-        }
+        // Cache the result
+        self.selection_cache.put(seed, (selected_nodes.clone(), proof.clone()));
         
-        // Fallback (should not happen)
-        Ok(available_nodes[0].clone())
+        Ok((selected_nodes, proof))
     }
     
     fn is_node_active(&self, node: &MixNodeInfo) -> bool {
@@ -180,6 +226,193 @@ impl MixNodeRegistry {
     }
 }
 
+/// Everything needed to re-derive a path: seed inputs, one VRF proof per hop and the
+/// hash of the registry snapshot the hops were picked from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathSelectionProof {
+    pub stream_id: Vec<u8>,
+    pub epoch: u64,
+    pub hop_proofs: Vec<VrfProof>,
+    pub registry_snapshot: [u8; 32],
+}
+
+impl PathSelectionProof {
+    /// epoch (8) || stream id length (2) || stream id || snapshot hash (32) || hop count (1) || proofs
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            8 + 2 + self.stream_id.len() + 32 + 1 + self.hop_proofs.len() * VRF_PROOF_SIZE
+        );
+        bytes.extend_from_slice(&self.epoch.to_be_bytes());
+        bytes.extend_from_slice(&(self.stream_id.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.stream_id);
+        bytes.extend_from_slice(&self.registry_snapshot);
+        bytes.push(self.hop_proofs.len() as u8);
+        for proof in &self.hop_proofs {
+            bytes.extend_from_slice(&proof.to_bytes());
+        }
+        bytes
+    }
+    
+    pub fn from_bytes(data: &[u8]) -> Result<Self, VrfProofError> {
+        fn take(data: &[u8], offset: usize, len: usize) -> Result<&[u8], VrfProofError> {
+            data.get(offset..offset + len).ok_or(VrfProofError::Malformed)
+        }
+        
+        let epoch = u64::from_be_bytes(take(data, 0, 8)?.try_into().unwrap());
+        let stream_len = u16::from_be_bytes(take(data, 8, 2)?.try_into().unwrap()) as usize;
+        let stream_id = take(data, 10, stream_len)?.to_vec();
+        let mut offset = 10 + stream_len;
+        let registry_snapshot: [u8; 32] = take(data, offset, 32)?.try_into().unwrap();
+        offset += 32;
+        let hop_count = take(data, offset, 1)?[0] as usize;
+        offset += 1;
+        
+        if data.len() != offset + hop_count * VRF_PROOF_SIZE {
+            return Err(VrfProofError::Malformed);
+        }
+        let hop_proofs = data[offset..]
+            .chunks_exact(VRF_PROOF_SIZE)
+            .map(VrfProof::from_bytes)
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(Self {
+            stream_id,
+            epoch,
+            hop_proofs,
+            registry_snapshot,
+        })
+    }
+}
+
+/// Check that `path` is exactly what the VRF key holder had to select from `snapshot`
+///
+/// Fails if the snapshot differs from the one the proof commits to, if any hop proof is
+/// invalid, or if any hop deviates from the VRF-determined choice.
+pub fn verify_path(
+    vrf_public_key: &VrfPublicKey,
+    proof: &PathSelectionProof,
+    path: &[MixNodeId],
+    snapshot: &[MixNodeInfo],
+) -> Result<(), VRFError> {
+    let mut snapshot = snapshot.to_vec();
+    snapshot.sort_by_key(|node| node.id);
+    
+    if snapshot_hash(&snapshot) != proof.registry_snapshot {
+        return Err(VRFError::SnapshotMismatch);
+    }
+    if path.len() != proof.hop_proofs.len() {
+        return Err(VRFError::PathMismatch { hop: path.len().min(proof.hop_proofs.len()) });
+    }
+    let layers = layer_count(&snapshot);
+    if let Some(layers) = layers {
+        if path.len() != layers as usize {
+            return Err(VRFError::LayerMismatch { layers, path_length: path.len() });
+        }
+    }
+    
+    let seed = path_seed(&proof.stream_id, proof.epoch);
+    let mut used_regions = std::collections::HashSet::new();
+    
+    for (hop, (node_id, hop_proof)) in path.iter().zip(&proof.hop_proofs).enumerate() {
+        let vrf_output = vrf_public_key.verify(&hop_input(&seed, hop), hop_proof)?;
+        let layer = layers.map(|_| hop as u8 + 1);
+        let expected = select_node_from_vrf(&vrf_output, &snapshot, layer, &path[..hop], &used_regions)?;
+        
+        if expected.id != *node_id {
+            return Err(VRFError::PathMismatch { hop });
+        }
+        used_regions.insert(expected.geographic_region.clone());
+    }
+    
+    Ok(())
+}
+
+fn path_seed(stream_id: &[u8], epoch: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"BETANET_MIX_PATH_v1");
+    hasher.update(stream_id);
+    hasher.update(epoch.to_be_bytes());
+    hasher.finalize().into()
+}
+
+fn hop_input(seed: &[u8; 32], hop: usize) -> Vec<u8> {
+    let mut input = Vec::with_capacity(33);
+    input.extend_from_slice(seed);
+    input.push(hop as u8);
+    input
+}
+
+/// Commit to the selection-relevant fields of a snapshot sorted by id
+fn snapshot_hash(snapshot: &[MixNodeInfo]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"BETANET_MIX_SNAPSHOT_v1");
+    for node in snapshot {
+        hasher.update(node.id);
+        hasher.update(node.public_key.compress().as_bytes());
+        hasher.update(node.stake_weight.to_be_bytes());
+        hasher.update([node.geographic_region.clone() as u8]);
+        hasher.update([node.layer.unwrap_or(0)]);
+    }
+    hasher.finalize().into()
+}
+
+/// Number of layers in a stratified snapshot, None if no node has a layer
+fn layer_count(snapshot: &[MixNodeInfo]) -> Option<u8> {
+    snapshot.iter().filter_map(|node| node.layer).max()
+}
+
+/// Stake-weighted choice over `snapshot`, which must be sorted by id so provers and
+/// verifiers walk the nodes in the same order
+fn select_node_from_vrf<'a>(
+    vrf_output: &[u8],
+    snapshot: &'a [MixNodeInfo],
+    layer: Option<u8>,
+    excluded_nodes: &[MixNodeId],
+    excluded_regions: &std::collections::HashSet<Region>
+) -> Result<&'a MixNodeInfo, VRFError> {
+    // Convert VRF output to selection index with stake weighting
+    let selection_value = u64::from_be_bytes([
+        vrf_output[0], vrf_output[1], vrf_output[2], vrf_output[3],
+        vrf_output[4], vrf_output[5], vrf_output[6], vrf_output[7],
+    ]);
+    
+    // Filter available nodes (exclude already selected + same regions)
+    let available_nodes: Vec<_> = snapshot.iter()
+        .filter(|node| layer.is_none() || node.layer == layer)
+        .filter(|node| !excluded_nodes.contains(&node.id))
+        .filter(|node| !excluded_regions.contains(&node.geographic_region))
+        .collect();
+    
+    if available_nodes.is_empty() {
+        return Err(VRFError::NoAvailableNodes);
+    }
+    
+    // Calculate total stake weight
+    let total_weight: u64 = available_nodes.iter()
+        .map(|node| node.stake_weight)
+        .sum();
+    
+    if total_weight == 0 {
+        // If no stake weights, select uniformly at random
+        let index = (selection_value as usize) % available_nodes.len();
+        return Ok(available_nodes[index]);
+    }
+    
+    // Select based on stake-weighted randomness
+    let selection_point = selection_value % total_weight;
+    let mut cumulative_weight = 0;
+    
+    for node in &available_nodes {
+        cumulative_weight += node.stake_weight;
+        if cumulative_weight > selection_point {
+            return Ok(node);
+        }
+    }
+    
+    // Fallback (should not happen)
+    Ok(available_nodes[0])
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum VRFError {
     #[error("VRF setup failed: {0}")]
@@ -188,4 +421,123 @@ pub enum VRFError {
     ProofGeneration(String),
     #[error("No available nodes for selection")]
     NoAvailableNodes,
-}
\ No newline at end of file
+    #[error("Invalid hop proof: {0}")]
+    InvalidProof(#[from] VrfProofError),
+    #[error("Registry snapshot does not match the proof")]
+    SnapshotMismatch,
+    #[error("Path deviates from VRF selection at hop {hop}")]
+    PathMismatch { hop: usize },
+    #[error("Path length {path_length} does not match {layers} mix layers")]
+    LayerMismatch { layers: u8, path_length: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+    
+    fn node(i: u8, region: Region) -> MixNodeInfo {
+        MixNodeInfo {
+            id: [i; 32],
+            public_key: RISTRETTO_BASEPOINT_POINT,
+            address: format!("127.0.0.1:{}", 9000 + i as u16).parse().unwrap(),
+            stake_weight: 100 * i as u64,
+            reliability_score: 1.0,
+            geographic_region: region,
+            last_seen: std::time::SystemTime::now(),
+            layer: None,
+        }
+    }
+    
+    #[test]
+    fn test_verify_path() {
+        let mut registry = MixNodeRegistry::with_vrf_key(VrfSecretKey::from_seed(&[3u8; 32]));
+        let regions = [Region::Europe, Region::Asia, Region::NorthAmerica, Region::Africa];
+        for i in 1..=8u8 {
+            registry.add_node(node(i, regions[i as usize % regions.len()].clone()));
+        }
+        let public_key = registry.get_vrf_public_key();
+        let snapshot = registry.snapshot();
+        
+        let (path, proof) = registry.select_path(b"stream", 7, 3).unwrap();
+        let proof = PathSelectionProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert!(verify_path(&public_key, &proof, &path, &snapshot).is_ok());
+        
+        // A relay that swaps in a different hop is caught
+        let mut biased = path.clone();
+        biased[1] = *snapshot.iter().map(|n| &n.id).find(|id| !path.contains(id)).unwrap();
+        assert!(matches!(
+            verify_path(&public_key, &proof, &biased, &snapshot),
+            Err(VRFError::PathMismatch { hop: 1 })
+        ));
+        
+        // So is a proof replayed against a different registry view
+        assert!(matches!(
+            verify_path(&public_key, &proof, &path, &snapshot[1..]),
+            Err(VRFError::SnapshotMismatch)
+        ));
+        
+        // And proofs from another VRF key
+        let other = VrfSecretKey::from_seed(&[4u8; 32]).public_key();
+        assert!(matches!(
+            verify_path(&other, &proof, &path, &snapshot),
+            Err(VRFError::InvalidProof(_))
+        ));
+    }
+    
+    #[test]
+    fn test_cached_path_follows_registry_changes() {
+        let mut registry = MixNodeRegistry::with_vrf_key(VrfSecretKey::from_seed(&[6u8; 32]));
+        let regions = [Region::Europe, Region::Asia, Region::NorthAmerica, Region::Africa];
+        for i in 1..=8u8 {
+            registry.add_node(node(i, regions[i as usize % regions.len()].clone()));
+        }
+        let public_key = registry.get_vrf_public_key();
+        let (path, _) = registry.select_path(b"stream", 7, 3).unwrap();
+        
+        // A joining node changes the snapshot the path must be proven against
+        registry.add_node(node(9, Region::Oceania));
+        let (_, proof) = registry.select_path(b"stream", 7, 3).unwrap();
+        assert_eq!(proof.registry_snapshot, snapshot_hash(&registry.snapshot()));
+        
+        // A hop that went quiet is no longer handed out from the cache
+        let mut stale = registry.get_node(&path[0]).unwrap().clone();
+        stale.last_seen = std::time::SystemTime::now() - std::time::Duration::from_secs(600);
+        registry.add_node(stale);
+        let (repath, proof) = registry.select_path(b"stream", 7, 3).unwrap();
+        assert!(!repath.contains(&path[0]));
+        assert!(verify_path(&public_key, &proof, &repath, &registry.snapshot()).is_ok());
+    }
+    
+    #[test]
+    fn test_layered_selection_balances_stake() {
+        let mut registry = MixNodeRegistry::with_vrf_key(VrfSecretKey::from_seed(&[5u8; 32]));
+        let regions = [Region::Europe, Region::Asia, Region::NorthAmerica, Region::Africa, Region::Oceania];
+        for i in 1..=30u8 {
+            registry.add_node(node(i, regions[i as usize % regions.len()].clone()));
+        }
+        
+        let stakes = registry.assign_layers(1, DEFAULT_MIX_LAYERS);
+        let max_stake = 100 * 30;
+        let average = stakes.iter().sum::<u64>() / stakes.len() as u64;
+        assert!(stakes.iter().all(|&stake| stake <= average + max_stake));
+        
+        let (path, proof) = registry.select_path(b"stream", 1, 3).unwrap();
+        for (hop, id) in path.iter().enumerate() {
+            assert_eq!(registry.get_node(id).unwrap().layer, Some(hop as u8 + 1));
+        }
+        let snapshot = registry.snapshot();
+        assert!(verify_path(&registry.get_vrf_public_key(), &proof, &path, &snapshot).is_ok());
+        
+        assert!(matches!(
+            registry.select_path(b"stream", 1, 4),
+            Err(VRFError::LayerMismatch { layers: 3, path_length: 4 })
+        ));
+        
+        // A new epoch reshuffles the layers
+        let before: Vec<_> = snapshot.iter().map(|n| n.layer).collect();
+        registry.assign_layers(2, DEFAULT_MIX_LAYERS);
+        let after: Vec<_> = registry.snapshot().iter().map(|n| n.layer).collect();
+        assert_ne!(before, after);
+    }
+}
@@ -0,0 +1,98 @@
+// dudect-style timing test for Sphinx packet processing
+//
+// Forward and deliver packets are processed in random interleaved order and their timings
+// compared with Welch's t-test, after cropping the slow tail caused by preemption and cache
+// misses. A |t| above the threshold means an observer could tell the two classes apart.
+// Debug builds are too slow and too noisy for this; run with `cargo test --release`.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::time::Instant;
+
+use nym_mixnode_rs::{SphinxMixer, SphinxPacket, SphinxPacketBuilder, ProcessedPayload};
+use curve25519_dalek::{scalar::Scalar, constants::RISTRETTO_BASEPOINT_POINT};
+
+const SAMPLES_PER_CLASS: usize = 3000;
+/// Measurements above this percentile of the pooled timings are discarded
+const CROP_PERCENTILE: f64 = 0.9;
+/// dudect treats |t| > 10 as a clear timing leak
+const T_THRESHOLD: f64 = 10.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Forward,
+    Deliver,
+}
+
+fn random_scalar() -> Scalar {
+    Scalar::from_bytes_mod_order(rand::thread_rng().gen())
+}
+
+fn welch_t(a: &[f64], b: &[f64]) -> f64 {
+    let stats = |xs: &[f64]| {
+        let n = xs.len() as f64;
+        let mean = xs.iter().sum::<f64>() / n;
+        let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        (mean, var, n)
+    };
+    let (mean_a, var_a, n_a) = stats(a);
+    let (mean_b, var_b, n_b) = stats(b);
+    (mean_a - mean_b) / (var_a / n_a + var_b / n_b).sqrt()
+}
+
+#[test]
+#[cfg_attr(debug_assertions, ignore = "timing is only meaningful in optimized builds")]
+fn test_forward_and_deliver_timing_indistinguishable() {
+    let private_key = random_scalar();
+    let mut mixer = SphinxMixer::new(private_key);
+    let next_hop_key = random_scalar() * RISTRETTO_BASEPOINT_POINT;
+    let message = [0x42u8; 64];
+
+    // Fresh packets for every measurement, so none is rejected as a replay
+    let mut packets: Vec<(Class, SphinxPacket)> = Vec::with_capacity(2 * SAMPLES_PER_CLASS);
+    for _ in 0..SAMPLES_PER_CLASS {
+        let forward = SphinxPacketBuilder::new()
+            .route(vec![([1u8; 32], mixer.public_key()), ([2u8; 32], next_hop_key)])
+            .payload(&message)
+            .build()
+            .unwrap();
+        let deliver = SphinxPacketBuilder::new()
+            .route(vec![([1u8; 32], mixer.public_key())])
+            .payload(&message)
+            .build()
+            .unwrap();
+        packets.push((Class::Forward, forward));
+        packets.push((Class::Deliver, deliver));
+    }
+    packets.shuffle(&mut rand::thread_rng());
+
+    // Warm up caches and the allocator
+    for (_, packet) in packets.iter().take(100) {
+        mixer.process_packet(packet).unwrap();
+    }
+
+    let mut timings: Vec<(Class, f64)> = Vec::with_capacity(packets.len());
+    for (class, packet) in packets.iter().skip(100) {
+        let start = Instant::now();
+        let processed = mixer.process_packet(packet).unwrap();
+        let elapsed = start.elapsed().as_nanos() as f64;
+
+        let delivered = matches!(processed.payload, ProcessedPayload::Final(_));
+        assert_eq!(delivered, *class == Class::Deliver);
+        timings.push((*class, elapsed));
+    }
+
+    let mut pooled: Vec<f64> = timings.iter().map(|(_, t)| *t).collect();
+    pooled.sort_by(f64::total_cmp);
+    let cutoff = pooled[(pooled.len() as f64 * CROP_PERCENTILE) as usize];
+
+    let class_timings = |class: Class| -> Vec<f64> {
+        timings.iter()
+            .filter(|(c, t)| *c == class && *t <= cutoff)
+            .map(|(_, t)| *t)
+            .collect()
+    };
+    let t = welch_t(&class_timings(Class::Forward), &class_timings(Class::Deliver));
+
+    assert!(t.abs() < T_THRESHOLD, "forward and deliver timings distinguishable: t = {:.2}", t);
+}
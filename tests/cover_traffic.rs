@@ -0,0 +1,57 @@
+// Cover traffic sent by a running node
+//
+// The node shares its peer registry with discovery, so a peer registered there must start
+// receiving drop cover packets it can unwrap.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use curve25519_dalek::scalar::Scalar;
+use nym_mixnode_rs::p2p::peer::{PeerInfo, PeerRegistry, PeerRegistryConfig};
+use nym_mixnode_rs::p2p::transport::PeerId;
+use nym_mixnode_rs::{
+    HighPerformanceMixnode, MixnodeConfig, ProcessedPayload, SphinxMixer, SphinxPacket,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_discovered_peer_receives_drop_cover() {
+    let listen = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let peer_socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut peer_mixer = SphinxMixer::new(Scalar::from(7u64));
+
+    // Stands in for the registry peer discovery fills
+    let peers = Arc::new(PeerRegistry::new(PeerRegistryConfig::default()));
+    peers.register_peer(PeerInfo {
+        peer_id: PeerId([0xD4; 32]),
+        address: peer_socket.local_addr().unwrap(),
+        public_key: peer_mixer.public_key(),
+        stake: 1000,
+        region: "Europe".to_string(),
+        version: "1.0.0".to_string(),
+        capabilities: Default::default(),
+        reputation: Default::default(),
+        connection_info: Default::default(),
+        last_seen: SystemTime::now(),
+        is_online: true,
+    }).await.unwrap();
+
+    let config = MixnodeConfig {
+        listen_address: listen.to_string(),
+        worker_threads: 2,
+        ..Default::default()
+    };
+    let mut node = HighPerformanceMixnode::with_peers(config, peers.clone()).unwrap();
+    assert!(Arc::ptr_eq(&node.peers(), &peers));
+    tokio::spawn(async move { node.run().await.unwrap() });
+
+    // Idle cover runs at about one packet a second, half of it drop cover
+    let mut buffer = [0u8; 2048];
+    let (size, _) = tokio::time::timeout(Duration::from_secs(20), peer_socket.recv_from(&mut buffer))
+        .await
+        .expect("no drop cover reached the peer")
+        .unwrap();
+
+    let packet = SphinxPacket::from_bytes(&buffer[..size]).unwrap();
+    let processed = peer_mixer.process_packet(&packet).unwrap();
+    assert!(matches!(processed.payload, ProcessedPayload::Final(_)));
+}
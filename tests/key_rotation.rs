@@ -0,0 +1,138 @@
+// Sphinx key rotation as seen by senders
+//
+// The node rotates its key at every epoch boundary. Its registry entry must advertise the new
+// key, and packets built against the advertised key must be delivered. Persisted keys come
+// back after a restart together with their replay caches.
+
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use nym_mixnode_rs::metrics::{MetricsCollector, MetricsSnapshot};
+use nym_mixnode_rs::{
+    HighPerformanceMixnode, MixNodeInfo, MixnodeConfig, Region, SphinxPacketBuilder,
+};
+
+/// Wait up to five seconds for the node's metrics to satisfy `seen`
+async fn wait_for(metrics: &MetricsCollector, seen: impl Fn(&MetricsSnapshot) -> bool) -> bool {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !seen(&metrics.get_snapshot().await) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.is_ok()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_rotated_key_is_published() {
+    let addr: SocketAddr = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let node_id = [0xC3u8; 32];
+
+    let mut config = MixnodeConfig {
+        listen_address: addr.to_string(),
+        worker_threads: 2,
+        node_id: Some(node_id),
+        ..Default::default()
+    };
+    config.sphinx.mean_delay = Duration::from_millis(5);
+    config.epoch.genesis = SystemTime::now();
+    config.epoch.epoch_length = Duration::from_secs(1);
+
+    let mut node = HighPerformanceMixnode::new(config).unwrap();
+    let initial_key = node.public_key().await;
+    let (registry, metrics) = (node.registry(), node.metrics());
+    registry.lock().await.add_node(MixNodeInfo {
+        id: node_id,
+        public_key: initial_key,
+        address: addr,
+        stake_weight: 1000,
+        reliability_score: 1.0,
+        geographic_region: Region::Europe,
+        last_seen: SystemTime::now(),
+        layer: None,
+    });
+    tokio::spawn(async move { node.run().await.unwrap() });
+
+    let rotated = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let key = registry.lock().await.get_node(&node_id).unwrap().public_key;
+            if key != initial_key {
+                return key;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("rotated key never reached the registry");
+
+    let packet = SphinxPacketBuilder::new()
+        .route(vec![(node_id, rotated)])
+        .payload(b"new key")
+        .build()
+        .unwrap();
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.send_to(&packet.to_bytes(), addr).await.unwrap();
+
+    let delivered = tokio::time::timeout(Duration::from_secs(5), async {
+        while metrics.get_snapshot().await.packets_delivered == 0 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await;
+    assert!(delivered.is_ok(), "packet for the published key was not delivered");
+}
+
+#[test]
+fn test_replay_rejected_after_restart() {
+    let dir = std::env::temp_dir().join(format!("replay-restart-{}", std::process::id()));
+    let node_config = |addr: SocketAddr| {
+        let mut config = MixnodeConfig {
+            listen_address: addr.to_string(),
+            worker_threads: 2,
+            ..Default::default()
+        };
+        config.sphinx.mean_delay = Duration::from_millis(5);
+        config.sphinx.key_store_path = Some(dir.join("sphinx_keys.json"));
+        config.sphinx.replay_save_interval = Duration::from_millis(100);
+        config
+    };
+
+    // First run: the packet is delivered and its tag saved
+    let addr = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (key, bytes) = runtime.block_on(async {
+        let mut node = HighPerformanceMixnode::new(node_config(addr)).unwrap();
+        let (key, metrics) = (node.public_key().await, node.metrics());
+        tokio::spawn(async move { node.run().await.unwrap() });
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let bytes = SphinxPacketBuilder::new()
+            .route(vec![([0xD5; 32], key)])
+            .payload(b"only once")
+            .build()
+            .unwrap()
+            .to_bytes();
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(&bytes, addr).await.unwrap();
+        assert!(wait_for(&metrics, |snapshot| snapshot.packets_delivered == 1).await, "packet was not delivered");
+
+        // Let a save pick up the tag
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        (key, bytes)
+    });
+    runtime.shutdown_timeout(Duration::from_secs(5));
+
+    // Second run restores the key and the replay caches
+    let addr = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut node = HighPerformanceMixnode::new(node_config(addr)).unwrap();
+        assert_eq!(node.public_key().await, key, "restart did not keep the Sphinx key");
+        let metrics = node.metrics();
+        tokio::spawn(async move { node.run().await.unwrap() });
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(&bytes, addr).await.unwrap();
+        assert!(wait_for(&metrics, |snapshot| snapshot.replayed == 1).await, "replay after restart was not rejected");
+        assert_eq!(metrics.get_snapshot().await.packets_delivered, 0);
+    });
+    runtime.shutdown_timeout(Duration::from_secs(5));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
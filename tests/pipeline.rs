@@ -0,0 +1,97 @@
+// End-to-end packet pipeline between two nodes
+//
+// Node A is the first hop and node B the second and last. A packet from SphinxPacketBuilder
+// goes through A's receiver, mixer workers, delay queue and sender, and must be delivered
+// by B.
+
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use nym_mixnode_rs::{
+    HighPerformanceMixnode, MixNodeInfo, MixnodeConfig, Region, SphinxPacketBuilder,
+};
+
+/// A loopback address with a port that was free a moment ago
+fn free_loopback_addr() -> SocketAddr {
+    std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+fn node_config(listen_address: SocketAddr) -> MixnodeConfig {
+    let mut config = MixnodeConfig {
+        listen_address: listen_address.to_string(),
+        worker_threads: 2,
+        ..Default::default()
+    };
+    config.sphinx.mean_delay = Duration::from_millis(5);
+    config
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_two_hop_packet_reaches_next_node() {
+    let (addr_a, addr_b) = (free_loopback_addr(), free_loopback_addr());
+    let (id_a, id_b) = ([0xA1u8; 32], [0xB2u8; 32]);
+
+    let mut node_a = HighPerformanceMixnode::new(node_config(addr_a)).unwrap();
+    let mut node_b = HighPerformanceMixnode::new(node_config(addr_b)).unwrap();
+    let (key_a, key_b) = (node_a.public_key().await, node_b.public_key().await);
+    let (metrics_a, metrics_b) = (node_a.metrics(), node_b.metrics());
+
+    // A resolves B from its registry when forwarding
+    node_a.registry().lock().await.add_node(MixNodeInfo {
+        id: id_b,
+        public_key: key_b,
+        address: addr_b,
+        stake_weight: 1000,
+        reliability_score: 1.0,
+        geographic_region: Region::Europe,
+        last_seen: SystemTime::now(),
+        layer: None,
+    });
+
+    tokio::spawn(async move { node_a.run().await.unwrap() });
+    tokio::spawn(async move { node_b.run().await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let packet = SphinxPacketBuilder::new()
+        .route(vec![(id_a, key_a), (id_b, key_b)])
+        .payload(b"two hops")
+        .build()
+        .unwrap();
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.send_to(&packet.to_bytes(), addr_a).await.unwrap();
+
+    let delivered = tokio::time::timeout(Duration::from_secs(5), async {
+        while metrics_b.get_snapshot().await.packets_delivered == 0 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await;
+    assert!(delivered.is_ok(), "next hop never delivered the packet");
+    assert_eq!(metrics_a.get_snapshot().await.packets_forwarded, 1);
+    assert_eq!(metrics_b.get_snapshot().await.packets_delivered, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_reply_at_exit_hop_is_not_counted_as_delivered() {
+    let addr = free_loopback_addr();
+    let id = [0xC4u8; 32];
+
+    let mut node = HighPerformanceMixnode::new(node_config(addr)).unwrap();
+    let key = node.public_key().await;
+    let metrics = node.metrics();
+    tokio::spawn(async move { node.run().await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let (surb, _keys) = SphinxPacketBuilder::new().route(vec![(id, key)]).build_surb().unwrap();
+    let packet = surb.reply(b"answer").unwrap();
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.send_to(&packet.to_bytes(), addr).await.unwrap();
+
+    // Replies are not handed to their SURB creator yet, so they must not look delivered
+    let dropped = tokio::time::timeout(Duration::from_secs(5), async {
+        while metrics.get_snapshot().await.not_implemented == 0 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await;
+    assert!(dropped.is_ok(), "reply was not counted as unhandled");
+    assert_eq!(metrics.get_snapshot().await.packets_delivered, 0);
+}
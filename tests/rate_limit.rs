@@ -0,0 +1,112 @@
+// Rate limiting in a running node's receive loop
+//
+// Datagrams beyond a source's per-IP budget must be dropped before parsing and counted as
+// DropReason::RateLimit, while a registry node at another address stays within its budget.
+
+use std::time::{Duration, SystemTime};
+
+use nym_mixnode_rs::{HighPerformanceMixnode, MixNodeInfo, MixnodeConfig, Region};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_flood_is_dropped_as_rate_limited() {
+    let listen = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let config = MixnodeConfig {
+        listen_address: listen.to_string(),
+        worker_threads: 2,
+        per_ip_packet_rate: 1,
+        per_ip_burst: 1,
+        ..Default::default()
+    };
+    let mut node = HighPerformanceMixnode::new(config).unwrap();
+    let metrics = node.metrics();
+    tokio::spawn(async move { node.run().await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    for _ in 0..50 {
+        client.send_to(&[0u8; 64], listen).await.unwrap();
+    }
+
+    let limited = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let snapshot = metrics.get_snapshot().await;
+            if snapshot.rate_limited > 0 && snapshot.packets_dropped >= 50 {
+                return snapshot;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("flood was not dropped");
+
+    // Whatever got past the limiter was dropped as malformed, not counted twice
+    assert!(limited.rate_limited >= 40, "only {} datagrams rate limited", limited.rate_limited);
+    assert_eq!(limited.packets_dropped, 50);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_registry_node_skips_per_ip_limit() {
+    assert_registry_node_is_exempt().await;
+}
+
+// A ring blocks in its wait while the exempt list is published, so every node start races the
+// first batch against the update; repeat it to catch a list that is only refreshed before the wait
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_registry_node_skips_per_ip_limit_io_uring() {
+    for _ in 0..10 {
+        assert_registry_node_is_exempt().await;
+    }
+}
+
+async fn assert_registry_node_is_exempt() {
+    let listen = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let config = MixnodeConfig {
+        listen_address: listen.to_string(),
+        worker_threads: 2,
+        per_ip_packet_rate: 1,
+        per_ip_burst: 1,
+        ..Default::default()
+    };
+    let mut node = HighPerformanceMixnode::new(config).unwrap();
+    let metrics = node.metrics();
+
+    // Loopback aliases let the mix and a stranger use different source IPs
+    let mix = tokio::net::UdpSocket::bind("127.0.0.2:0").await.unwrap();
+    node.registry().lock().await.add_node(MixNodeInfo {
+        id: [0xE5; 32],
+        public_key: node.public_key().await,
+        address: mix.local_addr().unwrap(),
+        stake_weight: 1000,
+        reliability_score: 1.0,
+        geographic_region: Region::Europe,
+        last_seen: SystemTime::now(),
+        layer: None,
+    });
+    tokio::spawn(async move { node.run().await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    for _ in 0..20 {
+        mix.send_to(&[0u8; 64], listen).await.unwrap();
+    }
+
+    let dropped = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let snapshot = metrics.get_snapshot().await;
+            if snapshot.packets_dropped >= 20 {
+                return snapshot;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("datagrams from the mix were not seen");
+
+    assert_eq!(dropped.rate_limited, 0);
+}
+
+#[test]
+fn test_max_packet_rate_beyond_u32_is_rejected() {
+    let config = MixnodeConfig {
+        max_packet_rate: u64::from(u32::MAX) + 1,
+        ..Default::default()
+    };
+    let error = HighPerformanceMixnode::new(config).err().expect("oversized rate was accepted");
+    assert!(error.to_string().contains("max_packet_rate"), "unexpected error: {}", error);
+}
@@ -0,0 +1,106 @@
+// Golden vector for the versioned Sphinx wire codec
+//
+// native_v1_two_hop.hex is a two-hop packet from SphinxPacketBuilder with a deterministic RNG;
+// any change to the native format or its cryptography changes these bytes.
+
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{CryptoRng, RngCore};
+use std::time::Duration;
+
+use nym_mixnode_rs::{
+    SphinxMixer, SphinxPacket, SphinxPacketBuilder, ProcessedPayload, RoutingCommand, SphinxVersion,
+    SphinxError,
+};
+
+const NATIVE_V1_TWO_HOP: &str = include_str!("vectors/native_v1_two_hop.hex");
+
+/// Deterministic RNG so the builder output is reproducible
+struct XofRng(blake3::OutputReader);
+
+impl XofRng {
+    fn new(seed: &[u8]) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(seed);
+        Self(hasher.finalize_xof())
+    }
+}
+
+impl RngCore for XofRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for XofRng {}
+
+fn decode_hex(vector: &str) -> Vec<u8> {
+    hex::decode(vector.trim()).expect("vector is valid hex")
+}
+
+#[test]
+fn test_native_v1_golden_vector() {
+    let first_key = Scalar::from_bytes_mod_order([0x11; 32]);
+    let second_key = Scalar::from_bytes_mod_order([0x22; 32]);
+    let mut first = SphinxMixer::new(first_key);
+    let mut second = SphinxMixer::new(second_key);
+
+    let packet = SphinxPacketBuilder::new()
+        .route(vec![([0xA1; 32], first.public_key()), ([0xA2; 32], second.public_key())])
+        .delays(vec![Duration::from_millis(25), Duration::ZERO])
+        .payload(b"golden vector")
+        .build_with_rng(&mut XofRng::new(b"nym-mixnode-rs golden vector v1"))
+        .unwrap();
+
+    let expected = decode_hex(NATIVE_V1_TWO_HOP);
+    assert_eq!(packet.to_bytes(), expected, "native encoding changed");
+
+    let decoded = SphinxPacket::from_bytes(&expected).unwrap();
+    assert_eq!(decoded.header.version, SphinxVersion::Native as u8);
+
+    let processed = first.process_packet(&decoded).unwrap();
+    assert_eq!(processed.routing_info.delay, Duration::from_millis(25));
+    let (RoutingCommand::Forward { next_hop }, ProcessedPayload::Forward(next_packet)) =
+        (processed.routing_info.command, processed.payload) else {
+        panic!("first hop should forward");
+    };
+    assert_eq!(next_hop, [0xA2; 32]);
+
+    let next_packet = SphinxPacket::from_bytes(&next_packet).unwrap();
+    assert_eq!(next_packet.header.version, SphinxVersion::Native as u8);
+    match second.process_packet(&next_packet).unwrap().payload {
+        ProcessedPayload::Final(message) => assert_eq!(message, b"golden vector"),
+        _ => panic!("second hop should deliver"),
+    }
+}
+
+#[test]
+fn test_unknown_version_rejected() {
+    let mut unknown_version = decode_hex(NATIVE_V1_TWO_HOP);
+    unknown_version[0] = 7;
+    assert!(matches!(SphinxPacket::from_bytes(&unknown_version), Err(SphinxError::UnsupportedVersion(7))));
+    assert!(matches!(SphinxVersion::try_from(7), Err(SphinxError::UnsupportedVersion(7))));
+}
+
+#[test]
+fn test_upstream_nym_packets_rejected() {
+    // Regular upstream packet: 348-byte header, 16-byte payload overhead, 2 KiB plaintext
+    let upstream = vec![0u8; 348 + 16 + 2048];
+    assert!(matches!(SphinxPacket::from_bytes(&upstream), Err(SphinxError::InvalidPacketSize)));
+}
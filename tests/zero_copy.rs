@@ -0,0 +1,197 @@
+// Steady-state forwarding must not touch the heap
+//
+// A counting global allocator tracks allocations made by the test thread. Once the buffer
+// pool is initialised and the replay cache's exact set has stopped growing, processing a
+// forward packet and dropping the result (which returns its buffer to the pool) must not
+// allocate.
+//
+// The pipeline test runs a current-thread runtime, so the mixer worker runs on the test thread
+// too. Packets are received into pooled buffers, parsed in place, dispatched to the worker,
+// processed as one batch and sent to the next hop from the buffer they were written into. The
+// only allocations left are the scratch vectors curve25519-dalek's double_and_compress_batch
+// makes for each batch, which are measured on their own and must account for all of them.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, ristretto::RistrettoPoint, scalar::Scalar};
+use nym_mixnode_rs::net::{BatchedSocket, RecvBuffers};
+use nym_mixnode_rs::{
+    get_pool_stats, MixerPool, PooledPacket, ProcessedPayload, ReplayCache, ReplayCacheConfig, ReplayState,
+    SphinxKeySet, SphinxMixer, SphinxPacket, SphinxPacketBuilder, SPHINX_PACKET_SIZE,
+};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn test_forwarding_is_allocation_free() {
+    // A small exact set fills up during warm-up, after which it no longer grows
+    let mut mixer = SphinxMixer::with_replay_config(
+        Scalar::from(7u64),
+        ReplayCacheConfig {
+            bloom_bits: 1 << 16,
+            max_exact_entries: 32,
+            ..Default::default()
+        },
+    );
+    let next_hop_key = Scalar::from(11u64) * RISTRETTO_BASEPOINT_POINT;
+
+    let packets: Vec<SphinxPacket> = (0..100)
+        .map(|_| {
+            SphinxPacketBuilder::new()
+                .route(vec![([1u8; 32], mixer.public_key()), ([2u8; 32], next_hop_key)])
+                .payload(b"zero copy")
+                .build()
+                .unwrap()
+        })
+        .collect();
+    let (warm_up, measured) = packets.split_at(50);
+
+    for packet in warm_up {
+        mixer.process_packet(packet).unwrap();
+    }
+
+    let before = allocations();
+    for packet in measured {
+        let processed = mixer.process_packet(packet).unwrap();
+        assert!(matches!(processed.payload, ProcessedPayload::Forward(_)));
+    }
+    let allocated = allocations() - before;
+
+    assert_eq!(allocated, 0, "forwarding {} packets allocated {} times", measured.len(), allocated);
+    assert!(get_pool_stats().0.cache_hits >= measured.len() as u64);
+}
+
+#[test]
+fn test_pipeline_is_allocation_free() {
+    const BATCH: usize = 32;
+    const WARM_UP_ROUNDS: usize = 10;
+    const ROUNDS: usize = 20;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let keys = SphinxKeySet::generate(0);
+        let replay_config = ReplayCacheConfig {
+            bloom_bits: 1 << 16,
+            max_exact_entries: 32,
+            ..Default::default()
+        };
+        let (pool, workers) = MixerPool::<SocketAddr>::with_replay_state(&keys, ReplayState {
+            shard_key: [7u8; 32],
+            caches: vec![ReplayCache::new(replay_config)],
+        });
+        let (results_tx, mut results_rx) = mpsc::channel(1024);
+        for worker in workers {
+            tokio::spawn(worker.run(results_tx.clone()));
+        }
+
+        let receiver = BatchedSocket::new(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()), true);
+        let sender = BatchedSocket::new(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()), true);
+        let client = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let next_hop = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        next_hop.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let listen = receiver.socket().local_addr().unwrap();
+        let next_hop_addr = next_hop.local_addr().unwrap();
+
+        let next_hop_key = Scalar::from(11u64) * RISTRETTO_BASEPOINT_POINT;
+        let datagrams: Vec<Vec<u8>> = (0..BATCH * ROUNDS)
+            .map(|_| {
+                SphinxPacketBuilder::new()
+                    .route(vec![([1u8; 32], keys.current.public_key()), ([2u8; 32], next_hop_key)])
+                    .payload(b"zero copy")
+                    .build()
+                    .unwrap()
+                    .to_bytes()
+            })
+            .collect();
+
+        // What curve25519-dalek allocates for one batch, the worker's only heap use
+        let points = vec![RISTRETTO_BASEPOINT_POINT; BATCH];
+        let before = allocations();
+        drop(RistrettoPoint::double_and_compress_batch(&points));
+        let per_batch = allocations() - before;
+
+        let mut buffers = RecvBuffers::new();
+        let mut batch = Vec::with_capacity(BATCH);
+        let mut processed = Vec::with_capacity(BATCH);
+        let mut outbound = Vec::with_capacity(BATCH);
+        let mut received = [0u8; SPHINX_PACKET_SIZE + 1];
+        let mut allocated = 0;
+        let hits_before = get_pool_stats().0.cache_hits;
+
+        for (round, datagrams) in datagrams.chunks(BATCH).enumerate() {
+            for datagram in datagrams {
+                client.send_to(datagram, listen).unwrap();
+            }
+
+            let before = allocations();
+
+            // Receive: the datagrams stay in the buffers they were received into
+            while batch.len() < BATCH {
+                receiver.recv_batch(&mut buffers).await.unwrap();
+                for (datagram, source) in buffers.drain() {
+                    batch.push((PooledPacket::from_buffer(datagram).unwrap(), source));
+                }
+            }
+
+            // Dispatch and process: the worker handles the whole round as one batch
+            assert_eq!(pool.dispatch(&mut batch), 0);
+            while processed.len() < BATCH {
+                results_rx.recv_many(&mut processed, BATCH).await;
+            }
+
+            // Forward: the packets leave from the pooled buffers the worker wrote them into
+            for (_, result) in processed.drain(..) {
+                match result.unwrap().payload {
+                    ProcessedPayload::Forward(bytes) => outbound.push((bytes, next_hop_addr)),
+                    other => panic!("expected a forward packet, got {:?}", other),
+                }
+            }
+            let mut sent = 0;
+            while sent < outbound.len() {
+                sent += sender.send_batch(&outbound[sent..]).await.unwrap();
+            }
+            outbound.clear();
+
+            if round >= WARM_UP_ROUNDS {
+                allocated += allocations() - before - per_batch;
+            }
+
+            for _ in 0..BATCH {
+                let (len, _) = next_hop.recv_from(&mut received).unwrap();
+                assert!(SphinxPacket::view(&received[..len]).is_ok());
+            }
+        }
+
+        let rounds = ROUNDS - WARM_UP_ROUNDS;
+        assert_eq!(allocated, 0, "forwarding {} packets allocated {} times", rounds * BATCH, allocated);
+        assert!(get_pool_stats().0.cache_hits - hits_before >= (2 * ROUNDS * BATCH) as u64);
+    });
+}